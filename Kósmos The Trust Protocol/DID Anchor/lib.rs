@@ -0,0 +1,132 @@
+//! Off-chain companion to hash-anchored DIDs.
+//!
+//! A DID registered with `register_anchored_did` keeps only a `DocumentAnchor` in the
+//! registry: the SHA-256 hash of its document and a locator (`ipfs://<cid>` or an
+//! `https://` URL) to fetch it from. This crate fetches the document behind a locator
+//! and checks it against the anchored hash, so that a resolver never hands out a
+//! document the controllers did not anchor.
+
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// A parsed document locator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Locator<'a> {
+    /// An IPFS content identifier, from `ipfs://<cid>`.
+    Ipfs(&'a str),
+    /// An HTTPS URL without its scheme, from `https://<host>/<path>`.
+    Https(&'a str),
+}
+
+impl<'a> Locator<'a> {
+    /// Parses a locator as accepted by the registry.
+    pub fn parse(locator: &'a str) -> Result<Self, AnchorError> {
+        let parsed = if let Some(cid) = locator.strip_prefix("ipfs://") {
+            Locator::Ipfs(cid)
+        } else if let Some(url) = locator.strip_prefix("https://") {
+            Locator::Https(url)
+        } else {
+            return Err(AnchorError::InvalidLocator);
+        };
+
+        match parsed {
+            Locator::Ipfs(rest) | Locator::Https(rest) if rest.is_empty() => Err(AnchorError::InvalidLocator),
+            _ => Ok(parsed),
+        }
+    }
+}
+
+/// A source of anchored documents.
+pub trait DocumentStore {
+    /// Returns the raw bytes of the document behind `locator`.
+    fn fetch(&self, locator: &Locator) -> Result<Vec<u8>, AnchorError>;
+}
+
+/// A document store backed by a local directory, e.g. a pinned IPFS export or a mirror
+/// of the HTTPS documents. `ipfs://<cid>` is read from `<root>/ipfs/<cid>` and
+/// `https://<host>/<path>` from `<root>/https/<host>/<path>`.
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStore { root: root.into() }
+    }
+
+    /// Returns the file a locator maps to. Locators that would escape the store root,
+    /// or that carry a query or fragment, are rejected.
+    pub fn path_for(&self, locator: &Locator) -> Result<PathBuf, AnchorError> {
+        let (dir, rest) = match locator {
+            Locator::Ipfs(cid) => ("ipfs", *cid),
+            Locator::Https(url) => ("https", *url),
+        };
+        if rest.contains(['?', '#', '\\']) {
+            return Err(AnchorError::InvalidLocator);
+        }
+
+        let relative = Path::new(rest);
+        if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(AnchorError::InvalidLocator);
+        }
+        Ok(self.root.join(dir).join(relative))
+    }
+}
+
+impl DocumentStore for LocalStore {
+    fn fetch(&self, locator: &Locator) -> Result<Vec<u8>, AnchorError> {
+        fs::read(self.path_for(locator)?).map_err(AnchorError::Fetch)
+    }
+}
+
+/// The errors that can occur while fetching an anchored document.
+#[derive(Debug)]
+pub enum AnchorError {
+    /// The locator does not use the `ipfs://` or `https://` scheme, or cannot be mapped
+    /// to the store.
+    InvalidLocator,
+    /// The store could not return the document.
+    Fetch(io::Error),
+    /// The fetched document does not hash to the anchored value.
+    HashMismatch { expected: [u8; 32], actual: [u8; 32] },
+}
+
+impl fmt::Display for AnchorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnchorError::InvalidLocator => write!(f, "invalid document locator"),
+            AnchorError::Fetch(err) => write!(f, "could not fetch document: {}", err),
+            AnchorError::HashMismatch { .. } => write!(f, "document does not match the anchored hash"),
+        }
+    }
+}
+
+impl std::error::Error for AnchorError {}
+
+/// Computes the hash to anchor for a document: the SHA-256 of its exact bytes.
+pub fn document_hash(document: &[u8]) -> [u8; 32] {
+    Sha256::digest(document).into()
+}
+
+/// Checks that `document` hashes to `expected`.
+pub fn verify_document(document: &[u8], expected: &[u8; 32]) -> Result<(), AnchorError> {
+    let actual = document_hash(document);
+    if &actual != expected {
+        return Err(AnchorError::HashMismatch { expected: *expected, actual });
+    }
+    Ok(())
+}
+
+/// Fetches the document behind `locator` from `store` and returns its bytes, provided
+/// they hash to the anchored `hash`.
+pub fn fetch_anchored_document<S: DocumentStore + ?Sized>(store: &S, locator: &str, hash: &[u8; 32]) -> Result<Vec<u8>, AnchorError> {
+    let document = store.fetch(&Locator::parse(locator)?)?;
+    verify_document(&document, hash)?;
+    Ok(document)
+}
+
+#[cfg(test)]
+mod test;
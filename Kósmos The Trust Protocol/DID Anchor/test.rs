@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{document_hash, fetch_anchored_document, AnchorError, LocalStore, Locator};
+
+const DOCUMENT: &[u8] = br#"{"id":"did:kosmos:anchored","verificationMethod":[]}"#;
+
+/// Creates an empty store directory unique to the test.
+fn temp_store(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("did-anchor-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    root
+}
+
+#[test]
+fn test_parse_locator() {
+    let cid = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+    assert_eq!(Locator::parse(&format!("ipfs://{}", cid)).ok(), Some(Locator::Ipfs(cid)));
+    assert_eq!(Locator::parse("https://example.com/did.json").ok(), Some(Locator::Https("example.com/did.json")));
+
+    for locator in ["", "ipfs://", "https://", "http://example.com/did.json", "ftp://example.com"] {
+        assert!(matches!(Locator::parse(locator), Err(AnchorError::InvalidLocator)), "{}", locator);
+    }
+}
+
+#[test]
+fn test_local_store_paths() {
+    let store = LocalStore::new("/srv/anchors");
+    assert_eq!(store.path_for(&Locator::Ipfs("bafy123")).unwrap(), PathBuf::from("/srv/anchors/ipfs/bafy123"));
+    assert_eq!(
+        store.path_for(&Locator::Https("example.com/dids/alice.json")).unwrap(),
+        PathBuf::from("/srv/anchors/https/example.com/dids/alice.json")
+    );
+
+    // Nothing outside the store root can be reached
+    for url in ["example.com/../../etc/passwd", "/etc/passwd", "example.com/did.json?v=2", "example.com/did.json#key-1"] {
+        assert!(matches!(store.path_for(&Locator::Https(url)), Err(AnchorError::InvalidLocator)), "{}", url);
+    }
+}
+
+#[test]
+fn test_fetch_anchored_document() {
+    let root = temp_store("fetch");
+    fs::create_dir_all(root.join("https/example.com")).unwrap();
+    fs::write(root.join("https/example.com/did.json"), DOCUMENT).unwrap();
+    fs::create_dir_all(root.join("ipfs")).unwrap();
+    fs::write(root.join("ipfs/bafy123"), DOCUMENT).unwrap();
+    let store = LocalStore::new(&root);
+
+    let hash = document_hash(DOCUMENT);
+    assert_eq!(fetch_anchored_document(&store, "https://example.com/did.json", &hash).unwrap(), DOCUMENT);
+    assert_eq!(fetch_anchored_document(&store, "ipfs://bafy123", &hash).unwrap(), DOCUMENT);
+
+    // A document that was changed after anchoring is rejected
+    fs::write(root.join("ipfs/bafy123"), b"{}").unwrap();
+    match fetch_anchored_document(&store, "ipfs://bafy123", &hash) {
+        Err(AnchorError::HashMismatch { expected, actual }) => {
+            assert_eq!(expected, hash);
+            assert_eq!(actual, document_hash(b"{}"));
+        }
+        other => panic!("expected a hash mismatch, got {:?}", other),
+    }
+
+    assert!(matches!(
+        fetch_anchored_document(&store, "ipfs://missing", &hash),
+        Err(AnchorError::Fetch(_))
+    ));
+
+    fs::remove_dir_all(&root).unwrap();
+}
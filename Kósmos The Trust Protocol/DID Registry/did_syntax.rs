@@ -0,0 +1,147 @@
+//! Parser for `did:kosmos` identifiers.
+//!
+//! The grammar enforced by the registry is:
+//!
+//! ```text
+//! did                = "did:kosmos:" [ network ":" ] method-specific-id
+//! network            = "testnet" / "futurenet"
+//! method-specific-id = 1*64( %x61-7A / DIGIT / "." / "-" / "_" )
+//! ```
+//!
+//! Identifiers on the main network carry no network segment, so `did:kosmos:mainnet:...`
+//! is rejected as non-canonical, as are upper-case letters anywhere in the DID.
+//!
+//! The parser works on plain bytes and has no dependency on the Soroban environment,
+//! so off-chain tooling can use it to validate identifiers before submitting them.
+
+pub const METHOD_PREFIX: &[u8] = b"did:kosmos:";
+pub const MAX_ID_LEN: usize = 64;
+/// Longest possible DID: the prefix, the longest network segment and a maximal id.
+pub const MAX_DID_LEN: usize = METHOD_PREFIX.len() + 10 + MAX_ID_LEN;
+
+/// Network ids (the SHA-256 hash of the network passphrase) of the known networks.
+pub const MAINNET_NETWORK_ID: [u8; 32] = [
+    0x7a, 0xc3, 0x39, 0x97, 0x54, 0x4e, 0x31, 0x75, 0xd2, 0x66, 0xbd, 0x02, 0x24, 0x39, 0xb2, 0x2c,
+    0xdb, 0x16, 0x50, 0x8c, 0x01, 0x16, 0x3f, 0x26, 0xe5, 0xcb, 0x2a, 0x3e, 0x10, 0x45, 0xa9, 0x79,
+];
+pub const TESTNET_NETWORK_ID: [u8; 32] = [
+    0xce, 0xe0, 0x30, 0x2d, 0x59, 0x84, 0x4d, 0x32, 0xbd, 0xca, 0x91, 0x5c, 0x82, 0x03, 0xdd, 0x44,
+    0xb3, 0x3f, 0xbb, 0x7e, 0xdc, 0x19, 0x05, 0x1e, 0xa3, 0x7a, 0xbe, 0xdf, 0x28, 0xec, 0xd4, 0x72,
+];
+pub const FUTURENET_NETWORK_ID: [u8; 32] = [
+    0xa3, 0xa1, 0xc6, 0xa7, 0x82, 0x86, 0x71, 0x3e, 0x29, 0xbe, 0x0e, 0x97, 0x85, 0x67, 0x0f, 0xa8,
+    0x38, 0xd1, 0x39, 0x17, 0xcd, 0x8e, 0xae, 0xb4, 0xa3, 0x57, 0x9f, 0xf1, 0xde, 0xbc, 0x7f, 0xd5,
+];
+
+/// The Stellar network a DID is anchored on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Futurenet,
+}
+
+impl Network {
+    /// The network segment as written in a DID. Mainnet has none.
+    pub fn segment(&self) -> Option<&'static [u8]> {
+        match self {
+            Network::Mainnet => None,
+            Network::Testnet => Some(b"testnet"),
+            Network::Futurenet => Some(b"futurenet"),
+        }
+    }
+
+    /// The known network with the given network id, if any.
+    pub fn from_network_id(network_id: &[u8; 32]) -> Option<Network> {
+        match *network_id {
+            MAINNET_NETWORK_ID => Some(Network::Mainnet),
+            TESTNET_NETWORK_ID => Some(Network::Testnet),
+            FUTURENET_NETWORK_ID => Some(Network::Futurenet),
+            _ => None,
+        }
+    }
+}
+
+/// A syntactically valid, canonical `did:kosmos` identifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParsedDid<'a> {
+    pub network: Network,
+    pub id: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DidSyntaxError {
+    /// The DID is longer than `MAX_DID_LEN`.
+    TooLong,
+    /// The DID does not start with `did:kosmos:`.
+    InvalidMethod,
+    /// The DID has a network segment that is not a known network.
+    UnknownNetwork,
+    /// The method-specific id is empty.
+    EmptyId,
+    /// The method-specific id is longer than `MAX_ID_LEN`.
+    IdTooLong,
+    /// The method-specific id contains a character outside the allowed set.
+    InvalidCharacter,
+    /// The DID is valid but not written in its canonical form
+    /// (upper-case letters, or an explicit `mainnet` segment).
+    NonCanonical,
+}
+
+/// Parses a `did:kosmos` identifier, accepting only its canonical form.
+pub fn parse(did: &[u8]) -> Result<ParsedDid<'_>, DidSyntaxError> {
+    if did.len() > MAX_DID_LEN {
+        return Err(DidSyntaxError::TooLong);
+    }
+    if did.len() < METHOD_PREFIX.len() || !did[..METHOD_PREFIX.len()].eq_ignore_ascii_case(METHOD_PREFIX) {
+        return Err(DidSyntaxError::InvalidMethod);
+    }
+    if &did[..METHOD_PREFIX.len()] != METHOD_PREFIX {
+        return Err(DidSyntaxError::NonCanonical);
+    }
+
+    let rest = &did[METHOD_PREFIX.len()..];
+    let (network, id) = match rest.iter().position(|&c| c == b':') {
+        None => (Network::Mainnet, rest),
+        Some(i) => {
+            let network = match &rest[..i] {
+                b"testnet" => Network::Testnet,
+                b"futurenet" => Network::Futurenet,
+                segment if segment.eq_ignore_ascii_case(b"mainnet")
+                    || segment.eq_ignore_ascii_case(b"testnet")
+                    || segment.eq_ignore_ascii_case(b"futurenet") =>
+                {
+                    return Err(DidSyntaxError::NonCanonical)
+                }
+                _ => return Err(DidSyntaxError::UnknownNetwork),
+            };
+            (network, &rest[i + 1..])
+        }
+    };
+
+    validate_id(id)?;
+    Ok(ParsedDid { network, id })
+}
+
+/// Checks a method-specific id on its own, e.g. one derived by the registry.
+pub fn validate_id(id: &[u8]) -> Result<(), DidSyntaxError> {
+    if id.is_empty() {
+        return Err(DidSyntaxError::EmptyId);
+    }
+    if id.len() > MAX_ID_LEN {
+        return Err(DidSyntaxError::IdTooLong);
+    }
+    for &c in id {
+        match c {
+            b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' => {}
+            b'A'..=b'Z' => return Err(DidSyntaxError::NonCanonical),
+            _ => return Err(DidSyntaxError::InvalidCharacter),
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if `did` is a valid, canonical `did:kosmos` identifier.
+pub fn is_valid(did: &[u8]) -> bool {
+    parse(did).is_ok()
+}
@@ -1,70 +1,767 @@
 #![no_std]
-use soroban_sdk::{contract, contractimpl, symbol_short, Env, String, Symbol, Vec, Address, Map};
+use soroban_sdk::{contract, contracterror, contractimpl, contracttype, symbol_short, token, xdr::ToXdr, Bytes, BytesN, Env, Map, String, Symbol, TryFromVal, Vec, Address};
+
+pub mod did_syntax;
+use did_syntax::DidSyntaxError;
 
 // --- Data Structures for DID Document ---
 // As per the blueprint, the DID document maps the DID to public keys, verification methods, and service endpoints.
 
+/// Upper bounds on the number of entries in a DID document, to keep each document
+/// within a single ledger entry.
+pub const MAX_VERIFICATION_METHODS: u32 = 10;
+pub const MAX_SERVICES: u32 = 10;
+pub const MAX_ALSO_KNOWN_AS: u32 = 10;
+
+/// Upper bound on the number of controllers of a single DID.
+pub const MAX_CONTROLLERS: u32 = 10;
+
+/// Upper bound on the number of delegates recorded for a single DID.
+pub const MAX_DELEGATES: u32 = 20;
+
+/// Upper bound on the number of recovery guardians of a single DID.
+pub const MAX_GUARDIANS: u32 = 10;
+
+/// Upper bound on the number of Stellar accounts linked to a single DID.
+pub const MAX_LINKED_ACCOUNTS: u32 = 20;
+
+/// Upper bound on the number of DIDs returned by one `get_controlled_dids` page.
+pub const MAX_PAGE_SIZE: u32 = 50;
+
+/// Upper bound on the number of historical document versions one `extend_did_ttl` call
+/// extends. Every extended entry is part of the transaction footprint, so a DID with a long
+/// history is kept alive a page of versions at a time.
+pub const MAX_TTL_VERSION_PAGE: u32 = 20;
+
+/// Per-transaction limits of the network (protocol 21 settings) on the number of ledger
+/// entries written and on the total size of the events published. `register_dids` sizes its
+/// batches to stay within them.
+pub const TX_MAX_WRITE_ENTRIES: u32 = 25;
+pub const TX_MAX_EVENTS_SIZE_BYTES: u32 = 8198;
+
+/// Ledger entries written by every registration: the DID's controller set, version counter,
+/// first document version, status and status history.
+const REGISTRATION_WRITES: u32 = 5;
+/// Ledger entries written for each controller of a new DID: its index entry and position.
+const CONTROLLER_INDEX_WRITES: u32 = 2;
+/// Ledger entries written once per controller in a batch: its DID count and the nonce its
+/// authorization consumes.
+const CONTROLLER_WRITES: u32 = 2;
+/// Bytes of the `did_reg`, `did_upd` and `did_stat` events of a registration, beyond the
+/// XDR encoding of the `DidRegistration` and two more copies of its DID.
+const REGISTRATION_EVENT_BYTES: u32 = 616;
+/// Bytes of the transfer event a Stellar Asset Contract publishes for a deposit.
+const DEPOSIT_EVENT_BYTES: u32 = 244;
+
+/// Upper bound on the number of DIDs registered by one `register_dids` call. Besides the
+/// nonce of the admin's authorization, each registration writes at least
+/// `REGISTRATION_WRITES` + `CONTROLLER_INDEX_WRITES` entries, so no more fit within
+/// `TX_MAX_WRITE_ENTRIES`. Entries with several controllers, large documents or a deposit
+/// cost more, and a full batch may not fit (see `register_dids`).
+pub const MAX_BATCH_SIZE: u32 = (TX_MAX_WRITE_ENTRIES - 1) / (REGISTRATION_WRITES + CONTROLLER_INDEX_WRITES);
+
+/// Upper bound on the length of a document locator, and the schemes it may use.
+pub const MAX_LOCATOR_LEN: u32 = 256;
+pub const LOCATOR_SCHEMES: [&[u8]; 2] = [b"ipfs://", b"https://"];
+
+/// Number of bytes of the controller hash used as the method-specific id of a derived DID
+/// (rendered as twice as many hex characters).
+pub const DERIVED_ID_BYTES: usize = 20;
+
+// --- Storage TTL ---
+// Each DID lives in its own persistent entries. Their TTL is bumped whenever the DID is
+// registered, updated or resolved, and anyone can pay to extend it via `extend_did_ttl`.
+pub const DAY_IN_LEDGERS: u32 = 17280;
+pub const DID_TTL_EXTEND_TO: u32 = 180 * DAY_IN_LEDGERS;
+pub const DID_TTL_THRESHOLD: u32 = DID_TTL_EXTEND_TO - 30 * DAY_IN_LEDGERS;
+pub const INSTANCE_TTL_EXTEND_TO: u32 = 30 * DAY_IN_LEDGERS;
+pub const INSTANCE_TTL_THRESHOLD: u32 = INSTANCE_TTL_EXTEND_TO - DAY_IN_LEDGERS;
+
+/// Version of the storage layout this code reads and writes. It is recorded at
+/// initialization and by `migrate`; registries that never recorded it are at version 1.
+///
+/// - 1: each DID is a controller (Address), an untyped document (Map<Symbol, Val>) and a
+///   status in instance storage, keyed by `Controller`, `(2, did)` and `Status`.
+/// - 2: each DID lives in its own persistent entries, with a controller set, versioned
+///   `DidDocument`s and a status history.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Version of the event payloads below (`DidRegisteredEvent`, `DocumentUpdatedEvent` and
+/// the other `*Event` types), carried in each of them as `event_version`. It changes
+/// whenever one of them changes shape.
+pub const EVENT_VERSION: u32 = 1;
+
+/// Errors returned by the DID registry.
+/// The numeric codes are stable: new errors are appended and existing codes are never reused.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    DidNotFound = 3,
+    DidAlreadyRegistered = 4,
+    // The DID does not follow the `did:kosmos` grammar
+    InvalidDid = 5,
+    // The DID is valid but not in canonical form (see `did_syntax`)
+    NonCanonicalDid = 6,
+    // Free-form DIDs cannot take the shape of a derived DID
+    ReservedDid = 7,
+    DidNotActive = 8,
+    // The DID has been revoked or deactivated
+    DidTerminated = 9,
+    DidNotSuspended = 10,
+    VersionNotFound = 11,
+    // A document entry is missing a required field, the document has no verification method,
+    // or a schema version 1 document has entries that do not map onto `DidDocument` fields
+    InvalidDocument = 12,
+    // A document, delegate or linked account limit would be exceeded
+    TooManyEntries = 13,
+    // Two document entries, or two entries of a verification relationship, share an id
+    DuplicateId = 14,
+    VerificationMethodNotFound = 15,
+    VerificationMethodExists = 16,
+    ServiceNotFound = 17,
+    ServiceExists = 18,
+    NotAController = 19,
+    DuplicateSigner = 20,
+    NotEnoughSignatures = 21,
+    // Empty, oversized or duplicated controllers, or an unreachable threshold
+    InvalidControllerSet = 22,
+    NoPendingController = 23,
+    // The operation is not allowed once pre-rotation is enabled
+    UsesPreRotation = 24,
+    NextControllerAlreadyCommitted = 25,
+    NoNextControllerCommitted = 26,
+    CommitmentMismatch = 27,
+    NoGuardians = 28,
+    NotAGuardian = 29,
+    DuplicateGuardian = 30,
+    NotEnoughGuardianApprovals = 31,
+    // Empty, oversized or duplicated guardians, or an unreachable threshold
+    InvalidGuardianSet = 32,
+    // A recovery to a different controller set is already in progress
+    RecoveryConflict = 33,
+    GuardianAlreadyApproved = 34,
+    NoRecoveryInProgress = 35,
+    RecoveryNotExecutable = 36,
+    InvalidDelegateValidity = 37,
+    DelegateNotFound = 38,
+    SignatureExpired = 39,
+    // The signing key is not an ed25519 key designated for capability invocation
+    KeyNotAuthorized = 40,
+    AccountAlreadyLinked = 41,
+    AccountNotLinked = 42,
+    // A page size of zero or above `MAX_PAGE_SIZE` (`MAX_TTL_VERSION_PAGE` for
+    // `extend_did_ttl`) was requested
+    InvalidPageSize = 43,
+    // A registration deposit must be a positive amount
+    InvalidDeposit = 44,
+    // The locator is empty, too long or does not use an `ipfs://` or `https://` scheme
+    InvalidAnchor = 45,
+    // The document of a hash-anchored DID lives off-chain and cannot be edited here
+    DocumentAnchored = 46,
+    NotAnchored = 47,
+    // The stored layout is newer than this code (see `migrate`)
+    UnsupportedSchemaVersion = 48,
+    // A batch must hold between 1 and `MAX_BATCH_SIZE` entries
+    InvalidBatchSize = 49,
+    // The entry would take the batch past `TX_MAX_WRITE_ENTRIES` or `TX_MAX_EVENTS_SIZE_BYTES`
+    BatchLimitExceeded = 50,
+}
+
+/// A public key that can be used to authenticate or authorize interactions
+/// with the DID subject (W3C `verificationMethod` entry).
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerificationMethod {
+    // e.g. "did:kosmos:123456789#key-1"
+    pub id: String,
+    // e.g. "Ed25519VerificationKey2020"
+    pub method_type: String,
+    // The DID that controls this key
+    pub controller: String,
+    pub public_key: Bytes,
+}
+
+/// A way of communicating with the DID subject (W3C `service` entry).
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Service {
+    pub id: String,
+    // e.g. "LinkedDomains"
+    pub service_type: String,
+    pub service_endpoint: String,
+}
+
+/// The DID Document stored for each DID.
+/// The verification relationships list the ids of the verification methods
+/// that may be used for each purpose.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DidDocument {
+    pub verification_method: Vec<VerificationMethod>,
+    pub service: Vec<Service>,
+    pub also_known_as: Vec<String>,
+    pub authentication: Vec<String>,
+    pub assertion_method: Vec<String>,
+    pub key_agreement: Vec<String>,
+    pub capability_invocation: Vec<String>,
+    pub capability_delegation: Vec<String>,
+}
+
+/// The purposes a verification method can be designated for (DID Core verification relationships).
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VerificationRelationship {
+    Authentication,
+    AssertionMethod,
+    KeyAgreement,
+    CapabilityInvocation,
+    CapabilityDelegation,
+}
+
+impl DidDocument {
+    /// The ids of the verification methods designated for `relationship`.
+    pub fn relationship(&self, relationship: VerificationRelationship) -> &Vec<String> {
+        match relationship {
+            VerificationRelationship::Authentication => &self.authentication,
+            VerificationRelationship::AssertionMethod => &self.assertion_method,
+            VerificationRelationship::KeyAgreement => &self.key_agreement,
+            VerificationRelationship::CapabilityInvocation => &self.capability_invocation,
+            VerificationRelationship::CapabilityDelegation => &self.capability_delegation,
+        }
+    }
+}
+
+/// The addresses that control a DID, and how many of them must authorize
+/// `update_document`, `revoke_did` and controller changes (m-of-n).
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ControllerSet {
+    pub controllers: Vec<Address>,
+    pub threshold: u32,
+}
+
+/// The guardians who can recover a DID whose controller keys are lost.
+/// Once `threshold` guardians approve a recovery, it can be executed after `delay`
+/// seconds, during which the current controllers can still cancel it.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GuardianSet {
+    pub guardians: Vec<Address>,
+    pub threshold: u32,
+    pub delay: u64,
+}
+
+/// A recovery of a DID to a new controller set, in progress.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecoveryRequest {
+    pub new_controller_set: ControllerSet,
+    // Guardians who have approved the recovery so far
+    pub approvals: Vec<Address>,
+    pub initiated_at: u64,
+    // Ledger timestamp from which the recovery can be executed; 0 until enough guardians approve
+    pub executable_at: u64,
+}
+
+/// An address the controllers have authorized to act for a DID, for one purpose
+/// and a limited time (ERC-1056 style delegate).
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Delegate {
+    pub delegate: Address,
+    // The purpose of the delegation, e.g. "sigAuth" or "veriKey"
+    pub delegate_type: Symbol,
+    pub valid_from: u64,
+    // The delegation is valid while the ledger timestamp is below this value
+    pub valid_to: u64,
+}
+
+/// A version of a DID document, recorded each time the document changes.
+/// Versions are numbered from 1 (the registered document) and never overwritten,
+/// so a DID can be resolved as it was at any point in time.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DocumentVersion {
+    // DID Core `versionId`
+    pub version_id: u32,
+    pub document: DidDocument,
+    pub ledger_sequence: u32,
+    // DID Core `versionTime`, as a ledger timestamp (seconds since the Unix epoch)
+    pub timestamp: u64,
+}
+
+/// The message a DID key signs to authorize an operation that a relayer submits and
+/// pays for (see `register_did_signed`, `update_document_signed` and `revoke_did_signed`).
+/// The signature covers the XDR encoding of this struct.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignedOperation {
+    // The registry the operation is meant for, so it cannot be replayed on another deployment
+    pub registry: Address,
+    pub did: String,
+    // "register", "update" or "revoke"
+    pub operation: Symbol,
+    // Must equal the DID's current nonce (see `get_nonce`); 0 for a registration
+    pub nonce: u64,
+    // Last ledger timestamp at which the signature is accepted
+    pub expiry: u64,
+    // SHA-256 of the XDR encoding of the operation's arguments (see each operation)
+    pub args_hash: BytesN<32>,
+}
+
 #[contract]
 pub struct DidRegistryContract;
 
 /// Represents the status of a DID.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u32)]
 pub enum DidStatus {
     Active = 1,
+    // Permanently revoked, e.g. after a key compromise
     Revoked = 2,
+    // Temporarily disabled; can be reactivated by the controllers or the guardians
+    Suspended = 3,
+    // Permanently retired, with a reason code recorded in the status history
+    Deactivated = 4,
 }
 
-impl soroban_sdk::TryFromVal<Env, soroban_sdk::Val> for DidStatus {
-    type Error = soroban_sdk::ConversionError;
-    fn try_from_val(env: &Env, val: &soroban_sdk::Val) -> Result<Self, Self::Error> {
-        let u32_val = u32::try_from_val(env, val)?;
-        match u32_val {
-            1 => Ok(DidStatus::Active),
-            2 => Ok(DidStatus::Revoked),
-            _ => Err(soroban_sdk::ConversionError),
-        }
-    }
+/// A transition of a DID's status, as recorded in its status history.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StatusChange {
+    pub status: DidStatus,
+    // Application-defined reason code; 0 when none was given
+    pub reason: u32,
+    pub ledger_sequence: u32,
+    pub timestamp: u64,
 }
 
-impl soroban_sdk::IntoVal<Env, soroban_sdk::Val> for DidStatus {
-    fn into_val(self, env: &Env) -> soroban_sdk::Val {
-        (self as u32).into_val(env)
-    }
+// --- Events ---
+// Every change to the state of a DID (its controllers, document, status, anchor,
+// delegates, guardians, recovery, linked accounts and controller commitments) is published
+// with one of the payloads below, under a `(topic, did)` pair of topics, so an indexer can
+// rebuild that state from the event stream alone. Operations also publish their own events
+// (e.g. `did_rev` or `vm_add`), which say who acted and how.
+
+/// Published as `did_reg` when a DID is registered, before its first document version
+/// and status.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DidRegisteredEvent {
+    pub event_version: u32,
+    pub controller_set: ControllerSet,
+}
+
+/// Published as `did_upd` for every version of a DID document, including the one written
+/// at registration. The payload carries the whole document.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DocumentUpdatedEvent {
+    pub event_version: u32,
+    pub version_id: u32,
+    pub document: DidDocument,
+    // SHA-256 of the XDR encoding of `document`
+    pub document_hash: BytesN<32>,
+    pub ledger_sequence: u32,
+    pub timestamp: u64,
+}
+
+/// Published as `did_stat` for every status transition of a DID, including the `Active`
+/// status it is registered with; these mirror `get_status_history`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StatusChangedEvent {
+    pub event_version: u32,
+    pub status: DidStatus,
+    // Application-defined reason code; 0 when none was given
+    pub reason: u32,
+    pub ledger_sequence: u32,
+    pub timestamp: u64,
+}
+
+/// Published as `ctl_chg` whenever the controllers of a DID are replaced.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ControllerChangedEvent {
+    pub event_version: u32,
+    pub previous_controller_set: ControllerSet,
+    pub controller_set: ControllerSet,
+    // "transfer" (`accept_controller`), "rotation" (`rotate_controller`) or "recovery" (`execute_recovery`)
+    pub cause: Symbol,
+}
+
+/// Published as `ctl_prop` when a controller transfer is proposed, replacing any pending
+/// one. The transfer takes effect with the `ctl_chg` of `accept_controller`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ControllerProposedEvent {
+    pub event_version: u32,
+    pub controller_set: ControllerSet,
+}
+
+/// Published as `ctl_cmt` whenever a DID commits to its next controller set: by
+/// `commit_next_controller`, which also drops any pending transfer, and by every rotation.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ControllerCommittedEvent {
+    pub event_version: u32,
+    // See `controller_commitment`
+    pub commitment: BytesN<32>,
+}
+
+/// Published as `anc_set` whenever a hash-anchored DID is pointed at an off-chain
+/// document, at registration and by `update_anchor`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AnchorSetEvent {
+    pub event_version: u32,
+    pub anchor: DocumentAnchor,
+}
+
+/// Published as `dlg_add` when a delegation is added or renewed. It replaces any earlier
+/// delegation of the same delegate for the same type.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DelegateAddedEvent {
+    pub event_version: u32,
+    pub delegate: Delegate,
+}
+
+/// Published as `dlg_rev` when a delegation is revoked before it expires.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DelegateRevokedEvent {
+    pub event_version: u32,
+    pub delegate: Address,
+    pub delegate_type: Symbol,
+}
+
+/// Published as `grd_set` when the guardians of a DID are set. Any recovery in progress is
+/// cancelled at the same time.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GuardiansSetEvent {
+    pub event_version: u32,
+    pub guardian_set: GuardianSet,
+}
+
+/// Published as `rec_init` when a guardian starts a recovery, just before its `rec_appr`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecoveryInitiatedEvent {
+    pub event_version: u32,
+    pub guardian: Address,
+    pub new_controller_set: ControllerSet,
+    pub initiated_at: u64,
+}
+
+/// Published as `rec_appr` for every guardian approval of a recovery, including the first.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecoveryApprovedEvent {
+    pub event_version: u32,
+    pub guardian: Address,
+}
+
+/// Published as `rec_ready` when a recovery has enough approvals to be executed.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecoveryReadyEvent {
+    pub event_version: u32,
+    // Ledger timestamp from which the recovery can be executed
+    pub executable_at: u64,
+}
+
+/// Published as `rec_cncl` when the controllers cancel a recovery in progress.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecoveryCancelledEvent {
+    pub event_version: u32,
+    pub signers: Vec<Address>,
+}
+
+/// Published as `rec_done` when a recovery is executed, after its `ctl_chg`. Any pending
+/// transfer and controller commitment are dropped at the same time.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecoveryExecutedEvent {
+    pub event_version: u32,
+    pub previous_controller_set: ControllerSet,
+    pub controller_set: ControllerSet,
+}
+
+/// Published as `acct_link` when a Stellar account is linked to a DID, and as `acct_unl`
+/// when it is unlinked, by the controllers or by the account itself.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AccountLinkEvent {
+    pub event_version: u32,
+    pub account: Address,
+}
+
+/// One free-form DID to register with `register_dids`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DidRegistration {
+    pub did: String,
+    pub controllers: Vec<Address>,
+    pub threshold: u32,
+    pub document: DidDocument,
+}
+
+/// The outcome of one entry of a `register_dids` batch.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RegistrationResult {
+    Registered,
+    // The code of the `Error` the entry was rejected with
+    Failed(u32),
+}
+
+/// The outcome of one DID passed to `migrate`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MigrationResult {
+    Migrated,
+    // The DID has no schema version 1 entries, e.g. because it was migrated before
+    Skipped,
+    // The code of the `Error` its version 1 document was rejected with; the DID stays in
+    // the version 1 layout until its controller calls `migrate_legacy_did`
+    Failed(u32),
+}
+
+/// One page of the DIDs controlled by an address, returned by `get_controlled_dids`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ControlledDids {
+    pub dids: Vec<String>,
+    // Cursor to pass for the next page, or None if this is the last one
+    pub next_cursor: Option<u32>,
+}
+
+/// A registration deposit: an amount of a Stellar asset, held through its token contract.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Deposit {
+    pub token: Address,
+    pub amount: i128,
+}
+
+/// Points at a DID document kept off-chain (see `register_anchored_did`).
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DocumentAnchor {
+    // SHA-256 of the document bytes served at `locator`
+    pub hash: BytesN<32>,
+    // Where to fetch the document, e.g. `ipfs://<cid>` or an `https://` URL
+    pub locator: String,
+}
+
+/// Where the document of a DID is kept.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DocumentSource {
+    // The document is stored in the registry
+    OnChain,
+    // Only the anchor is stored; the on-chain document is empty
+    Anchored(DocumentAnchor),
 }
 
+/// The result of resolving a DID with `get_did`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DidResolution {
+    pub status: DidStatus,
+    pub document: DidDocument,
+    // Delegates that are valid at the time of resolution
+    pub delegates: Vec<Delegate>,
+    pub source: DocumentSource,
+}
 
 /// Storage keys for DID data.
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 #[repr(u32)]
 pub enum DataKey {
-    // Stores the controller (Address) of a DID (String)
+    // Stores the controllers (ControllerSet) of a DID (String)
     Controller(String) = 1,
-    // Stores the DID Document (Map<Symbol, Val>) for a DID (String)
-    Document(String) = 2, 
+    // Stores one version (DocumentVersion) of the DID Document for a DID (String)
+    Document(String, u32) = 2,
     // Stores the Status (DidStatus) of a DID (String)
     Status(String) = 3,
+    // Stores the proposed new controllers (ControllerSet) of a DID (String), until they accept
+    PendingController(String) = 4,
+    // Stores the current document version number (u32) of a DID (String)
+    Version(String) = 5,
+    // Stores the admin (Address) who curates free-form DID names
+    Admin = 6,
+    // Stores the delegates (Vec<Delegate>) of a DID (String)
+    Delegates(String) = 7,
+    // Stores the hash (BytesN<32>) of the pre-committed next controller set of a DID (String)
+    NextControllerCommitment(String) = 8,
+    // Stores the recovery guardians (GuardianSet) of a DID (String)
+    Guardians(String) = 9,
+    // Stores the recovery in progress (RecoveryRequest) for a DID (String)
+    Recovery(String) = 10,
+    // Stores the status transitions (Vec<StatusChange>) of a DID (String)
+    StatusHistory(String) = 11,
+    // Stores the next signed-operation nonce (u64) of a DID (String)
+    Nonce(String) = 12,
+    // Stores the Stellar accounts (Vec<Address>) linked to a DID (String)
+    LinkedAccounts(String) = 13,
+    // Stores the DID (String) a linked Stellar account (Address) belongs to
+    AccountDid(Address) = 14,
+    // Stores the number (u32) of live DIDs a controller (Address) controls
+    ControllerDidCount(Address) = 15,
+    // Stores the DID (String) at a position (u32) in a controller's (Address) index
+    ControllerDid(Address, u32) = 16,
+    // Stores the position (u32) of a DID (String) in a controller's (Address) index
+    ControllerDidIndex(Address, String) = 17,
+    // Stores the deposit (Deposit) required to register a DID, if any
+    DepositConfig = 18,
+    // Stores the deposit (Deposit) held for a DID (String)
+    Deposit(String) = 19,
+    // Stores the off-chain document pointer (DocumentAnchor) of a hash-anchored DID (String)
+    Anchor(String) = 20,
+    // Stores the storage layout version (u32) of the registry
+    SchemaVersion = 21,
+    // Stores the document (Map<Symbol, Val>) a DID (String) had under schema version 1
+    LegacyDocument(String) = 22,
+    // Stores the off-chain document pointer (DocumentAnchor) of one version (u32) of a hash-anchored DID (String)
+    AnchorVersion(String, u32) = 23,
 }
 
 impl soroban_sdk::IntoVal<Env, soroban_sdk::Val> for DataKey {
-    fn into_val(self, env: &Env) -> soroban_sdk::Val {
+    fn into_val(&self, env: &Env) -> soroban_sdk::Val {
         match self {
             DataKey::Controller(did) => {
-                let mut vec = Vec::new(env);
-                vec.push_back(1u32);
-                vec.push_back(did);
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(1u32.into_val(env));
+                vec.push_back(did.into_val(env));
                 vec.into_val(env)
             }
-            DataKey::Document(did) => {
-                let mut vec = Vec::new(env);
-                vec.push_back(2u32);
-                vec.push_back(did);
+            DataKey::Document(did, version_id) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(2u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.push_back(version_id.into_val(env));
                 vec.into_val(env)
             }
             DataKey::Status(did) => {
-                let mut vec = Vec::new(env);
-                vec.push_back(3u32);
-                vec.push_back(did);
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(3u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::PendingController(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(4u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::Version(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(5u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::Admin => 6u32.into_val(env),
+            DataKey::Delegates(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(7u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::NextControllerCommitment(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(8u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::Guardians(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(9u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::Recovery(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(10u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::StatusHistory(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(11u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::Nonce(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(12u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::LinkedAccounts(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(13u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::AccountDid(account) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(14u32.into_val(env));
+                vec.push_back(account.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::ControllerDidCount(controller) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(15u32.into_val(env));
+                vec.push_back(controller.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::ControllerDid(controller, position) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(16u32.into_val(env));
+                vec.push_back(controller.into_val(env));
+                vec.push_back(position.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::ControllerDidIndex(controller, did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(17u32.into_val(env));
+                vec.push_back(controller.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::DepositConfig => 18u32.into_val(env),
+            DataKey::Deposit(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(19u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::Anchor(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(20u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::SchemaVersion => 21u32.into_val(env),
+            DataKey::LegacyDocument(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(22u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::AnchorVersion(did, version_id) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(23u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.push_back(version_id.into_val(env));
                 vec.into_val(env)
             }
         }
@@ -76,93 +773,2155 @@ impl soroban_sdk::IntoVal<Env, soroban_sdk::Val> for DataKey {
 #[contractimpl]
 impl DidRegistryContract {
 
-    /// Registers a new 'did:kosmos' identifier.
-    /// The controller is the address that has authority over this DID.
-    /// The document contains public keys, service endpoints, etc.
-    pub fn register_did(env: Env, controller: Address, did: String, document: Map<Symbol, soroban_sdk::Val>) {
-        controller.require_auth();
-
-        let controller_key = DataKey::Controller(did.clone());
-        if env.storage().instance().has(&controller_key) {
-            panic!("DID already registered");
+    /// Initializes the registry with an administrator.
+    /// The admin curates free-form DID names registered through `register_did`.
+    pub fn initialize(env: Env, admin: Address) -> Result<(), Error> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(Error::AlreadyInitialized);
         }
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        Ok(())
+    }
 
-        env.storage().instance().set(&controller_key, &controller);
-        env.storage().instance().set(&DataKey::Document(did.clone()), &document);
-        env.storage().instance().set(&DataKey::Status(did.clone()), &DidStatus::Active);
+    /// Replaces the registry's code with the uploaded WASM `new_wasm_hash`, keeping all of
+    /// its storage. The new code runs from the next call on; if it changes the storage
+    /// layout, `migrate` must be called right after.
+    /// Must be authorized by the admin.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        let admin: Address = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin) => admin,
+            None => return Err(Error::NotInitialized),
+        };
+        admin.require_auth();
+
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
 
         // Emit event
         env.events().publish(
-            (symbol_short!("did_reg"), did),
-            controller
+            (symbol_short!("upgraded"),),
+            new_wasm_hash
         );
+
+        Ok(())
     }
 
-    /// Updates the DID document for an existing DID.
-    /// Only the current controller of the DID can perform this action.
-    pub fn update_document(env: Env, did: String, new_document: Map<Symbol, soroban_sdk::Val>) {
-        let controller_key = DataKey::Controller(did.clone());
-        if !env.storage().instance().has(&controller_key) {
-            panic!("DID not found");
+    /// Brings storage written by an earlier version of the registry up to `SCHEMA_VERSION`
+    /// and records the new version. Storage that is already current is left as it is.
+    /// Fails with `UnsupportedSchemaVersion` if the storage was written by a newer version,
+    /// e.g. after upgrading to older code.
+    ///
+    /// Version 1 kept DIDs in instance storage, where they cannot be listed, so the DIDs it
+    /// registered are passed in `legacy_dids` and each is moved to the current layout. They
+    /// can be spread over several calls; DIDs without version 1 entries are skipped. A DID
+    /// whose version 1 document does not convert to a valid `DidDocument` is left where it
+    /// is and reported as failed. This step runs whatever version is recorded, since a
+    /// version 1 registry has to be initialized, which records the current version, before
+    /// it can be migrated.
+    /// Returns one result per DID, in order. Must be authorized by the admin.
+    pub fn migrate(env: Env, legacy_dids: Vec<String>) -> Result<Vec<MigrationResult>, Error> {
+        let admin: Address = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin) => admin,
+            None => return Err(Error::NotInitialized),
+        };
+        admin.require_auth();
+
+        let from = Self::get_schema_version(env.clone());
+        if from > SCHEMA_VERSION {
+            return Err(Error::UnsupportedSchemaVersion);
+        }
+        let mut results = Vec::new(&env);
+        for did in legacy_dids.iter() {
+            results.push_back(match Self::migrate_did_to_v2(&env, &did, None) {
+                Ok(true) => MigrationResult::Migrated,
+                Ok(false) => MigrationResult::Skipped,
+                Err(err) => MigrationResult::Failed(err as u32),
+            });
         }
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("migrated"),),
+            (from, SCHEMA_VERSION)
+        );
+
+        Ok(results)
+    }
 
-        let controller: Address = env.storage().instance().get(&controller_key).unwrap();
+    /// Returns the version of the layout the registry's storage is in.
+    pub fn get_schema_version(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::SchemaVersion).unwrap_or(1)
+    }
+
+    /// Returns the document a DID had under schema version 1, if it was migrated from it.
+    /// That document predates `DidDocument` and is kept as it was, next to the version 1
+    /// `DidDocument` it was converted to or replaced with.
+    pub fn get_legacy_document(env: Env, did: String) -> Option<Map<Symbol, soroban_sdk::Val>> {
+        env.storage().persistent().get(&DataKey::LegacyDocument(did))
+    }
+
+    /// Migrates a DID that `migrate` left in the schema version 1 layout because its
+    /// document could not be converted, with `document` as its version 1 `DidDocument`.
+    /// Fails with `DidNotFound` if the DID has no version 1 entries.
+    /// Must be authorized by the DID's version 1 controller.
+    pub fn migrate_legacy_did(env: Env, did: String, document: DidDocument) -> Result<(), Error> {
+        let controller: Address = match env.storage().instance().get(&DataKey::Controller(did.clone())) {
+            Some(controller) => controller,
+            None => return Err(Error::DidNotFound),
+        };
         controller.require_auth();
 
-        let status: DidStatus = env.storage().instance().get(&DataKey::Status(did.clone())).unwrap();
-        if status != DidStatus::Active {
-            panic!("Cannot update a revoked DID");
-        }
+        Self::migrate_did_to_v2(&env, &did, Some(document))?;
+        Ok(())
+    }
+
+    /// Sets the deposit charged for every new DID, or removes it with `None`.
+    /// The deposit is taken from the first controller at registration (from the payer of a
+    /// signed registration), held by the registry and refunded to the first controller at
+    /// that time when the DID is revoked or deactivated. DIDs keep the deposit they paid when the configuration changes.
+    /// Must be authorized by the admin.
+    pub fn set_deposit(env: Env, deposit: Option<Deposit>) -> Result<(), Error> {
+        let admin: Address = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin) => admin,
+            None => return Err(Error::NotInitialized),
+        };
+        admin.require_auth();
 
-        env.storage().instance().set(&DataKey::Document(did.clone()), &new_document);
+        match &deposit {
+            Some(deposit) if deposit.amount <= 0 => return Err(Error::InvalidDeposit),
+            Some(deposit) => env.storage().instance().set(&DataKey::DepositConfig, deposit),
+            None => env.storage().instance().remove(&DataKey::DepositConfig),
+        }
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
 
         // Emit event
         env.events().publish(
-            (symbol_short!("did_upd"), did),
-            symbol_short!("doc_upd")
+            (symbol_short!("dep_set"),),
+            deposit
         );
+
+        Ok(())
     }
 
-    /// Revokes a DID. This is a permanent action.
-    /// Only the current controller of the DID can perform this action.
-    pub fn revoke_did(env: Env, did: String) {
-        let controller_key = DataKey::Controller(did.clone());
-        if !env.storage().instance().has(&controller_key) {
-            panic!("DID not found");
+    /// Returns the deposit currently charged for registering a DID, if any.
+    pub fn get_deposit(env: Env) -> Option<Deposit> {
+        env.storage().instance().get(&DataKey::DepositConfig)
+    }
+
+    /// Returns the deposit held for a DID, if one was paid and has not been refunded.
+    pub fn get_did_deposit(env: Env, did: String) -> Option<Deposit> {
+        env.storage().persistent().get(&DataKey::Deposit(did))
+    }
+
+    /// Registers a new 'did:kosmos' identifier with a free-form name.
+    /// The controller is the address that has authority over this DID.
+    /// The document contains public keys, service endpoints, etc.
+    /// Free-form names are curated, so the admin must also authorize the registration;
+    /// anyone else should use `register_derived_did`.
+    pub fn register_did(env: Env, controller: Address, did: String, document: DidDocument) -> Result<(), Error> {
+        let mut controllers = Vec::new(&env);
+        controllers.push_back(controller);
+        Self::register_multi_controller_did(env, controllers, 1, did, document)
+    }
+
+    /// Registers a new free-form 'did:kosmos' identifier controlled by several addresses.
+    /// `threshold` of the `controllers` must later sign to update, revoke or transfer the DID.
+    /// The admin and every controller must authorize the registration.
+    pub fn register_multi_controller_did(env: Env, controllers: Vec<Address>, threshold: u32, did: String, document: DidDocument) -> Result<(), Error> {
+        let admin: Address = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin) => admin,
+            None => return Err(Error::NotInitialized),
+        };
+        admin.require_auth();
+
+        Self::validate_did(&did)?;
+
+        let controller_set = ControllerSet { controllers, threshold };
+        Self::validate_controller_set(&controller_set)?;
+        for controller in controller_set.controllers.iter() {
+            controller.require_auth();
+        }
+
+        Self::validate_document(&document)?;
+        let payer = controller_set.controllers.get(0).unwrap();
+        Self::register(&env, &payer, controller_set, did, document)
+    }
+
+    /// Registers several free-form DIDs at once, e.g. when an organization onboards its
+    /// members, and returns the outcome of each entry in order. An entry that is invalid or
+    /// already registered (including earlier in the batch) is skipped without affecting the
+    /// others; a failing deposit transfer still fails the whole call.
+    /// Entries are taken in order while the ledger entries the call writes, authorization
+    /// nonces included, and the events it publishes fit within `TX_MAX_WRITE_ENTRIES` and
+    /// `TX_MAX_EVENTS_SIZE_BYTES`; an entry that does not fit is skipped with
+    /// `BatchLimitExceeded`, to be submitted in another batch.
+    /// Every entry is checked before any authorization is required. The admin must then
+    /// authorize the call, and so must every distinct controller of the entries that will be
+    /// registered, once each. Fails with `InvalidBatchSize` unless there are between 1 and
+    /// `MAX_BATCH_SIZE` entries.
+    pub fn register_dids(env: Env, registrations: Vec<DidRegistration>) -> Result<Vec<RegistrationResult>, Error> {
+        let admin: Address = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin) => admin,
+            None => return Err(Error::NotInitialized),
+        };
+
+        if registrations.is_empty() || registrations.len() > MAX_BATCH_SIZE {
+            return Err(Error::InvalidBatchSize);
+        }
+
+        // Check every entry first, so that only the controllers of DIDs that will be
+        // registered are asked to authorize, once each for the batch
+        let deposit = env.storage().instance().has(&DataKey::DepositConfig);
+        let mut results = Vec::new(&env);
+        let mut dids: Vec<String> = Vec::new(&env);
+        let mut signers: Vec<Address> = Vec::new(&env);
+        let mut payers: Vec<Address> = Vec::new(&env);
+        // The admin's authorization writes a nonce
+        let mut writes = 1;
+        let mut event_bytes = 0;
+        for registration in registrations.iter() {
+            let checked = Self::validate_registration(&registration).and_then(|()| {
+                if Self::is_registered(&env, &registration.did) || dids.contains(&registration.did) {
+                    return Err(Error::DidAlreadyRegistered);
+                }
+                let (entry_writes, entry_event_bytes) = Self::registration_cost(&env, &registration, &signers, &payers, deposit);
+                if writes + entry_writes > TX_MAX_WRITE_ENTRIES || event_bytes + entry_event_bytes > TX_MAX_EVENTS_SIZE_BYTES {
+                    return Err(Error::BatchLimitExceeded);
+                }
+                Ok((entry_writes, entry_event_bytes))
+            });
+            match checked {
+                Ok((entry_writes, entry_event_bytes)) => {
+                    writes += entry_writes;
+                    event_bytes += entry_event_bytes;
+                    dids.push_back(registration.did.clone());
+                    let payer = registration.controllers.get(0).unwrap();
+                    if !payers.contains(&payer) {
+                        payers.push_back(payer);
+                    }
+                    for controller in registration.controllers.iter() {
+                        if !signers.contains(&controller) {
+                            signers.push_back(controller);
+                        }
+                    }
+                    results.push_back(RegistrationResult::Registered);
+                }
+                Err(error) => results.push_back(RegistrationResult::Failed(error as u32)),
+            }
+        }
+        admin.require_auth();
+        for signer in signers.iter() {
+            signer.require_auth();
+        }
+
+        for (i, registration) in registrations.iter().enumerate() {
+            if results.get(i as u32) != Some(RegistrationResult::Registered) {
+                continue;
+            }
+            let payer = registration.controllers.get(0).unwrap();
+            let controller_set = ControllerSet { controllers: registration.controllers, threshold: registration.threshold };
+            if let Err(error) = Self::register(&env, &payer, controller_set, registration.did, registration.document) {
+                results.set(i as u32, RegistrationResult::Failed(error as u32));
+            }
         }
 
-        let controller: Address = env.storage().instance().get(&controller_key).unwrap();
+        Ok(results)
+    }
+
+    /// Registers the DID derived from the controller's address (see `derive_did`).
+    /// No admin involvement is needed: a given address always maps to the same DID,
+    /// so nobody can claim an identifier that belongs to someone else's key.
+    /// Returns the registered DID.
+    pub fn register_derived_did(env: Env, controller: Address, document: DidDocument) -> Result<String, Error> {
+        controller.require_auth();
+
+        Self::validate_document(&document)?;
+        let did = Self::derive_did(env.clone(), controller.clone());
+        let mut controllers = Vec::new(&env);
+        controllers.push_back(controller.clone());
+        Self::register(&env, &controller, ControllerSet { controllers, threshold: 1 }, did.clone(), document)?;
+
+        Ok(did)
+    }
+
+    /// Registers the DID derived from the controller's address (see `derive_did`) with its
+    /// document kept off-chain: only the document's SHA-256 hash and a locator to fetch it
+    /// from are stored. The on-chain document of such a DID stays empty and cannot be edited;
+    /// the controllers publish a new document with `update_anchor` instead. Each anchor is
+    /// recorded for the document version it was set with (see `get_anchor_version`).
+    /// Returns the registered DID.
+    pub fn register_anchored_did(env: Env, controller: Address, anchor: DocumentAnchor) -> Result<String, Error> {
         controller.require_auth();
 
-        env.storage().instance().set(&DataKey::Status(did.clone()), &DidStatus::Revoked);
+        Self::validate_anchor(&anchor)?;
+        let did = Self::derive_did(env.clone(), controller.clone());
+        let mut controllers = Vec::new(&env);
+        controllers.push_back(controller.clone());
+        let document = DidDocument {
+            verification_method: Vec::new(&env),
+            service: Vec::new(&env),
+            also_known_as: Vec::new(&env),
+            authentication: Vec::new(&env),
+            assertion_method: Vec::new(&env),
+            key_agreement: Vec::new(&env),
+            capability_invocation: Vec::new(&env),
+            capability_delegation: Vec::new(&env),
+        };
+        Self::register(&env, &controller, ControllerSet { controllers, threshold: 1 }, did.clone(), document)?;
+        Self::set_anchor(&env, &did, anchor);
+
+        Ok(did)
+    }
+
+    /// Points a hash-anchored DID at a new off-chain document. Like a document update, this
+    /// writes the next document version, which stays empty on-chain and carries the new
+    /// anchor.
+    /// `signers` must contain at least `threshold` of the DID's controllers, each of which must authorize the call.
+    pub fn update_anchor(env: Env, did: String, signers: Vec<Address>, anchor: DocumentAnchor) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+
+        if !env.storage().persistent().has(&DataKey::Anchor(did.clone())) {
+            return Err(Error::NotAnchored);
+        }
+        Self::validate_anchor(&anchor)?;
+        let document = Self::current_document(&env, &did);
+        Self::write_document_version(&env, &did, document);
+        Self::set_anchor(&env, &did, anchor);
+
+        Ok(())
+    }
+
+    /// Returns the anchor a hash-anchored DID had at a version of its document, i.e. the
+    /// hash and locator of the off-chain document that version stands for.
+    /// Fails with `NotAnchored` if the DID's document is kept on-chain.
+    pub fn get_anchor_version(env: Env, did: String, version_id: u32) -> Result<DocumentAnchor, Error> {
+        if !env.storage().persistent().has(&DataKey::Controller(did.clone())) {
+            return Err(Error::DidNotFound);
+        }
+        if !env.storage().persistent().has(&DataKey::Anchor(did.clone())) {
+            return Err(Error::NotAnchored);
+        }
+
+        match env.storage().persistent().get(&DataKey::AnchorVersion(did, version_id)) {
+            Some(anchor) => Ok(anchor),
+            None => Err(Error::VersionNotFound),
+        }
+    }
+
+    /// Computes the DID that `register_derived_did` assigns to a controller:
+    /// `did:kosmos:`, the network segment of the network the registry runs on, and the hex
+    /// encoding of the first `DERIVED_ID_BYTES` bytes of the SHA-256 hash of the network id
+    /// followed by the address's XDR encoding. The same controller therefore gets a
+    /// different DID on each network.
+    pub fn derive_did(env: Env, controller: Address) -> String {
+        Self::derived_did(&env, controller.to_xdr(&env))
+    }
+
+    /// Registers the DID derived from an ed25519 key (see `derive_key_did`) on behalf of
+    /// the key holder, who only signs; the relayer submitting the call pays for it.
+    /// `key_id` names a verification method of `document` that holds the 32-byte key and
+    /// is listed under `capabilityInvocation`. The signature is over a `SignedOperation`
+    /// with operation "register", nonce 0 and `args_hash` the SHA-256 of the XDR encoding
+    /// of `(ControllerSet { controllers, threshold }, document)`.
+    /// The signature is the only authorization: the controllers need not authorize the call,
+    /// so they may be accounts that do not exist on the ledger yet. A relayer cannot register
+    /// someone else's key under its own controllers, since the DID is bound to the signing
+    /// key and the signature covers the controller set. `payer` pays the registration
+    /// deposit, if one is configured, and must authorize the call in that case.
+    /// Returns the registered DID.
+    /// Everything but the signature is checked first and fails with an `Error`; a signature
+    /// that does not verify traps, failing the call with a host error instead.
+    #[allow(clippy::too_many_arguments)]
+    pub fn register_did_signed(env: Env, payer: Address, controllers: Vec<Address>, threshold: u32, document: DidDocument, key_id: String, expiry: u64, signature: BytesN<64>) -> Result<String, Error> {
+        let public_key = Self::signing_key(&document, &key_id)?;
+        let did = Self::derive_key_did(env.clone(), public_key.clone());
+
+        let controller_set = ControllerSet { controllers, threshold };
+        Self::validate_controller_set(&controller_set)?;
+        Self::validate_document(&document)?;
+        if env.storage().persistent().has(&DataKey::Controller(did.clone())) {
+            return Err(Error::DidAlreadyRegistered);
+        }
+        let args_hash = env.crypto().sha256(&(controller_set.clone(), document.clone()).to_xdr(&env)).to_bytes();
+        Self::verify_signed_operation(&env, &did, &public_key, symbol_short!("register"), args_hash, expiry, &signature)?;
+        if env.storage().instance().has(&DataKey::DepositConfig) {
+            payer.require_auth();
+        }
+
+        Self::register(&env, &payer, controller_set, did.clone(), document)?;
+
+        Ok(did)
+    }
+
+    /// Computes the DID that `register_did_signed` assigns to an ed25519 public key, like
+    /// `derive_did` but hashing the key instead of an address.
+    pub fn derive_key_did(env: Env, public_key: BytesN<32>) -> String {
+        Self::derived_did(&env, Bytes::from(public_key))
+    }
+
+    /// Updates the DID document for an existing DID.
+    /// `signers` must contain at least `threshold` of the DID's controllers, each of which must authorize the call.
+    pub fn update_document(env: Env, did: String, signers: Vec<Address>, new_document: DidDocument) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+
+        Self::write_document_change(&env, &did, new_document)?;
+
+        Ok(())
+    }
+
+    /// Updates the DID document on behalf of the holder of one of its keys, who only signs;
+    /// the relayer submitting the call pays for it.
+    /// `key_id` names a verification method of the current document that holds a 32-byte
+    /// ed25519 key and is listed under `capabilityInvocation`. The signature is over a
+    /// `SignedOperation` with operation "update", the DID's current nonce and `args_hash`
+    /// the SHA-256 of the XDR encoding of `new_document`.
+    /// A key holder acts alone, so this fails with `NotEnoughSignatures` unless the DID has
+    /// a single controller. Everything but the signature is checked first and fails with an
+    /// `Error`; a signature that does not verify traps, failing the call with a host error.
+    pub fn update_document_signed(env: Env, did: String, new_document: DidDocument, key_id: String, expiry: u64, signature: BytesN<64>) -> Result<(), Error> {
+        Self::require_single_controller(&env, &did)?;
+        Self::require_active(&env, &did)?;
+        if env.storage().persistent().has(&DataKey::Anchor(did.clone())) {
+            return Err(Error::DocumentAnchored);
+        }
+        Self::validate_document(&new_document)?;
+
+        let public_key = Self::signing_key(&Self::current_document(&env, &did), &key_id)?;
+        let args_hash = env.crypto().sha256(&new_document.clone().to_xdr(&env)).to_bytes();
+        Self::verify_signed_operation(&env, &did, &public_key, symbol_short!("update"), args_hash, expiry, &signature)?;
+
+        Self::write_document_change(&env, &did, new_document)?;
+
+        Ok(())
+    }
+
+    /// Adds a verification method to the DID document, creating a new document version.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn add_verification_method(env: Env, did: String, signers: Vec<Address>, method: VerificationMethod) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+
+        let mut document = Self::current_document(&env, &did);
+        if document.verification_method.iter().any(|existing| existing.id == method.id) {
+            return Err(Error::VerificationMethodExists);
+        }
+        document.verification_method.push_back(method.clone());
+        Self::write_document_change(&env, &did, document)?;
 
         // Emit event
         env.events().publish(
-            (symbol_short!("did_rev"), did),
-            controller
+            (symbol_short!("vm_add"), did),
+            method.id
         );
+
+        Ok(())
     }
 
-    /// Resolves a DID string to its document and status.
-    /// This is a read-only function.
-    pub fn get_did(env: Env, did: String) -> (DidStatus, Map<Symbol, soroban_sdk::Val>) {
-        let controller_key = DataKey::Controller(did.clone());
-        if !env.storage().instance().has(&controller_key) {
-            panic!("DID not found");
+    /// Replaces the verification method with the same id, creating a new document version.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn replace_verification_method(env: Env, did: String, signers: Vec<Address>, method: VerificationMethod) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+
+        let mut document = Self::current_document(&env, &did);
+        let index = match document.verification_method.iter().position(|existing| existing.id == method.id) {
+            Some(index) => index as u32,
+            None => return Err(Error::VerificationMethodNotFound),
+        };
+        document.verification_method.set(index, method.clone());
+        Self::write_document_change(&env, &did, document)?;
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("vm_repl"), did),
+            method.id
+        );
+
+        Ok(())
+    }
+
+    /// Removes a verification method by id, creating a new document version.
+    /// The method is also removed from every verification relationship.
+    /// A document must keep at least one verification method.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn remove_verification_method(env: Env, did: String, signers: Vec<Address>, method_id: String) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+
+        let mut document = Self::current_document(&env, &did);
+        let index = match document.verification_method.iter().position(|existing| existing.id == method_id) {
+            Some(index) => index as u32,
+            None => return Err(Error::VerificationMethodNotFound),
+        };
+        document.verification_method.remove(index);
+        for references in [
+            &mut document.authentication,
+            &mut document.assertion_method,
+            &mut document.key_agreement,
+            &mut document.capability_invocation,
+            &mut document.capability_delegation,
+        ] {
+            if let Some(i) = references.iter().position(|id| id == method_id) {
+                references.remove(i as u32);
+            }
         }
+        Self::write_document_change(&env, &did, document)?;
 
-        let status: DidStatus = env.storage().instance().get(&DataKey::Status(did.clone())).unwrap();
-        let document: Map<Symbol, soroban_sdk::Val> = env.storage().instance().get(&DataKey::Document(did.clone())).unwrap();
+        // Emit event
+        env.events().publish(
+            (symbol_short!("vm_rem"), did),
+            method_id
+        );
 
-        (status, document)
+        Ok(())
     }
 
-     /// Resolves a DID string to its controller address.
-    pub fn get_controller(env: Env, did: String) -> Address {
-        let controller_key = DataKey::Controller(did.clone());
-        if !env.storage().instance().has(&controller_key) {
-            panic!("DID not found");
+    /// Adds a service endpoint to the DID document, creating a new document version.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn add_service(env: Env, did: String, signers: Vec<Address>, service: Service) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+
+        let mut document = Self::current_document(&env, &did);
+        if document.service.iter().any(|existing| existing.id == service.id) {
+            return Err(Error::ServiceExists);
         }
-        env.storage().instance().get(&controller_key).unwrap()
+        document.service.push_back(service.clone());
+        Self::write_document_change(&env, &did, document)?;
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("svc_add"), did),
+            service.id
+        );
+
+        Ok(())
     }
-}
+
+    /// Replaces the service endpoint with the same id, creating a new document version.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn replace_service(env: Env, did: String, signers: Vec<Address>, service: Service) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+
+        let mut document = Self::current_document(&env, &did);
+        let index = match document.service.iter().position(|existing| existing.id == service.id) {
+            Some(index) => index as u32,
+            None => return Err(Error::ServiceNotFound),
+        };
+        document.service.set(index, service.clone());
+        Self::write_document_change(&env, &did, document)?;
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("svc_repl"), did),
+            service.id
+        );
+
+        Ok(())
+    }
+
+    /// Removes a service endpoint by id, creating a new document version.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn remove_service(env: Env, did: String, signers: Vec<Address>, service_id: String) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+
+        let mut document = Self::current_document(&env, &did);
+        let index = match document.service.iter().position(|existing| existing.id == service_id) {
+            Some(index) => index as u32,
+            None => return Err(Error::ServiceNotFound),
+        };
+        document.service.remove(index);
+        Self::write_document_change(&env, &did, document)?;
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("svc_rem"), did),
+            service_id
+        );
+
+        Ok(())
+    }
+
+    /// Revokes a DID. This is a permanent action.
+    /// `signers` must contain at least `threshold` of the DID's controllers, each of which must authorize the call.
+    pub fn revoke_did(env: Env, did: String, signers: Vec<Address>) -> Result<(), Error> {
+        let controller_set = Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_not_terminated(&env, &did)?;
+
+        Self::set_status(&env, &did, DidStatus::Revoked, 0);
+        Self::update_controller_index(&env, &did, &controller_set.controllers, &Vec::new(&env));
+        Self::refund_deposit(&env, &did, &controller_set);
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_rev"), did),
+            signers
+        );
+
+        Ok(())
+    }
+
+    /// Revokes a DID on behalf of the holder of one of its keys, who only signs;
+    /// the relayer submitting the call pays for it. This is a permanent action.
+    /// `key_id` names a verification method of the current document that holds a 32-byte
+    /// ed25519 key and is listed under `capabilityInvocation`. The signature is over a
+    /// `SignedOperation` with operation "revoke", the DID's current nonce and `args_hash`
+    /// the SHA-256 of empty bytes.
+    /// A key holder acts alone, so this fails with `NotEnoughSignatures` unless the DID has
+    /// a single controller. Everything but the signature is checked first and fails with an
+    /// `Error`; a signature that does not verify traps, failing the call with a host error.
+    /// The event carries `key_id` in place of the controller signers.
+    pub fn revoke_did_signed(env: Env, did: String, key_id: String, expiry: u64, signature: BytesN<64>) -> Result<(), Error> {
+        Self::require_single_controller(&env, &did)?;
+        Self::require_not_terminated(&env, &did)?;
+
+        let public_key = Self::signing_key(&Self::current_document(&env, &did), &key_id)?;
+        let args_hash = env.crypto().sha256(&Bytes::new(&env)).to_bytes();
+        Self::verify_signed_operation(&env, &did, &public_key, symbol_short!("revoke"), args_hash, expiry, &signature)?;
+
+        let controller_set: ControllerSet = env.storage().persistent().get(&DataKey::Controller(did.clone())).unwrap();
+        Self::set_status(&env, &did, DidStatus::Revoked, 0);
+        Self::update_controller_index(&env, &did, &controller_set.controllers, &Vec::new(&env));
+        Self::refund_deposit(&env, &did, &controller_set);
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_rev"), did),
+            key_id
+        );
+
+        Ok(())
+    }
+
+    /// Returns the nonce the next signed operation on a DID must carry.
+    pub fn get_nonce(env: Env, did: String) -> u64 {
+        env.storage().persistent().get(&DataKey::Nonce(did)).unwrap_or(0)
+    }
+
+    /// Temporarily suspends an active DID, e.g. when a key leak is suspected.
+    /// While suspended the document cannot be updated and delegates are not valid,
+    /// but the controllers can still be rotated or recovered.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn suspend_did(env: Env, did: String, signers: Vec<Address>, reason: u32) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+
+        Self::set_status(&env, &did, DidStatus::Suspended, reason);
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_susp"), did),
+            reason
+        );
+
+        Ok(())
+    }
+
+    /// Reactivates a suspended DID.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn reactivate_did(env: Env, did: String, signers: Vec<Address>) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::reactivate(&env, &did)?;
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_react"), did),
+            signers
+        );
+
+        Ok(())
+    }
+
+    /// Reactivates a suspended DID with the approval of a quorum of its guardians,
+    /// for when the controllers cannot act.
+    /// `guardians` must contain at least `threshold` of the DID's guardians, each of which must authorize the call.
+    pub fn reactivate_did_by_guardians(env: Env, did: String, guardians: Vec<Address>) -> Result<(), Error> {
+        let guardian_set: GuardianSet = match env.storage().persistent().get(&DataKey::Guardians(did.clone())) {
+            Some(guardian_set) => guardian_set,
+            None => return Err(Error::NoGuardians),
+        };
+        for (i, guardian) in guardians.iter().enumerate() {
+            if !guardian_set.guardians.contains(&guardian) {
+                return Err(Error::NotAGuardian);
+            }
+            for other in guardians.iter().skip(i + 1) {
+                if other == guardian {
+                    return Err(Error::DuplicateGuardian);
+                }
+            }
+        }
+        if guardians.len() < guardian_set.threshold {
+            return Err(Error::NotEnoughGuardianApprovals);
+        }
+        for guardian in guardians.iter() {
+            guardian.require_auth();
+        }
+
+        Self::reactivate(&env, &did)?;
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_react"), did),
+            guardians
+        );
+
+        Ok(())
+    }
+
+    /// Permanently deactivates a DID, recording why.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn deactivate_did(env: Env, did: String, signers: Vec<Address>, reason: u32) -> Result<(), Error> {
+        let controller_set = Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_not_terminated(&env, &did)?;
+
+        Self::set_status(&env, &did, DidStatus::Deactivated, reason);
+        Self::update_controller_index(&env, &did, &controller_set.controllers, &Vec::new(&env));
+        Self::refund_deposit(&env, &did, &controller_set);
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_deact"), did),
+            reason
+        );
+
+        Ok(())
+    }
+
+    /// Returns every status transition of a DID, oldest first, starting with its registration.
+    pub fn get_status_history(env: Env, did: String) -> Result<Vec<StatusChange>, Error> {
+        match env.storage().persistent().get(&DataKey::StatusHistory(did)) {
+            Some(history) => Ok(history),
+            None => Err(Error::DidNotFound),
+        }
+    }
+
+    /// Returns the current status of a DID.
+    pub fn get_status(env: Env, did: String) -> Result<DidStatus, Error> {
+        match env.storage().persistent().get(&DataKey::Status(did)) {
+            Some(status) => Ok(status),
+            None => Err(Error::DidNotFound),
+        }
+    }
+
+    /// Resolves a DID string to its document, status and currently valid delegates.
+    /// This is a read-only function.
+    pub fn get_did(env: Env, did: String) -> Result<DidResolution, Error> {
+        let controller_key = DataKey::Controller(did.clone());
+        if !env.storage().persistent().has(&controller_key) {
+            return Err(Error::DidNotFound);
+        }
+
+        let status: DidStatus = env.storage().persistent().get(&DataKey::Status(did.clone())).unwrap();
+        let document = Self::current_document(&env, &did);
+        let delegates = Self::active_delegates(&env, &did);
+        let source = match env.storage().persistent().get(&DataKey::Anchor(did.clone())) {
+            Some(anchor) => DocumentSource::Anchored(anchor),
+            None => DocumentSource::OnChain,
+        };
+        Self::extend_ttl_for(&env, &did);
+
+        Ok(DidResolution { status, document, delegates, source })
+    }
+
+    /// Resolves a DID to a specific version of its document (DID Core `versionId`).
+    pub fn get_did_version(env: Env, did: String, version_id: u32) -> Result<DocumentVersion, Error> {
+        if !env.storage().persistent().has(&DataKey::Controller(did.clone())) {
+            return Err(Error::DidNotFound);
+        }
+
+        match env.storage().persistent().get(&DataKey::Document(did, version_id)) {
+            Some(version) => Ok(version),
+            None => Err(Error::VersionNotFound),
+        }
+    }
+
+    /// Resolves a DID to the version of its document that was current at `timestamp`
+    /// (DID Core `versionTime`), i.e. the latest version written at or before it.
+    pub fn get_did_at(env: Env, did: String, timestamp: u64) -> Result<DocumentVersion, Error> {
+        if !env.storage().persistent().has(&DataKey::Controller(did.clone())) {
+            return Err(Error::DidNotFound);
+        }
+
+        match Self::document_at(&env, &did, timestamp) {
+            Some(version) => Ok(version),
+            None => Err(Error::VersionNotFound),
+        }
+    }
+
+    /// Checks whether the verification method with `public_key` was designated for
+    /// `relationship` in the DID's document at `timestamp`, and the DID was active then.
+    /// Lets credential and proof verifiers check that a signature was made with a key
+    /// the DID subject had authorized for that purpose.
+    pub fn is_authorized_key(env: Env, did: String, public_key: Bytes, relationship: VerificationRelationship, timestamp: u64) -> Result<bool, Error> {
+        if !env.storage().persistent().has(&DataKey::Controller(did.clone())) {
+            return Err(Error::DidNotFound);
+        }
+
+        if Self::status_at(&env, &did, timestamp) != Some(DidStatus::Active) {
+            return Ok(false);
+        }
+        let document = match Self::document_at(&env, &did, timestamp) {
+            Some(version) => version.document,
+            None => return Ok(false),
+        };
+
+        let method_ids = document.relationship(relationship);
+        Ok(document
+            .verification_method
+            .iter()
+            .any(|method| method.public_key == public_key && method_ids.contains(&method.id)))
+    }
+
+    /// Resolves a DID string to its controllers and authorization threshold.
+    pub fn get_controller(env: Env, did: String) -> Result<ControllerSet, Error> {
+        let controller_key = DataKey::Controller(did.clone());
+        if !env.storage().persistent().has(&controller_key) {
+            return Err(Error::DidNotFound);
+        }
+        Self::extend_ttl_for(&env, &did);
+        Ok(env.storage().persistent().get(&controller_key).unwrap())
+    }
+
+    /// Proposes a new controller set for a DID (first step of a controller transfer).
+    /// Requires `threshold` of the current controllers; the transfer takes effect once
+    /// the new controllers call `accept_controller`. A new proposal replaces any pending one.
+    pub fn change_controller(env: Env, did: String, signers: Vec<Address>, new_controllers: Vec<Address>, new_threshold: u32) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_not_terminated(&env, &did)?;
+
+        if env.storage().persistent().has(&DataKey::NextControllerCommitment(did.clone())) {
+            return Err(Error::UsesPreRotation);
+        }
+
+        let new_controller_set = ControllerSet { controllers: new_controllers, threshold: new_threshold };
+        Self::validate_controller_set(&new_controller_set)?;
+
+        env.storage().persistent().set(&DataKey::PendingController(did.clone()), &new_controller_set);
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("ctl_prop"), did),
+            ControllerProposedEvent {
+                event_version: EVENT_VERSION,
+                controller_set: new_controller_set,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Accepts a pending controller transfer (second step).
+    /// Must be authorized by every proposed controller, which proves they hold the new keys.
+    pub fn accept_controller(env: Env, did: String) -> Result<(), Error> {
+        let controller_key = DataKey::Controller(did.clone());
+        if !env.storage().persistent().has(&controller_key) {
+            return Err(Error::DidNotFound);
+        }
+
+        let pending_key = DataKey::PendingController(did.clone());
+        let new_controller_set: ControllerSet = match env.storage().persistent().get(&pending_key) {
+            Some(controller_set) => controller_set,
+            None => return Err(Error::NoPendingController),
+        };
+        for controller in new_controller_set.controllers.iter() {
+            controller.require_auth();
+        }
+
+        Self::require_not_terminated(&env, &did)?;
+        if env.storage().persistent().has(&DataKey::NextControllerCommitment(did.clone())) {
+            return Err(Error::UsesPreRotation);
+        }
+
+        let old_controller_set: ControllerSet = env.storage().persistent().get(&controller_key).unwrap();
+        env.storage().persistent().remove(&pending_key);
+        Self::set_controllers(&env, &did, old_controller_set, new_controller_set, symbol_short!("transfer"));
+        Self::extend_ttl_for(&env, &did);
+
+        Ok(())
+    }
+
+    /// Returns the proposed controller set of a DID, if a transfer is pending.
+    pub fn get_pending_controller(env: Env, did: String) -> Option<ControllerSet> {
+        env.storage().persistent().get(&DataKey::PendingController(did))
+    }
+
+    /// Computes the commitment to a controller set used for pre-rotation:
+    /// the SHA-256 hash of the set's XDR encoding.
+    pub fn controller_commitment(env: Env, controllers: Vec<Address>, threshold: u32) -> BytesN<32> {
+        let controller_set = ControllerSet { controllers, threshold };
+        env.crypto().sha256(&controller_set.to_xdr(&env)).to_bytes()
+    }
+
+    /// Enables pre-rotation for a DID by committing to the hash of its next controller set
+    /// (see `controller_commitment`). From then on the controllers can only be changed with
+    /// `rotate_controller`, by the holders of the committed keys, so a stolen current key
+    /// is not enough to take over the DID.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn commit_next_controller(env: Env, did: String, signers: Vec<Address>, commitment: BytesN<32>) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_not_terminated(&env, &did)?;
+
+        // Once set, the commitment can only be replaced by a rotation
+        let commitment_key = DataKey::NextControllerCommitment(did.clone());
+        if env.storage().persistent().has(&commitment_key) {
+            return Err(Error::NextControllerAlreadyCommitted);
+        }
+
+        env.storage().persistent().set(&commitment_key, &commitment);
+        env.storage().persistent().remove(&DataKey::PendingController(did.clone()));
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("ctl_cmt"), did),
+            ControllerCommittedEvent {
+                event_version: EVENT_VERSION,
+                commitment,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Rotates a pre-rotation DID to the controller set it committed to, and commits to the
+    /// set that will follow it. Every new controller must authorize the rotation; the current
+    /// controllers are not involved.
+    pub fn rotate_controller(env: Env, did: String, new_controllers: Vec<Address>, new_threshold: u32, next_commitment: BytesN<32>) -> Result<(), Error> {
+        let controller_key = DataKey::Controller(did.clone());
+        if !env.storage().persistent().has(&controller_key) {
+            return Err(Error::DidNotFound);
+        }
+
+        let commitment_key = DataKey::NextControllerCommitment(did.clone());
+        let commitment: BytesN<32> = match env.storage().persistent().get(&commitment_key) {
+            Some(commitment) => commitment,
+            None => return Err(Error::NoNextControllerCommitted),
+        };
+
+        Self::require_not_terminated(&env, &did)?;
+
+        let new_controller_set = ControllerSet { controllers: new_controllers, threshold: new_threshold };
+        if env.crypto().sha256(&new_controller_set.clone().to_xdr(&env)).to_bytes() != commitment {
+            return Err(Error::CommitmentMismatch);
+        }
+        Self::validate_controller_set(&new_controller_set)?;
+        for controller in new_controller_set.controllers.iter() {
+            controller.require_auth();
+        }
+
+        let old_controller_set: ControllerSet = env.storage().persistent().get(&controller_key).unwrap();
+        env.storage().persistent().set(&commitment_key, &next_commitment);
+        Self::set_controllers(&env, &did, old_controller_set, new_controller_set, symbol_short!("rotation"));
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("ctl_cmt"), did),
+            ControllerCommittedEvent {
+                event_version: EVENT_VERSION,
+                commitment: next_commitment,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Returns the committed hash of a DID's next controller set, if pre-rotation is enabled.
+    pub fn get_next_controller_commitment(env: Env, did: String) -> Option<BytesN<32>> {
+        env.storage().persistent().get(&DataKey::NextControllerCommitment(did))
+    }
+
+    /// Designates the guardians who can recover the DID, replacing any previous set and
+    /// cancelling any recovery in progress.
+    /// Guardians must be configured before pre-rotation is enabled, since otherwise a
+    /// stolen current key could appoint guardians and bypass the commitment.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn set_guardians(env: Env, did: String, signers: Vec<Address>, guardians: Vec<Address>, threshold: u32, delay: u64) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_not_terminated(&env, &did)?;
+        if env.storage().persistent().has(&DataKey::NextControllerCommitment(did.clone())) {
+            return Err(Error::UsesPreRotation);
+        }
+
+        let count = guardians.len();
+        if count == 0 || count > MAX_GUARDIANS {
+            return Err(Error::InvalidGuardianSet);
+        }
+        if threshold == 0 || threshold > count {
+            return Err(Error::InvalidGuardianSet);
+        }
+        for (i, guardian) in guardians.iter().enumerate() {
+            for other in guardians.iter().skip(i + 1) {
+                if other == guardian {
+                    return Err(Error::DuplicateGuardian);
+                }
+            }
+        }
+
+        let guardian_set = GuardianSet { guardians, threshold, delay };
+        env.storage().persistent().set(&DataKey::Guardians(did.clone()), &guardian_set);
+        env.storage().persistent().remove(&DataKey::Recovery(did.clone()));
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("grd_set"), did),
+            GuardiansSetEvent {
+                event_version: EVENT_VERSION,
+                guardian_set,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Returns the recovery guardians of a DID, if any.
+    pub fn get_guardians(env: Env, did: String) -> Option<GuardianSet> {
+        env.storage().persistent().get(&DataKey::Guardians(did))
+    }
+
+    /// Approves the recovery of a DID to a new controller set.
+    /// The first approval starts a recovery; further guardians must approve the same set.
+    /// Once `threshold` guardians have approved, the recovery can be executed after the delay.
+    pub fn approve_recovery(env: Env, did: String, guardian: Address, new_controllers: Vec<Address>, new_threshold: u32) -> Result<(), Error> {
+        guardian.require_auth();
+
+        let guardian_set: GuardianSet = match env.storage().persistent().get(&DataKey::Guardians(did.clone())) {
+            Some(guardian_set) => guardian_set,
+            None => return Err(Error::NoGuardians),
+        };
+        if !guardian_set.guardians.contains(&guardian) {
+            return Err(Error::NotAGuardian);
+        }
+
+        Self::require_not_terminated(&env, &did)?;
+
+        let new_controller_set = ControllerSet { controllers: new_controllers, threshold: new_threshold };
+        Self::validate_controller_set(&new_controller_set)?;
+
+        let recovery_key = DataKey::Recovery(did.clone());
+        let now = env.ledger().timestamp();
+        let mut recovery = match env.storage().persistent().get::<_, RecoveryRequest>(&recovery_key) {
+            Some(recovery) => {
+                if recovery.new_controller_set != new_controller_set {
+                    return Err(Error::RecoveryConflict);
+                }
+                if recovery.approvals.contains(&guardian) {
+                    return Err(Error::GuardianAlreadyApproved);
+                }
+                recovery
+            }
+            None => {
+                // Emit event
+                env.events().publish(
+                    (symbol_short!("rec_init"), did.clone()),
+                    RecoveryInitiatedEvent {
+                        event_version: EVENT_VERSION,
+                        guardian: guardian.clone(),
+                        new_controller_set: new_controller_set.clone(),
+                        initiated_at: now,
+                    }
+                );
+                RecoveryRequest {
+                    new_controller_set,
+                    approvals: Vec::new(&env),
+                    initiated_at: now,
+                    executable_at: 0,
+                }
+            }
+        };
+
+        recovery.approvals.push_back(guardian.clone());
+        env.events().publish(
+            (symbol_short!("rec_appr"), did.clone()),
+            RecoveryApprovedEvent {
+                event_version: EVENT_VERSION,
+                guardian,
+            }
+        );
+
+        if recovery.executable_at == 0 && recovery.approvals.len() >= guardian_set.threshold {
+            recovery.executable_at = now.saturating_add(guardian_set.delay);
+            env.events().publish(
+                (symbol_short!("rec_ready"), did.clone()),
+                RecoveryReadyEvent {
+                    event_version: EVENT_VERSION,
+                    executable_at: recovery.executable_at,
+                }
+            );
+        }
+
+        env.storage().persistent().set(&recovery_key, &recovery);
+        Self::extend_ttl_for(&env, &did);
+        Ok(())
+    }
+
+    /// Cancels a recovery in progress, e.g. one started by compromised guardians.
+    /// `signers` must contain at least `threshold` of the DID's current controllers.
+    pub fn cancel_recovery(env: Env, did: String, signers: Vec<Address>) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+
+        let recovery_key = DataKey::Recovery(did.clone());
+        if !env.storage().persistent().has(&recovery_key) {
+            return Err(Error::NoRecoveryInProgress);
+        }
+        env.storage().persistent().remove(&recovery_key);
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("rec_cncl"), did),
+            RecoveryCancelledEvent {
+                event_version: EVENT_VERSION,
+                signers,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Executes an approved recovery once its delay has passed, replacing the DID's
+    /// controllers. Any pending transfer and any pre-rotation commitment are cleared,
+    /// since they were made with the lost keys. Anyone may call this.
+    pub fn execute_recovery(env: Env, did: String) -> Result<(), Error> {
+        let controller_key = DataKey::Controller(did.clone());
+        if !env.storage().persistent().has(&controller_key) {
+            return Err(Error::DidNotFound);
+        }
+
+        let recovery_key = DataKey::Recovery(did.clone());
+        let recovery: RecoveryRequest = match env.storage().persistent().get(&recovery_key) {
+            Some(recovery) => recovery,
+            None => return Err(Error::NoRecoveryInProgress),
+        };
+        if recovery.executable_at == 0 || env.ledger().timestamp() < recovery.executable_at {
+            return Err(Error::RecoveryNotExecutable);
+        }
+
+        Self::require_not_terminated(&env, &did)?;
+
+        let old_controller_set: ControllerSet = env.storage().persistent().get(&controller_key).unwrap();
+        env.storage().persistent().remove(&recovery_key);
+        env.storage().persistent().remove(&DataKey::PendingController(did.clone()));
+        env.storage().persistent().remove(&DataKey::NextControllerCommitment(did.clone()));
+        Self::set_controllers(&env, &did, old_controller_set.clone(), recovery.new_controller_set.clone(), symbol_short!("recovery"));
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("rec_done"), did),
+            RecoveryExecutedEvent {
+                event_version: EVENT_VERSION,
+                previous_controller_set: old_controller_set,
+                controller_set: recovery.new_controller_set,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Returns the recovery in progress for a DID, if any.
+    pub fn get_recovery(env: Env, did: String) -> Option<RecoveryRequest> {
+        env.storage().persistent().get(&DataKey::Recovery(did))
+    }
+
+    /// Authorizes `delegate` to act for the DID for `delegate_type` during the next
+    /// `validity` seconds, without giving it control of the DID.
+    /// Adding a delegate that already exists for the same type renews its validity window.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn add_delegate(env: Env, did: String, signers: Vec<Address>, delegate: Address, delegate_type: Symbol, validity: u64) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+        if validity == 0 {
+            return Err(Error::InvalidDelegateValidity);
+        }
+
+        // Drop expired entries and any previous entry for the same delegation
+        let now = env.ledger().timestamp();
+        let mut delegates = Vec::new(&env);
+        for entry in Self::active_delegates(&env, &did).iter() {
+            if entry.delegate != delegate || entry.delegate_type != delegate_type {
+                delegates.push_back(entry);
+            }
+        }
+        if delegates.len() >= MAX_DELEGATES {
+            return Err(Error::TooManyEntries);
+        }
+
+        let entry = Delegate {
+            delegate,
+            delegate_type,
+            valid_from: now,
+            valid_to: now.saturating_add(validity),
+        };
+        delegates.push_back(entry.clone());
+        env.storage().persistent().set(&DataKey::Delegates(did.clone()), &delegates);
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("dlg_add"), did),
+            DelegateAddedEvent {
+                event_version: EVENT_VERSION,
+                delegate: entry,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Revokes a delegation before it expires.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn revoke_delegate(env: Env, did: String, signers: Vec<Address>, delegate: Address, delegate_type: Symbol) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+
+        let mut found = false;
+        let mut delegates = Vec::new(&env);
+        for entry in Self::active_delegates(&env, &did).iter() {
+            if entry.delegate == delegate && entry.delegate_type == delegate_type {
+                found = true;
+            } else {
+                delegates.push_back(entry);
+            }
+        }
+        if !found {
+            return Err(Error::DelegateNotFound);
+        }
+
+        env.storage().persistent().set(&DataKey::Delegates(did.clone()), &delegates);
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("dlg_rev"), did),
+            DelegateRevokedEvent {
+                event_version: EVENT_VERSION,
+                delegate,
+                delegate_type,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Checks whether `delegate` is currently authorized to act for the DID for `delegate_type`.
+    /// Delegations are only valid while the DID is active: never for a revoked, suspended
+    /// or deactivated DID.
+    pub fn valid_delegate(env: Env, did: String, delegate_type: Symbol, delegate: Address) -> bool {
+        let status: Option<DidStatus> = env.storage().persistent().get(&DataKey::Status(did.clone()));
+        if status != Some(DidStatus::Active) {
+            return false;
+        }
+
+        Self::active_delegates(&env, &did)
+            .iter()
+            .any(|entry| entry.delegate == delegate && entry.delegate_type == delegate_type)
+    }
+
+    /// Links a Stellar account to the DID, attesting that both belong to the same subject.
+    /// The account must authorize the call to consent, and can belong to one DID only.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn link_account(env: Env, did: String, signers: Vec<Address>, account: Address) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::require_active(&env, &did)?;
+        account.require_auth();
+
+        let account_key = DataKey::AccountDid(account.clone());
+        if env.storage().persistent().has(&account_key) {
+            return Err(Error::AccountAlreadyLinked);
+        }
+        let accounts_key = DataKey::LinkedAccounts(did.clone());
+        let mut accounts: Vec<Address> = env.storage().persistent().get(&accounts_key).unwrap_or(Vec::new(&env));
+        if accounts.len() >= MAX_LINKED_ACCOUNTS {
+            return Err(Error::TooManyEntries);
+        }
+
+        accounts.push_back(account.clone());
+        env.storage().persistent().set(&accounts_key, &accounts);
+        env.storage().persistent().set(&account_key, &did);
+        Self::extend_ttl_for(&env, &did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("acct_link"), did),
+            AccountLinkEvent {
+                event_version: EVENT_VERSION,
+                account,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Unlinks a Stellar account from the DID.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn unlink_account(env: Env, did: String, signers: Vec<Address>, account: Address) -> Result<(), Error> {
+        Self::require_controller_auth(&env, &did, &signers)?;
+        Self::remove_linked_account(&env, &did, &account)
+    }
+
+    /// Lets a linked account withdraw its consent and unlink itself from its DID.
+    pub fn release_account(env: Env, account: Address) -> Result<(), Error> {
+        account.require_auth();
+
+        let did: String = match env.storage().persistent().get(&DataKey::AccountDid(account.clone())) {
+            Some(did) => did,
+            None => return Err(Error::AccountNotLinked),
+        };
+        Self::remove_linked_account(&env, &did, &account)
+    }
+
+    /// Returns the Stellar accounts linked to a DID.
+    pub fn get_linked_accounts(env: Env, did: String) -> Vec<Address> {
+        env.storage().persistent().get(&DataKey::LinkedAccounts(did)).unwrap_or(Vec::new(&env))
+    }
+
+    /// Returns the DID a Stellar account is linked to, if any.
+    /// Callers should check the DID's status, since links survive revocation.
+    pub fn get_account_did(env: Env, account: Address) -> Option<String> {
+        env.storage().persistent().get(&DataKey::AccountDid(account))
+    }
+
+    /// Lists the live DIDs an address is a controller of, `limit` at a time starting at
+    /// `cursor` (0 for the first page). DIDs are added to the index when an address becomes
+    /// a controller and removed when it stops being one or the DID is revoked or deactivated.
+    /// A removal moves the last DID of the index into the freed position, so a listing that
+    /// spans a removal may miss that DID; start again from 0 to get a consistent view.
+    /// Fails with `InvalidPageSize` unless `limit` is between 1 and `MAX_PAGE_SIZE`.
+    pub fn get_controlled_dids(env: Env, controller: Address, cursor: u32, limit: u32) -> Result<ControlledDids, Error> {
+        if limit == 0 || limit > MAX_PAGE_SIZE {
+            return Err(Error::InvalidPageSize);
+        }
+
+        let count = Self::get_controlled_did_count(env.clone(), controller.clone());
+        let end = count.min(cursor.saturating_add(limit));
+        let mut dids = Vec::new(&env);
+        for position in cursor..end {
+            dids.push_back(env.storage().persistent().get(&DataKey::ControllerDid(controller.clone(), position)).unwrap());
+        }
+
+        let next_cursor = if end < count { Some(end) } else { None };
+        Ok(ControlledDids { dids, next_cursor })
+    }
+
+    /// Returns the number of live DIDs an address is a controller of.
+    pub fn get_controlled_did_count(env: Env, controller: Address) -> u32 {
+        env.storage().persistent().get(&DataKey::ControllerDidCount(controller)).unwrap_or(0)
+    }
+
+    /// Extends the TTL of the storage entries belonging to a DID, together with up to
+    /// `limit` historical document versions starting at `from_version` (1 for the first
+    /// page). Returns the version to pass as `from_version` for the next page, or None once
+    /// every historical version has been extended.
+    /// Anyone may call this (and pay for it) to keep a DID from being archived.
+    /// Fails with `InvalidPageSize` unless `limit` is between 1 and `MAX_TTL_VERSION_PAGE`.
+    pub fn extend_did_ttl(env: Env, did: String, from_version: u32, limit: u32) -> Result<Option<u32>, Error> {
+        if limit == 0 || limit > MAX_TTL_VERSION_PAGE {
+            return Err(Error::InvalidPageSize);
+        }
+        if !env.storage().persistent().has(&DataKey::Controller(did.clone())) {
+            return Err(Error::DidNotFound);
+        }
+        Self::extend_ttl_for(&env, &did);
+
+        // The current version was extended above, so only older ones are paged through
+        let current: u32 = env.storage().persistent().get(&DataKey::Version(did.clone())).unwrap();
+        let start = from_version.max(1);
+        let end = current.min(start.saturating_add(limit));
+        let anchored = env.storage().persistent().has(&DataKey::Anchor(did.clone()));
+        for version_id in start..end {
+            env.storage().persistent().extend_ttl(&DataKey::Document(did.clone(), version_id), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+            if anchored {
+                env.storage().persistent().extend_ttl(&DataKey::AnchorVersion(did.clone(), version_id), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+            }
+        }
+
+        Ok(if end < current { Some(end) } else { None })
+    }
+
+    /// Returns the ed25519 key of the verification method `key_id`, which must be designated
+    /// for `capabilityInvocation` in `document`.
+    fn signing_key(document: &DidDocument, key_id: &String) -> Result<BytesN<32>, Error> {
+        if !document.capability_invocation.contains(key_id) {
+            return Err(Error::KeyNotAuthorized);
+        }
+        let method = match document.verification_method.iter().find(|method| method.id == *key_id) {
+            Some(method) => method,
+            None => return Err(Error::VerificationMethodNotFound),
+        };
+        match BytesN::<32>::try_from(method.public_key) {
+            Ok(public_key) => Ok(public_key),
+            Err(_) => Err(Error::KeyNotAuthorized),
+        }
+    }
+
+    /// Checks a signed operation's expiry and nonce, verifies its signature (panicking if
+    /// it is invalid) and consumes the nonce.
+    fn verify_signed_operation(env: &Env, did: &String, public_key: &BytesN<32>, operation: Symbol, args_hash: BytesN<32>, expiry: u64, signature: &BytesN<64>) -> Result<(), Error> {
+        if env.ledger().timestamp() > expiry {
+            return Err(Error::SignatureExpired);
+        }
+
+        let nonce_key = DataKey::Nonce(did.clone());
+        let nonce: u64 = env.storage().persistent().get(&nonce_key).unwrap_or(0);
+        let message = SignedOperation {
+            registry: env.current_contract_address(),
+            did: did.clone(),
+            operation,
+            nonce,
+            expiry,
+            args_hash,
+        };
+        env.crypto().ed25519_verify(public_key, &message.to_xdr(env), signature);
+
+        env.storage().persistent().set(&nonce_key, &(nonce + 1));
+        Ok(())
+    }
+
+    /// Removes a linked account and its reverse lookup entry.
+    fn remove_linked_account(env: &Env, did: &String, account: &Address) -> Result<(), Error> {
+        let accounts_key = DataKey::LinkedAccounts(did.clone());
+        let mut accounts: Vec<Address> = env.storage().persistent().get(&accounts_key).unwrap_or(Vec::new(env));
+        let index = match accounts.iter().position(|linked| linked == *account) {
+            Some(index) => index as u32,
+            None => return Err(Error::AccountNotLinked),
+        };
+
+        accounts.remove(index);
+        env.storage().persistent().set(&accounts_key, &accounts);
+        env.storage().persistent().remove(&DataKey::AccountDid(account.clone()));
+        Self::extend_ttl_for(env, did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("acct_unl"), did.clone()),
+            AccountLinkEvent {
+                event_version: EVENT_VERSION,
+                account: account.clone(),
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Moves a DID from the controller index of every address in `old_controllers` that is
+    /// not in `new_controllers` to that of every address in `new_controllers` that was not
+    /// in `old_controllers`.
+    fn update_controller_index(env: &Env, did: &String, old_controllers: &Vec<Address>, new_controllers: &Vec<Address>) {
+        let persistent = env.storage().persistent();
+        for controller in old_controllers.iter() {
+            if new_controllers.contains(&controller) {
+                continue;
+            }
+
+            let index_key = DataKey::ControllerDidIndex(controller.clone(), did.clone());
+            let position: u32 = match persistent.get(&index_key) {
+                Some(position) => position,
+                None => continue,
+            };
+            let count_key = DataKey::ControllerDidCount(controller.clone());
+            let last: u32 = persistent.get::<_, u32>(&count_key).unwrap() - 1;
+            if position != last {
+                // Swap the last DID into the freed position
+                let last_did: String = persistent.get(&DataKey::ControllerDid(controller.clone(), last)).unwrap();
+                persistent.set(&DataKey::ControllerDid(controller.clone(), position), &last_did);
+                persistent.set(&DataKey::ControllerDidIndex(controller.clone(), last_did), &position);
+            }
+            persistent.remove(&DataKey::ControllerDid(controller.clone(), last));
+            persistent.remove(&index_key);
+            if last == 0 {
+                persistent.remove(&count_key);
+            } else {
+                persistent.set(&count_key, &last);
+            }
+        }
+
+        for controller in new_controllers.iter() {
+            if old_controllers.contains(&controller) {
+                continue;
+            }
+
+            let count_key = DataKey::ControllerDidCount(controller.clone());
+            let count: u32 = persistent.get(&count_key).unwrap_or(0);
+            persistent.set(&DataKey::ControllerDid(controller.clone(), count), did);
+            persistent.set(&DataKey::ControllerDidIndex(controller.clone(), did.clone()), &count);
+            persistent.set(&count_key, &(count + 1));
+        }
+    }
+
+    /// Returns the deposit held for a terminated DID to its first controller.
+    fn refund_deposit(env: &Env, did: &String, controller_set: &ControllerSet) {
+        let deposit_key = DataKey::Deposit(did.clone());
+        let deposit: Deposit = match env.storage().persistent().get(&deposit_key) {
+            Some(deposit) => deposit,
+            None => return,
+        };
+        let recipient = controller_set.controllers.get(0).unwrap();
+        token::Client::new(env, &deposit.token).transfer(&env.current_contract_address(), &recipient, &deposit.amount);
+        env.storage().persistent().remove(&deposit_key);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("dep_ref"), did.clone()),
+            (recipient, deposit)
+        );
+    }
+
+    /// Moves a suspended DID back to `Active`.
+    fn reactivate(env: &Env, did: &String) -> Result<(), Error> {
+        let status: DidStatus = env.storage().persistent().get(&DataKey::Status(did.clone())).unwrap();
+        if status != DidStatus::Suspended {
+            return Err(Error::DidNotSuspended);
+        }
+
+        Self::set_status(env, did, DidStatus::Active, 0);
+        Self::extend_ttl_for(env, did);
+        Ok(())
+    }
+
+    /// Records a status transition, with the current ledger and time, in the DID's history.
+    fn set_status(env: &Env, did: &String, status: DidStatus, reason: u32) {
+        let history_key = DataKey::StatusHistory(did.clone());
+        let mut history: Vec<StatusChange> = env.storage().persistent().get(&history_key).unwrap_or(Vec::new(env));
+        history.push_back(StatusChange {
+            status,
+            reason,
+            ledger_sequence: env.ledger().sequence(),
+            timestamp: env.ledger().timestamp(),
+        });
+
+        env.storage().persistent().set(&DataKey::Status(did.clone()), &status);
+        env.storage().persistent().set(&history_key, &history);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_stat"), did.clone()),
+            StatusChangedEvent {
+                event_version: EVENT_VERSION,
+                status,
+                reason,
+                ledger_sequence: env.ledger().sequence(),
+                timestamp: env.ledger().timestamp(),
+            }
+        );
+    }
+
+    /// Replaces the controllers of a DID and moves it between their controller indexes.
+    fn set_controllers(env: &Env, did: &String, old_controller_set: ControllerSet, new_controller_set: ControllerSet, cause: Symbol) {
+        env.storage().persistent().set(&DataKey::Controller(did.clone()), &new_controller_set);
+        Self::update_controller_index(env, did, &old_controller_set.controllers, &new_controller_set.controllers);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("ctl_chg"), did.clone()),
+            ControllerChangedEvent {
+                event_version: EVENT_VERSION,
+                previous_controller_set: old_controller_set,
+                controller_set: new_controller_set,
+                cause,
+            }
+        );
+    }
+
+    /// Fails with `DidNotActive` unless the DID is `Active`.
+    fn require_active(env: &Env, did: &String) -> Result<(), Error> {
+        let status: DidStatus = env.storage().persistent().get(&DataKey::Status(did.clone())).unwrap();
+        if status != DidStatus::Active {
+            return Err(Error::DidNotActive);
+        }
+
+        Ok(())
+    }
+
+    /// Fails with `DidNotFound` if the DID is not registered, and with `NotEnoughSignatures`
+    /// if it has more than one controller, whose threshold a single signed operation cannot
+    /// meet.
+    fn require_single_controller(env: &Env, did: &String) -> Result<(), Error> {
+        let controller_set: ControllerSet = env.storage().persistent().get(&DataKey::Controller(did.clone())).ok_or(Error::DidNotFound)?;
+        if controller_set.controllers.len() > 1 {
+            return Err(Error::NotEnoughSignatures);
+        }
+
+        Ok(())
+    }
+
+    /// Fails with `DidTerminated` if the DID has been permanently revoked or deactivated.
+    fn require_not_terminated(env: &Env, did: &String) -> Result<(), Error> {
+        let status: DidStatus = env.storage().persistent().get(&DataKey::Status(did.clone())).unwrap();
+        if status == DidStatus::Revoked || status == DidStatus::Deactivated {
+            return Err(Error::DidTerminated);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the delegates of a DID whose validity window includes the current ledger time.
+    fn active_delegates(env: &Env, did: &String) -> Vec<Delegate> {
+        let now = env.ledger().timestamp();
+        let delegates: Vec<Delegate> = env.storage().persistent().get(&DataKey::Delegates(did.clone())).unwrap_or(Vec::new(env));
+
+        let mut active = Vec::new(env);
+        for entry in delegates.iter() {
+            if entry.valid_from <= now && now < entry.valid_to {
+                active.push_back(entry);
+            }
+        }
+        active
+    }
+
+    /// Returns the version of a DID's document that was current at `timestamp`, if any.
+    fn document_at(env: &Env, did: &String, timestamp: u64) -> Option<DocumentVersion> {
+        // Versions are written in ledger order, so their timestamps are non-decreasing.
+        let mut low: u32 = 1;
+        let mut high: u32 = env.storage().persistent().get(&DataKey::Version(did.clone())).unwrap();
+        let mut found: Option<DocumentVersion> = None;
+        while low <= high {
+            let mid = low + (high - low) / 2;
+            let version: DocumentVersion = env.storage().persistent().get(&DataKey::Document(did.clone(), mid)).unwrap();
+            if version.timestamp <= timestamp {
+                found = Some(version);
+                low = mid + 1;
+            } else {
+                high = mid - 1;
+            }
+        }
+        found
+    }
+
+    /// Returns the status a DID had at `timestamp`, if it existed then.
+    fn status_at(env: &Env, did: &String, timestamp: u64) -> Option<DidStatus> {
+        let history: Vec<StatusChange> = env.storage().persistent().get(&DataKey::StatusHistory(did.clone())).unwrap();
+        let mut status = None;
+        for change in history.iter() {
+            if change.timestamp > timestamp {
+                break;
+            }
+            status = Some(change.status);
+        }
+        status
+    }
+
+    /// Returns the current version of a DID's document.
+    fn current_document(env: &Env, did: &String) -> DidDocument {
+        let version_id: u32 = env.storage().persistent().get(&DataKey::Version(did.clone())).unwrap();
+        let version: DocumentVersion = env.storage().persistent().get(&DataKey::Document(did.clone(), version_id)).unwrap();
+        version.document
+    }
+
+    /// Validates a changed document and stores it as the next version.
+    /// Fails with `DocumentAnchored` if the DID's document is kept off-chain.
+    fn write_document_change(env: &Env, did: &String, document: DidDocument) -> Result<(), Error> {
+        if env.storage().persistent().has(&DataKey::Anchor(did.clone())) {
+            return Err(Error::DocumentAnchored);
+        }
+        Self::validate_document(&document)?;
+        Self::write_document_version(env, did, document);
+        Self::extend_ttl_for(env, did);
+        Ok(())
+    }
+
+    /// Stores `document` as the next version of the DID's document.
+    fn write_document_version(env: &Env, did: &String, document: DidDocument) {
+        let version_key = DataKey::Version(did.clone());
+        let version_id: u32 = env.storage().persistent().get(&version_key).unwrap_or(0) + 1;
+
+        let document_hash = env.crypto().sha256(&document.clone().to_xdr(env)).to_bytes();
+        let version = DocumentVersion {
+            version_id,
+            document,
+            ledger_sequence: env.ledger().sequence(),
+            timestamp: env.ledger().timestamp(),
+        };
+        env.storage().persistent().set(&DataKey::Document(did.clone(), version_id), &version);
+        env.storage().persistent().set(&version_key, &version_id);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_upd"), did.clone()),
+            DocumentUpdatedEvent {
+                event_version: EVENT_VERSION,
+                version_id,
+                document: version.document,
+                document_hash,
+                ledger_sequence: version.ledger_sequence,
+                timestamp: version.timestamp,
+            }
+        );
+    }
+
+    /// Makes `anchor` the current anchor of a DID and records it for the current document
+    /// version.
+    fn set_anchor(env: &Env, did: &String, anchor: DocumentAnchor) {
+        let version_id: u32 = env.storage().persistent().get(&DataKey::Version(did.clone())).unwrap();
+        env.storage().persistent().set(&DataKey::AnchorVersion(did.clone(), version_id), &anchor);
+        env.storage().persistent().set(&DataKey::Anchor(did.clone()), &anchor);
+        Self::extend_ttl_for(env, did);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("anc_set"), did.clone()),
+            AnchorSetEvent {
+                event_version: EVENT_VERSION,
+                anchor,
+            }
+        );
+    }
+
+    /// Bumps the persistent entries of a DID, along with the contract instance.
+    /// Only the current document version is bumped here; older versions are kept alive
+    /// by `extend_did_ttl`.
+    fn extend_ttl_for(env: &Env, did: &String) {
+        let persistent = env.storage().persistent();
+        let version_key = DataKey::Version(did.clone());
+        let version_id: u32 = persistent.get(&version_key).unwrap();
+        persistent.extend_ttl(&DataKey::Controller(did.clone()), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        persistent.extend_ttl(&version_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        persistent.extend_ttl(&DataKey::Document(did.clone(), version_id), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        persistent.extend_ttl(&DataKey::Status(did.clone()), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        persistent.extend_ttl(&DataKey::StatusHistory(did.clone()), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        let pending_key = DataKey::PendingController(did.clone());
+        if persistent.has(&pending_key) {
+            persistent.extend_ttl(&pending_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        }
+        let delegates_key = DataKey::Delegates(did.clone());
+        if persistent.has(&delegates_key) {
+            persistent.extend_ttl(&delegates_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        }
+        let commitment_key = DataKey::NextControllerCommitment(did.clone());
+        if persistent.has(&commitment_key) {
+            persistent.extend_ttl(&commitment_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        }
+        let guardians_key = DataKey::Guardians(did.clone());
+        if persistent.has(&guardians_key) {
+            persistent.extend_ttl(&guardians_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        }
+        let recovery_key = DataKey::Recovery(did.clone());
+        if persistent.has(&recovery_key) {
+            persistent.extend_ttl(&recovery_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        }
+        let nonce_key = DataKey::Nonce(did.clone());
+        if persistent.has(&nonce_key) {
+            persistent.extend_ttl(&nonce_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        }
+        let accounts_key = DataKey::LinkedAccounts(did.clone());
+        if let Some(accounts) = persistent.get::<_, Vec<Address>>(&accounts_key) {
+            persistent.extend_ttl(&accounts_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+            for account in accounts.iter() {
+                persistent.extend_ttl(&DataKey::AccountDid(account), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+            }
+        }
+        let deposit_key = DataKey::Deposit(did.clone());
+        if persistent.has(&deposit_key) {
+            persistent.extend_ttl(&deposit_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        }
+        let anchor_key = DataKey::Anchor(did.clone());
+        if persistent.has(&anchor_key) {
+            persistent.extend_ttl(&anchor_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+            persistent.extend_ttl(&DataKey::AnchorVersion(did.clone(), version_id), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        }
+        let legacy_key = DataKey::LegacyDocument(did.clone());
+        if persistent.has(&legacy_key) {
+            persistent.extend_ttl(&legacy_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+        }
+        let controller_set: ControllerSet = persistent.get(&DataKey::Controller(did.clone())).unwrap();
+        for controller in controller_set.controllers.iter() {
+            let index_key = DataKey::ControllerDidIndex(controller.clone(), did.clone());
+            if let Some(position) = persistent.get::<_, u32>(&index_key) {
+                persistent.extend_ttl(&index_key, DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+                persistent.extend_ttl(&DataKey::ControllerDid(controller.clone(), position), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+                persistent.extend_ttl(&DataKey::ControllerDidCount(controller), DID_TTL_THRESHOLD, DID_TTL_EXTEND_TO);
+            }
+        }
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+    }
+
+    /// Checks that `signers` are distinct controllers of the DID, that there are at least
+    /// `threshold` of them, and requires each one's authorization.
+    /// Returns the DID's controller set.
+    fn require_controller_auth(env: &Env, did: &String, signers: &Vec<Address>) -> Result<ControllerSet, Error> {
+        let controller_key = DataKey::Controller(did.clone());
+        let controller_set: ControllerSet = match env.storage().persistent().get(&controller_key) {
+            Some(controller_set) => controller_set,
+            None => return Err(Error::DidNotFound),
+        };
+
+        for (i, signer) in signers.iter().enumerate() {
+            if !controller_set.controllers.contains(&signer) {
+                return Err(Error::NotAController);
+            }
+            for other in signers.iter().skip(i + 1) {
+                if other == signer {
+                    return Err(Error::DuplicateSigner);
+                }
+            }
+        }
+        if signers.len() < controller_set.threshold {
+            return Err(Error::NotEnoughSignatures);
+        }
+
+        for signer in signers.iter() {
+            signer.require_auth();
+        }
+
+        Ok(controller_set)
+    }
+
+    /// Stores a newly registered DID. Callers are responsible for authorization and for
+    /// validating the document.
+    fn register(env: &Env, payer: &Address, controller_set: ControllerSet, did: String, document: DidDocument) -> Result<(), Error> {
+        if Self::is_registered(env, &did) {
+            return Err(Error::DidAlreadyRegistered);
+        }
+        let controller_key = DataKey::Controller(did.clone());
+
+        // Take the registration deposit, if one is configured
+        if let Some(deposit) = env.storage().instance().get::<_, Deposit>(&DataKey::DepositConfig) {
+            token::Client::new(env, &deposit.token).transfer(payer, &env.current_contract_address(), &deposit.amount);
+            env.storage().persistent().set(&DataKey::Deposit(did.clone()), &deposit);
+        }
+
+        env.storage().persistent().set(&controller_key, &controller_set);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_reg"), did.clone()),
+            DidRegisteredEvent {
+                event_version: EVENT_VERSION,
+                controller_set: controller_set.clone(),
+            }
+        );
+
+        Self::write_document_version(env, &did, document);
+        Self::set_status(env, &did, DidStatus::Active, 0);
+        Self::update_controller_index(env, &did, &Vec::new(env), &controller_set.controllers);
+        Self::extend_ttl_for(env, &did);
+
+        Ok(())
+    }
+
+    /// Whether a DID has been registered. A DID still in instance storage was registered
+    /// under schema version 1 and is waiting for `migrate`.
+    fn is_registered(env: &Env, did: &String) -> bool {
+        let controller_key = DataKey::Controller(did.clone());
+        env.storage().persistent().has(&controller_key) || env.storage().instance().has(&controller_key)
+    }
+
+    /// Moves a DID registered under schema version 1 out of instance storage. Its controller
+    /// becomes a 1-of-1 controller set and its status is kept; its untyped document is
+    /// converted to the version 1 `DidDocument`, unless `document` replaces it, and kept as
+    /// its legacy document. The same events as for a registration are published, so
+    /// indexers pick the DID up.
+    /// Nothing is written if the document does not convert or validate. Returns whether
+    /// the DID had version 1 entries to migrate.
+    fn migrate_did_to_v2(env: &Env, did: &String, document: Option<DidDocument>) -> Result<bool, Error> {
+        let instance = env.storage().instance();
+        let controller_key = DataKey::Controller(did.clone());
+        let controller: Address = match instance.get(&controller_key) {
+            Some(controller) => controller,
+            None => return Ok(false),
+        };
+        // Version 1 keyed the document by the DID alone; `DataKey::Document` adds the version
+        let legacy_document_key = (2u32, did.clone());
+        let legacy_document: Map<Symbol, soroban_sdk::Val> = instance.get(&legacy_document_key).unwrap_or(Map::new(env));
+        let document = match document {
+            Some(document) => document,
+            None => Self::convert_legacy_document(env, &legacy_document)?,
+        };
+        Self::validate_document(&document)?;
+        let status: DidStatus = instance.get(&DataKey::Status(did.clone())).unwrap_or(DidStatus::Active);
+        instance.remove(&controller_key);
+        instance.remove(&legacy_document_key);
+        instance.remove(&DataKey::Status(did.clone()));
+
+        let mut controllers = Vec::new(env);
+        controllers.push_back(controller);
+        let controller_set = ControllerSet { controllers, threshold: 1 };
+        env.storage().persistent().set(&controller_key, &controller_set);
+        env.storage().persistent().set(&DataKey::LegacyDocument(did.clone()), &legacy_document);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("did_reg"), did.clone()),
+            DidRegisteredEvent {
+                event_version: EVENT_VERSION,
+                controller_set: controller_set.clone(),
+            }
+        );
+
+        Self::write_document_version(env, did, document);
+        Self::set_status(env, did, status, 0);
+        // Revoked DIDs are not in their controllers' index
+        if status == DidStatus::Active {
+            Self::update_controller_index(env, did, &Vec::new(env), &controller_set.controllers);
+        }
+        Self::extend_ttl_for(env, did);
+
+        Ok(true)
+    }
+
+    /// Converts a schema version 1 document to a `DidDocument`. Version 1 accepted any map;
+    /// it converts only if every key names a `DidDocument` field and holds a value of that
+    /// field's type, so nothing in it is dropped.
+    fn convert_legacy_document(env: &Env, legacy_document: &Map<Symbol, soroban_sdk::Val>) -> Result<DidDocument, Error> {
+        let mut document = DidDocument {
+            verification_method: Vec::new(env),
+            service: Vec::new(env),
+            also_known_as: Vec::new(env),
+            authentication: Vec::new(env),
+            assertion_method: Vec::new(env),
+            key_agreement: Vec::new(env),
+            capability_invocation: Vec::new(env),
+            capability_delegation: Vec::new(env),
+        };
+        for (key, value) in legacy_document.iter() {
+            if key == Symbol::new(env, "verification_method") {
+                document.verification_method = Self::convert_legacy_vec(env, &value)?;
+            } else if key == Symbol::new(env, "service") {
+                document.service = Self::convert_legacy_vec(env, &value)?;
+            } else if key == Symbol::new(env, "also_known_as") {
+                document.also_known_as = Self::convert_legacy_vec(env, &value)?;
+            } else if key == Symbol::new(env, "authentication") {
+                document.authentication = Self::convert_legacy_vec(env, &value)?;
+            } else if key == Symbol::new(env, "assertion_method") {
+                document.assertion_method = Self::convert_legacy_vec(env, &value)?;
+            } else if key == Symbol::new(env, "key_agreement") {
+                document.key_agreement = Self::convert_legacy_vec(env, &value)?;
+            } else if key == Symbol::new(env, "capability_invocation") {
+                document.capability_invocation = Self::convert_legacy_vec(env, &value)?;
+            } else if key == Symbol::new(env, "capability_delegation") {
+                document.capability_delegation = Self::convert_legacy_vec(env, &value)?;
+            } else {
+                return Err(Error::InvalidDocument);
+            }
+        }
+        Ok(document)
+    }
+
+    /// Converts a list from a schema version 1 document, checking every element, since a
+    /// `Vec` only checks its elements when they are read.
+    fn convert_legacy_vec<T>(env: &Env, value: &soroban_sdk::Val) -> Result<Vec<T>, Error>
+    where
+        T: TryFromVal<Env, soroban_sdk::Val> + soroban_sdk::IntoVal<Env, soroban_sdk::Val>,
+    {
+        let values = Vec::<soroban_sdk::Val>::try_from_val(env, value).map_err(|_| Error::InvalidDocument)?;
+        let mut items = Vec::new(env);
+        for value in values.iter() {
+            items.push_back(T::try_from_val(env, &value).map_err(|_| Error::InvalidDocument)?);
+        }
+        Ok(items)
+    }
+
+    /// Checks a `register_dids` entry the way `register_multi_controller_did` checks its
+    /// arguments.
+    fn validate_registration(registration: &DidRegistration) -> Result<(), Error> {
+        Self::validate_did(&registration.did)?;
+        Self::validate_controller_set(&ControllerSet {
+            controllers: registration.controllers.clone(),
+            threshold: registration.threshold,
+        })?;
+        Self::validate_document(&registration.document)
+    }
+
+    /// What registering a `register_dids` entry adds to the call: the ledger entries it
+    /// writes and the bytes of the events it publishes. A controller's DID count and
+    /// authorization nonce are written once per batch, so they only count for controllers not
+    /// yet in `signers`; likewise the deposit payer's balance for payers not yet in `payers`,
+    /// and the registry's own balance for the first deposit of the batch.
+    fn registration_cost(env: &Env, registration: &DidRegistration, signers: &Vec<Address>, payers: &Vec<Address>, deposit: bool) -> (u32, u32) {
+        let mut writes = REGISTRATION_WRITES;
+        for controller in registration.controllers.iter() {
+            writes += CONTROLLER_INDEX_WRITES;
+            if !signers.contains(&controller) {
+                writes += CONTROLLER_WRITES;
+            }
+        }
+        let did_bytes = registration.did.clone().to_xdr(env).len();
+        let mut event_bytes = registration.clone().to_xdr(env).len() + 2 * did_bytes + REGISTRATION_EVENT_BYTES;
+
+        if deposit {
+            writes += 1;
+            if !payers.contains(registration.controllers.get(0).unwrap()) {
+                writes += 1;
+            }
+            if payers.is_empty() {
+                writes += 1;
+            }
+            event_bytes += DEPOSIT_EVENT_BYTES;
+        }
+
+        (writes, event_bytes)
+    }
+
+    /// Checks that an anchor's locator is non-empty, within `MAX_LOCATOR_LEN` and uses one
+    /// of the `LOCATOR_SCHEMES`.
+    fn validate_anchor(anchor: &DocumentAnchor) -> Result<(), Error> {
+        let len = anchor.locator.len() as usize;
+        if len > MAX_LOCATOR_LEN as usize {
+            return Err(Error::InvalidAnchor);
+        }
+        let mut buf = [0u8; MAX_LOCATOR_LEN as usize];
+        anchor.locator.copy_into_slice(&mut buf[..len]);
+
+        let locator = &buf[..len];
+        for scheme in LOCATOR_SCHEMES {
+            if locator.len() > scheme.len() && locator.starts_with(scheme) {
+                return Ok(());
+            }
+        }
+        Err(Error::InvalidAnchor)
+    }
+
+    /// Checks that a free-form DID follows the `did:kosmos` grammar and is in canonical form.
+    fn validate_did(did: &String) -> Result<(), Error> {
+        let len = did.len() as usize;
+        if len > did_syntax::MAX_DID_LEN {
+            return Err(Error::InvalidDid);
+        }
+        let mut buf = [0u8; did_syntax::MAX_DID_LEN];
+        did.copy_into_slice(&mut buf[..len]);
+
+        let parsed = match did_syntax::parse(&buf[..len]) {
+            Ok(parsed) => parsed,
+            Err(DidSyntaxError::NonCanonical) => return Err(Error::NonCanonicalDid),
+            Err(_) => return Err(Error::InvalidDid),
+        };
+
+        // Ids shaped like a derived id are reserved for `register_derived_did`.
+        if parsed.id.len() == 2 * DERIVED_ID_BYTES && parsed.id.iter().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::ReservedDid);
+        }
+
+        Ok(())
+    }
+
+    /// Renders the DID derived from `input` on the current network. Networks other than the
+    /// known ones have no network segment, like mainnet, but still hash differently.
+    fn derived_did(env: &Env, input: Bytes) -> String {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        let network_id = env.ledger().network_id();
+        let mut preimage = Bytes::from(network_id.clone());
+        preimage.append(&input);
+        let hash = env.crypto().sha256(&preimage).to_array();
+
+        let mut buf = [0u8; did_syntax::MAX_DID_LEN];
+        let mut len = did_syntax::METHOD_PREFIX.len();
+        buf[..len].copy_from_slice(did_syntax::METHOD_PREFIX);
+        let network = did_syntax::Network::from_network_id(&network_id.to_array());
+        if let Some(segment) = network.and_then(|network| network.segment()) {
+            buf[len..len + segment.len()].copy_from_slice(segment);
+            buf[len + segment.len()] = b':';
+            len += segment.len() + 1;
+        }
+        for byte in &hash[..DERIVED_ID_BYTES] {
+            buf[len] = HEX[(byte >> 4) as usize];
+            buf[len + 1] = HEX[(byte & 0x0f) as usize];
+            len += 2;
+        }
+
+        String::from_bytes(env, &buf[..len])
+    }
+
+    /// Checks that a controller set is non-empty, has no duplicates and a reachable threshold.
+    fn validate_controller_set(controller_set: &ControllerSet) -> Result<(), Error> {
+        let count = controller_set.controllers.len();
+        if count == 0 || count > MAX_CONTROLLERS {
+            return Err(Error::InvalidControllerSet);
+        }
+        if controller_set.threshold == 0 || controller_set.threshold > count {
+            return Err(Error::InvalidControllerSet);
+        }
+        for (i, controller) in controller_set.controllers.iter().enumerate() {
+            for other in controller_set.controllers.iter().skip(i + 1) {
+                if other == controller {
+                    return Err(Error::InvalidControllerSet);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a DID document is well-formed before it is stored.
+    /// Every entry must carry its required fields, and ids must be unique within the document.
+    fn validate_document(document: &DidDocument) -> Result<(), Error> {
+        if document.verification_method.is_empty() {
+            return Err(Error::InvalidDocument);
+        }
+        if document.verification_method.len() > MAX_VERIFICATION_METHODS
+            || document.service.len() > MAX_SERVICES
+            || document.also_known_as.len() > MAX_ALSO_KNOWN_AS
+        {
+            return Err(Error::TooManyEntries);
+        }
+
+        for (i, method) in document.verification_method.iter().enumerate() {
+            if method.id.is_empty()
+                || method.method_type.is_empty()
+                || method.controller.is_empty()
+                || method.public_key.is_empty()
+            {
+                return Err(Error::InvalidDocument);
+            }
+            for other in document.verification_method.iter().skip(i + 1) {
+                if other.id == method.id {
+                    return Err(Error::DuplicateId);
+                }
+            }
+        }
+
+        for (i, service) in document.service.iter().enumerate() {
+            if service.id.is_empty() || service.service_type.is_empty() || service.service_endpoint.is_empty() {
+                return Err(Error::InvalidDocument);
+            }
+            for other in document.service.iter().skip(i + 1) {
+                if other.id == service.id {
+                    return Err(Error::DuplicateId);
+                }
+            }
+        }
+
+        for alias in document.also_known_as.iter() {
+            if alias.is_empty() {
+                return Err(Error::InvalidDocument);
+            }
+        }
+
+        // Relationships may only reference verification methods of this document, once each.
+        for references in [
+            &document.authentication,
+            &document.assertion_method,
+            &document.key_agreement,
+            &document.capability_invocation,
+            &document.capability_delegation,
+        ] {
+            for (i, id) in references.iter().enumerate() {
+                if !document.verification_method.iter().any(|method| method.id == id) {
+                    return Err(Error::VerificationMethodNotFound);
+                }
+                for other in references.iter().skip(i + 1) {
+                    if other == id {
+                        return Err(Error::DuplicateId);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -1,85 +1,202 @@
-#![no_std]
+extern crate std;
+
 use soroban_sdk::{
-    testutils::{Address as _, Events as _, MockAuth, MockAuthInvoke},
-    Address, Env, Map, symbol_short, String, IntoVal,
+    testutils::{storage::Persistent as _, Address as _, Events as _, Ledger as _, MockAuth, MockAuthInvoke},
+    token, vec, xdr::{ContractEventType, Limits, ToXdr, WriteXdr}, Address, Bytes, BytesN, Env, Map, symbol_short, String, Symbol, IntoVal, TryFromVal, Vec,
 };
-use crate::{DidRegistryContract, DidRegistryContractClient, DidStatus};
+use ed25519_dalek::{Signer, SigningKey};
+use crate::did_syntax::{self, DidSyntaxError, Network, ParsedDid};
+use crate::{AccountLinkEvent, AnchorSetEvent, ControllerCommittedEvent, ControllerProposedEvent, DelegateAddedEvent, DelegateRevokedEvent, GuardiansSetEvent, RecoveryApprovedEvent, RecoveryCancelledEvent, RecoveryExecutedEvent, RecoveryInitiatedEvent, RecoveryReadyEvent, RecoveryRequest, ControlledDids, ControllerChangedEvent, ControllerSet, DataKey, DidRegisteredEvent, DidRegistration, DocumentUpdatedEvent, StatusChangedEvent, Delegate, Deposit, DidDocument, DocumentAnchor, DocumentSource, Error, DidResolution, GuardianSet, StatusChange, VerificationRelationship, DocumentVersion, DidRegistryContract, DidRegistryContractClient, DidStatus, MigrationResult, Service, SignedOperation, VerificationMethod, RegistrationResult, DAY_IN_LEDGERS, DID_TTL_EXTEND_TO, DID_TTL_THRESHOLD, EVENT_VERSION, INSTANCE_TTL_EXTEND_TO, INSTANCE_TTL_THRESHOLD, MAX_BATCH_SIZE, MAX_PAGE_SIZE, MAX_TTL_VERSION_PAGE, SCHEMA_VERSION, TX_MAX_EVENTS_SIZE_BYTES, TX_MAX_WRITE_ENTRIES};
+
+fn setup_test(env: &Env) -> (Address, DidRegistryContractClient<'_>, Address) {
+    let contract_id = env.register_contract(None, DidRegistryContract);
+    let client = DidRegistryContractClient::new(env, &contract_id);
+    let admin = Address::generate(env);
+
+    client.initialize(&admin);
+
+    (contract_id, client, admin)
+}
+
+fn verification_method(env: &Env, did: &str, id: &str) -> VerificationMethod {
+    VerificationMethod {
+        id: String::from_str(env, id),
+        method_type: String::from_str(env, "Ed25519VerificationKey2020"),
+        controller: String::from_str(env, did),
+        public_key: Bytes::from_array(env, &[7u8; 32]),
+    }
+}
+
+fn service(env: &Env, id: &str, endpoint: &str) -> Service {
+    Service {
+        id: String::from_str(env, id),
+        service_type: String::from_str(env, "LinkedDomains"),
+        service_endpoint: String::from_str(env, endpoint),
+    }
+}
+
+fn document(env: &Env, did: &str) -> DidDocument {
+    DidDocument {
+        verification_method: vec![env, verification_method(env, did, "#key-1")],
+        service: Vec::new(env),
+        also_known_as: Vec::new(env),
+        authentication: Vec::new(env),
+        assertion_method: Vec::new(env),
+        key_agreement: Vec::new(env),
+        capability_invocation: Vec::new(env),
+        capability_delegation: Vec::new(env),
+    }
+}
+
+/// A document whose "#key-1" holds the public half of `signing_key`, designated for capability invocation.
+fn signer_document(env: &Env, did: &str, signing_key: &SigningKey) -> DidDocument {
+    let mut document = document(env, did);
+    let mut method = verification_method(env, did, "#key-1");
+    method.public_key = Bytes::from_array(env, &signing_key.verifying_key().to_bytes());
+    document.verification_method = vec![env, method];
+    document.capability_invocation.push_back(String::from_str(env, "#key-1"));
+    document
+}
+
+/// The registry's own code, built for `wasm32v1-none` the way it is deployed.
+fn registry_wasm() -> std::vec::Vec<u8> {
+    let status = std::process::Command::new(env!("CARGO"))
+        .args(["build", "--release", "--target", "wasm32v1-none", "--package", env!("CARGO_PKG_NAME")])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .unwrap();
+    assert!(status.success(), "building the registry WASM failed");
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../target/wasm32v1-none/release/did_registry.wasm")).unwrap()
+}
+
+/// Signs a `SignedOperation` the way an off-chain wallet would.
+#[allow(clippy::too_many_arguments)]
+fn sign_operation(env: &Env, contract_id: &Address, signing_key: &SigningKey, did: &String, operation: Symbol, nonce: u64, expiry: u64, args_hash: BytesN<32>) -> BytesN<64> {
+    let message = SignedOperation {
+        registry: contract_id.clone(),
+        did: did.clone(),
+        operation,
+        nonce,
+        expiry,
+        args_hash,
+    }
+    .to_xdr(env);
+
+    let mut buf = [0u8; 1024];
+    let len = message.len() as usize;
+    message.copy_into_slice(&mut buf[..len]);
+    BytesN::from_array(env, &signing_key.sign(&buf[..len]).to_bytes())
+}
 
 #[test]
 fn test_did_registration_and_resolution() {
     let env = Env::default();
-    let contract_id = env.register_contract(None, DidRegistryContract);
-    let client = DidRegistryContractClient::new(&env, &contract_id);
+    let (contract_id, client, admin) = setup_test(&env);
 
     let controller = Address::generate(&env);
-    let did_string = String::from_slice(&env, "did:kosmos:123456789");
+    let did_string = String::from_str(&env, "did:kosmos:123456789");
     
-    let mut document = Map::new(&env);
-    document.set(symbol_short!("pubkey"), String::from_slice(&env, "G..."));
-    document.set(symbol_short!("service"), String::from_slice(&env, "https://example.com/endpoint"));
+    let mut document = document(&env, "did:kosmos:123456789");
+    document.service.push_back(service(&env, "#endpoint", "https://example.com/endpoint"));
 
     // Register the DID with mock authentication
     client
-        .mock_auths(&[MockAuth {
-            address: &controller,
-            invoke: &MockAuthInvoke {
-                contract: &contract_id,
-                fn_name: "register_did",
-                args: (
-                    controller.clone(),
-                    did_string.clone(),
-                    document.clone(),
-                ).into_val(&env),
-                sub_invokes: &[],
+        .mock_auths(&[
+            MockAuth {
+                address: &admin,
+                invoke: &MockAuthInvoke {
+                    contract: &contract_id,
+                    fn_name: "register_did",
+                    args: (
+                        controller.clone(),
+                        did_string.clone(),
+                        document.clone(),
+                    ).into_val(&env),
+                    sub_invokes: &[],
+                },
             },
-        }])
+            MockAuth {
+                address: &controller,
+                invoke: &MockAuthInvoke {
+                    contract: &contract_id,
+                    fn_name: "register_did",
+                    args: (
+                        controller.clone(),
+                        did_string.clone(),
+                        document.clone(),
+                    ).into_val(&env),
+                    sub_invokes: &[],
+                },
+            },
+        ])
         .register_did(&controller, &did_string, &document);
 
     // Resolve the DID
-    let (status, resolved_document) = client.get_did(&did_string);
+    let DidResolution { status, document: resolved_document, .. } = client.get_did(&did_string);
 
     assert_eq!(status, DidStatus::Active);
     assert_eq!(resolved_document, document);
-    assert_eq!(client.get_controller(&did_string), controller);
+    let controller_set = ControllerSet { controllers: vec![&env, controller.clone()], threshold: 1 };
+    assert_eq!(client.get_controller(&did_string), controller_set);
 
-    // Check events
-     let mut events = env.events().all();
-     let event = events.pop_back_unchecked();
+    // Check events: the registration, then the first document version and status
+     let events = env.events().all();
+     let (_, topics, data) = events.get(events.len() - 3).unwrap();
      assert_eq!(
-         event.topics,
+         topics,
          (symbol_short!("did_reg"), did_string.clone()).into_val(&env)
      );
-     assert_eq!(event.data, controller.into_val(&env));
+     assert_eq!(
+         DidRegisteredEvent::try_from_val(&env, &data),
+         Ok(DidRegisteredEvent { event_version: EVENT_VERSION, controller_set })
+     );
+     let (_, topics, data) = events.get(events.len() - 2).unwrap();
+     assert_eq!(topics, (symbol_short!("did_upd"), did_string.clone()).into_val(&env));
+     let updated = DocumentUpdatedEvent::try_from_val(&env, &data).unwrap();
+     assert_eq!((updated.version_id, updated.document), (1, document));
+     let (_, topics, data) = events.last().unwrap();
+     assert_eq!(topics, (symbol_short!("did_stat"), did_string.clone()).into_val(&env));
+     assert_eq!(StatusChangedEvent::try_from_val(&env, &data).unwrap().status, DidStatus::Active);
 }
 
 #[test]
 fn test_did_update() {
     let env = Env::default();
-    let contract_id = env.register_contract(None, DidRegistryContract);
-    let client = DidRegistryContractClient::new(&env, &contract_id);
+    let (contract_id, client, admin) = setup_test(&env);
 
     let controller = Address::generate(&env);
-    let did_string = String::from_slice(&env, "did:kosmos:user-a");
+    let did_string = String::from_str(&env, "did:kosmos:user-a");
     
-    let mut doc_v1 = Map::new(&env);
-    doc_v1.set(symbol_short!("key"), String::from_slice(&env, "value1"));
+    let doc_v1 = document(&env, "did:kosmos:user-a");
 
     // Register
     client
-        .mock_auths(&[MockAuth {
-            address: &controller,
-            invoke: &MockAuthInvoke {
-                contract: &contract_id,
-                fn_name: "register_did",
-                args: (controller.clone(), did_string.clone(), doc_v1).into_val(&env),
-                sub_invokes: &[],
+        .mock_auths(&[
+            MockAuth {
+                address: &admin,
+                invoke: &MockAuthInvoke {
+                    contract: &contract_id,
+                    fn_name: "register_did",
+                    args: (controller.clone(), did_string.clone(), doc_v1.clone()).into_val(&env),
+                    sub_invokes: &[],
+                },
             },
-        }])
+            MockAuth {
+                address: &controller,
+                invoke: &MockAuthInvoke {
+                    contract: &contract_id,
+                    fn_name: "register_did",
+                    args: (controller.clone(), did_string.clone(), doc_v1.clone()).into_val(&env),
+                    sub_invokes: &[],
+                },
+            },
+        ])
         .register_did(&controller, &did_string, &doc_v1);
 
     // Update
-    let mut doc_v2 = Map::new(&env);
-    doc_v2.set(symbol_short!("key"), String::from_slice(&env, "value2"));
+    let mut doc_v2 = doc_v1.clone();
+    doc_v2.verification_method.push_back(verification_method(&env, "did:kosmos:user-a", "#key-2"));
+    doc_v2.also_known_as.push_back(String::from_str(&env, "https://user-a.example.com"));
 
     client
         .mock_auths(&[MockAuth {
@@ -87,14 +204,14 @@ fn test_did_update() {
             invoke: &MockAuthInvoke {
                 contract: &contract_id,
                 fn_name: "update_document",
-                args: (did_string.clone(), doc_v2.clone()).into_val(&env),
+                args: (did_string.clone(), vec![&env, controller.clone()], doc_v2.clone()).into_val(&env),
                 sub_invokes: &[],
             },
         }])
-        .update_document(&did_string, &doc_v2);
+        .update_document(&did_string, &vec![&env, controller.clone()], &doc_v2);
 
     // Resolve and check
-    let (status, resolved_document) = client.get_did(&did_string);
+    let DidResolution { status, document: resolved_document, .. } = client.get_did(&did_string);
     assert_eq!(status, DidStatus::Active);
     assert_eq!(resolved_document, doc_v2);
 }
@@ -102,51 +219,2068 @@ fn test_did_update() {
 #[test]
 fn test_did_revocation() {
     let env = Env::default();
-    let contract_id = env.register_contract(None, DidRegistryContract);
-    let client = DidRegistryContractClient::new(&env, &contract_id);
+    let (contract_id, client, admin) = setup_test(&env);
 
     let controller = Address::generate(&env);
-    let did_string = String::from_slice(&env, "did:kosmos:user-to-revoke");
+    let did_string = String::from_str(&env, "did:kosmos:user-to-revoke");
     
-    let mut document = Map::new(&env);
-    document.set(symbol_short!("key"), String::from_slice(&env, "any_value"));
+    let document = document(&env, "did:kosmos:user-to-revoke");
 
     // Register
+    client
+        .mock_auths(&[
+            MockAuth {
+                address: &admin,
+                invoke: &MockAuthInvoke {
+                    contract: &contract_id,
+                    fn_name: "register_did",
+                    args: (controller.clone(), did_string.clone(), document.clone()).into_val(&env),
+                    sub_invokes: &[],
+                },
+            },
+            MockAuth {
+                address: &controller,
+                invoke: &MockAuthInvoke {
+                    contract: &contract_id,
+                    fn_name: "register_did",
+                    args: (controller.clone(), did_string.clone(), document.clone()).into_val(&env),
+                    sub_invokes: &[],
+                },
+            },
+        ])
+        .register_did(&controller, &did_string, &document);
+
+    // Revoke
     client
         .mock_auths(&[MockAuth {
             address: &controller,
             invoke: &MockAuthInvoke {
                 contract: &contract_id,
-                fn_name: "register_did",
-                args: (controller.clone(), did_string.clone(), document).into_val(&env),
+                fn_name: "revoke_did",
+                args: (did_string.clone(), vec![&env, controller.clone()]).into_val(&env),
                 sub_invokes: &[],
             },
         }])
-        .register_did(&controller, &did_string, &document);
+        .revoke_did(&did_string, &vec![&env, controller.clone()]);
 
-    // Revoke
+    // Resolve and check
+    let DidResolution { status, .. } = client.get_did(&did_string);
+    assert_eq!(status, DidStatus::Revoked);
+}
+
+#[test]
+fn test_get_nonexistent_did() {
+    let env = Env::default();
+    let (_, client, _) = setup_test(&env);
+    let did_string = String::from_str(&env, "did:kosmos:nonexistent");
+    assert_eq!(client.try_get_did(&did_string), Err(Ok(Error::DidNotFound)));
+}
+
+#[test]
+fn test_register_document_without_verification_method() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:no-keys");
+    let mut document = document(&env, "did:kosmos:no-keys");
+    document.verification_method = Vec::new(&env);
+
+    assert_eq!(client.try_register_did(&controller, &did_string, &document), Err(Ok(Error::InvalidDocument)));
+}
+
+#[test]
+fn test_update_document_with_duplicate_method_ids() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:dup-keys");
+    let mut document = document(&env, "did:kosmos:dup-keys");
+    client.register_did(&controller, &did_string, &document);
+
+    document.verification_method.push_back(verification_method(&env, "did:kosmos:dup-keys", "#key-1"));
+    assert_eq!(client.try_update_document(&did_string, &vec![&env, controller.clone()], &document), Err(Ok(Error::DuplicateId)));
+}
+
+#[test]
+fn test_register_document_with_empty_service_endpoint() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:bad-service");
+    let mut document = document(&env, "did:kosmos:bad-service");
+    document.service.push_back(service(&env, "#endpoint", ""));
+
+    assert_eq!(client.try_register_did(&controller, &did_string, &document), Err(Ok(Error::InvalidDocument)));
+}
+
+#[test]
+fn test_did_entries_are_persistent_and_ttl_can_be_extended() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:long-lived");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:long-lived"));
+
+    let keys = [
+        DataKey::Controller(did_string.clone()),
+        DataKey::Document(did_string.clone(), 1),
+        DataKey::Status(did_string.clone()),
+    ];
+    env.as_contract(&contract_id, || {
+        assert!(env.storage().persistent().has(&DataKey::Controller(did_string.clone())));
+        assert!(!env.storage().instance().has(&DataKey::Controller(did_string.clone())));
+        for key in keys.iter() {
+            assert_eq!(env.storage().persistent().get_ttl(key), DID_TTL_EXTEND_TO);
+        }
+    });
+
+    // Let the DID's entries age below the extension threshold, keeping the contract
+    // instance itself alive in between.
+    let elapsed = 32 * DAY_IN_LEDGERS;
+    env.ledger().with_mut(|li| li.sequence_number += elapsed / 2);
+    env.as_contract(&contract_id, || {
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+    });
+    env.ledger().with_mut(|li| li.sequence_number += elapsed / 2);
+    env.as_contract(&contract_id, || {
+        for key in keys.iter() {
+            let ttl = env.storage().persistent().get_ttl(key);
+            assert_eq!(ttl, DID_TTL_EXTEND_TO - elapsed);
+            assert!(ttl < DID_TTL_THRESHOLD);
+        }
+    });
+
+    // Anyone can keep the DID alive, without the controller's authorization.
+    assert_eq!(client.mock_auths(&[]).extend_did_ttl(&did_string, &1, &MAX_TTL_VERSION_PAGE), None);
+    env.as_contract(&contract_id, || {
+        for key in keys.iter() {
+            assert_eq!(env.storage().persistent().get_ttl(key), DID_TTL_EXTEND_TO);
+        }
+    });
+
+    let DidResolution { status, .. } = client.get_did(&did_string);
+    assert_eq!(status, DidStatus::Active);
+}
+
+#[test]
+fn test_controller_transfer() {
+    let env = Env::default();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let new_controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:rotating");
+    let document = document(&env, "did:kosmos:rotating");
+
+    env.mock_all_auths();
+    client.register_did(&controller, &did_string, &document);
+
+    // Propose, authorized by the current controller
+    let signers = vec![&env, controller.clone()];
+    let new_controllers = vec![&env, new_controller.clone()];
     client
         .mock_auths(&[MockAuth {
             address: &controller,
             invoke: &MockAuthInvoke {
                 contract: &contract_id,
-                fn_name: "revoke_did",
+                fn_name: "change_controller",
+                args: (did_string.clone(), signers.clone(), new_controllers.clone(), 1u32).into_val(&env),
+                sub_invokes: &[],
+            },
+        }])
+        .change_controller(&did_string, &signers, &new_controllers, &1);
+
+    // Nothing changes until the new controller accepts
+    let old_set = ControllerSet { controllers: signers, threshold: 1 };
+    let new_set = ControllerSet { controllers: new_controllers, threshold: 1 };
+    assert_eq!(client.get_controller(&did_string), old_set);
+    assert_eq!(client.get_pending_controller(&did_string), Some(new_set.clone()));
+
+    // Accept, authorized by the new controller
+    client
+        .mock_auths(&[MockAuth {
+            address: &new_controller,
+            invoke: &MockAuthInvoke {
+                contract: &contract_id,
+                fn_name: "accept_controller",
                 args: (did_string.clone(),).into_val(&env),
                 sub_invokes: &[],
             },
         }])
-        .revoke_did(&did_string);
+        .accept_controller(&did_string);
 
-    // Resolve and check
-    let (status, _) = client.get_did(&did_string);
-    assert_eq!(status, DidStatus::Revoked);
+    assert_eq!(client.get_controller(&did_string), new_set);
+    assert_eq!(client.get_pending_controller(&did_string), None);
+
+    // Check events
+    let mut events = env.events().all();
+    let (_, topics, data) = events.pop_back_unchecked();
+    assert_eq!(
+        topics,
+        (symbol_short!("ctl_chg"), did_string.clone()).into_val(&env)
+    );
+    assert_eq!(
+        ControllerChangedEvent::try_from_val(&env, &data),
+        Ok(ControllerChangedEvent {
+            event_version: EVENT_VERSION,
+            previous_controller_set: old_set,
+            controller_set: new_set,
+            cause: symbol_short!("transfer"),
+        })
+    );
 }
 
 #[test]
-#[should_panic(expected = "DID not found")]
-fn test_get_nonexistent_did() {
+fn test_accept_controller_without_proposal() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:no-proposal");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:no-proposal"));
+
+    assert_eq!(client.try_accept_controller(&did_string), Err(Ok(Error::NoPendingController)));
+}
+
+#[test]
+fn test_multi_controller_threshold() {
+    let env = Env::default();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let carol = Address::generate(&env);
+    let controllers = vec![&env, alice.clone(), bob.clone(), carol.clone()];
+    let did_string = String::from_str(&env, "did:kosmos:acme-corp");
+    let doc_v1 = document(&env, "did:kosmos:acme-corp");
+
+    env.mock_all_auths();
+    client.register_multi_controller_did(&controllers, &2, &did_string, &doc_v1);
+    assert_eq!(
+        client.get_controller(&did_string),
+        ControllerSet { controllers: controllers.clone(), threshold: 2 }
+    );
+
+    // Two of three controllers sign the update
+    let mut doc_v2 = doc_v1.clone();
+    doc_v2.service.push_back(service(&env, "#website", "https://acme.example.com"));
+    let signers = vec![&env, alice.clone(), carol.clone()];
+    client
+        .mock_auths(&[
+            MockAuth {
+                address: &alice,
+                invoke: &MockAuthInvoke {
+                    contract: &contract_id,
+                    fn_name: "update_document",
+                    args: (did_string.clone(), signers.clone(), doc_v2.clone()).into_val(&env),
+                    sub_invokes: &[],
+                },
+            },
+            MockAuth {
+                address: &carol,
+                invoke: &MockAuthInvoke {
+                    contract: &contract_id,
+                    fn_name: "update_document",
+                    args: (did_string.clone(), signers.clone(), doc_v2.clone()).into_val(&env),
+                    sub_invokes: &[],
+                },
+            },
+        ])
+        .update_document(&did_string, &signers, &doc_v2);
+
+    let DidResolution { document: resolved_document, .. } = client.get_did(&did_string);
+    assert_eq!(resolved_document, doc_v2);
+}
+
+#[test]
+fn test_multi_controller_below_threshold() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let controllers = vec![&env, alice.clone(), bob.clone()];
+    let did_string = String::from_str(&env, "did:kosmos:two-of-two");
+    client.register_multi_controller_did(&controllers, &2, &did_string, &document(&env, "did:kosmos:two-of-two"));
+
+    assert_eq!(client.try_revoke_did(&did_string, &vec![&env, alice]), Err(Ok(Error::NotEnoughSignatures)));
+}
+
+#[test]
+fn test_update_by_non_controller() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let outsider = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:guarded");
+    let document = document(&env, "did:kosmos:guarded");
+    client.register_did(&controller, &did_string, &document);
+
+    assert_eq!(client.try_update_document(&did_string, &vec![&env, outsider], &document), Err(Ok(Error::NotAController)));
+}
+
+#[test]
+fn test_document_version_history() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let did_string = String::from_str(&env, "did:kosmos:versioned");
+    let doc_v1 = document(&env, "did:kosmos:versioned");
+
+    env.ledger().with_mut(|li| {
+        li.sequence_number = 100;
+        li.timestamp = 1_000;
+    });
+    client.register_did(&controller, &did_string, &doc_v1);
+
+    let mut doc_v2 = doc_v1.clone();
+    doc_v2.verification_method.set(0, verification_method(&env, "did:kosmos:versioned", "#key-2"));
+    env.ledger().with_mut(|li| {
+        li.sequence_number = 200;
+        li.timestamp = 2_000;
+    });
+    client.update_document(&did_string, &signers, &doc_v2);
+
+    // The current document is the latest version
+    let DidResolution { document: resolved_document, .. } = client.get_did(&did_string);
+    assert_eq!(resolved_document, doc_v2);
+
+    // Resolution by versionId
+    assert_eq!(
+        client.get_did_version(&did_string, &1),
+        DocumentVersion { version_id: 1, document: doc_v1.clone(), ledger_sequence: 100, timestamp: 1_000 }
+    );
+    assert_eq!(client.get_did_version(&did_string, &2).document, doc_v2);
+
+    // Resolution by versionTime
+    assert_eq!(client.get_did_at(&did_string, &1_000).version_id, 1);
+    assert_eq!(client.get_did_at(&did_string, &1_999).document, doc_v1);
+    assert_eq!(client.get_did_at(&did_string, &2_000).version_id, 2);
+    assert_eq!(client.get_did_at(&did_string, &u64::MAX).document, doc_v2);
+}
+
+#[test]
+fn test_extend_did_ttl_pages_through_document_versions() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let did_string = String::from_str(&env, "did:kosmos:long-history");
+    let mut doc = document(&env, "did:kosmos:long-history");
+    client.register_did(&controller, &did_string, &doc);
+
+    // More historical versions than fit in one page
+    let versions = 2 * MAX_TTL_VERSION_PAGE + 5;
+    for version_id in 2..=versions {
+        doc.also_known_as = vec![&env, String::from_str(&env, if version_id % 2 == 0 { "https://a.example" } else { "https://b.example" })];
+        client.update_document(&did_string, &signers, &doc);
+    }
+    assert_eq!(client.get_did_version(&did_string, &versions).document, doc);
+
+    // Age every version below the extension threshold, keeping the contract instance alive
+    let elapsed = 32 * DAY_IN_LEDGERS;
+    env.ledger().with_mut(|li| li.sequence_number += elapsed / 2);
+    env.as_contract(&contract_id, || {
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+    });
+    env.ledger().with_mut(|li| li.sequence_number += elapsed / 2);
+    let version_ttl = |version_id: u32| {
+        env.as_contract(&contract_id, || env.storage().persistent().get_ttl(&DataKey::Document(did_string.clone(), version_id)))
+    };
+    for version_id in 1..=versions {
+        assert!(version_ttl(version_id) < DID_TTL_THRESHOLD);
+    }
+
+    assert_eq!(client.try_extend_did_ttl(&did_string, &1, &0), Err(Ok(Error::InvalidPageSize)));
+    assert_eq!(client.try_extend_did_ttl(&did_string, &1, &(MAX_TTL_VERSION_PAGE + 1)), Err(Ok(Error::InvalidPageSize)));
+
+    // The first page extends the current version and the oldest historical ones
+    let next = client.extend_did_ttl(&did_string, &1, &MAX_TTL_VERSION_PAGE);
+    assert_eq!(next, Some(MAX_TTL_VERSION_PAGE + 1));
+    assert_eq!(version_ttl(versions), DID_TTL_EXTEND_TO);
+    assert_eq!(version_ttl(MAX_TTL_VERSION_PAGE), DID_TTL_EXTEND_TO);
+    assert!(version_ttl(MAX_TTL_VERSION_PAGE + 1) < DID_TTL_THRESHOLD);
+
+    // Following the returned cursor reaches every version
+    let mut cursor = next;
+    let mut pages = 1;
+    while let Some(from_version) = cursor {
+        cursor = client.extend_did_ttl(&did_string, &from_version, &MAX_TTL_VERSION_PAGE);
+        pages += 1;
+    }
+    assert_eq!(pages, 3);
+    for version_id in 1..=versions {
+        assert_eq!(version_ttl(version_id), DID_TTL_EXTEND_TO);
+    }
+}
+
+#[test]
+fn test_resolve_before_registration() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:late");
+    env.ledger().with_mut(|li| li.timestamp = 5_000);
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:late"));
+
+    assert_eq!(client.try_get_did_at(&did_string, &4_999), Err(Ok(Error::VersionNotFound)));
+}
+
+#[test]
+fn test_did_syntax() {
+    assert_eq!(
+        did_syntax::parse(b"did:kosmos:123456789"),
+        Ok(ParsedDid { network: Network::Mainnet, id: b"123456789" })
+    );
+    assert_eq!(
+        did_syntax::parse(b"did:kosmos:testnet:user-a"),
+        Ok(ParsedDid { network: Network::Testnet, id: b"user-a" })
+    );
+    assert_eq!(
+        did_syntax::parse(b"did:kosmos:futurenet:org.acme_1"),
+        Ok(ParsedDid { network: Network::Futurenet, id: b"org.acme_1" })
+    );
+
+    assert_eq!(did_syntax::parse(b"hello"), Err(DidSyntaxError::InvalidMethod));
+    assert_eq!(did_syntax::parse(b"did:web:foo"), Err(DidSyntaxError::InvalidMethod));
+    assert_eq!(did_syntax::parse(b"did:kosmos:"), Err(DidSyntaxError::EmptyId));
+    assert_eq!(did_syntax::parse(b"did:kosmos:testnet:"), Err(DidSyntaxError::EmptyId));
+    assert_eq!(did_syntax::parse(b"did:kosmos:devnet:abc"), Err(DidSyntaxError::UnknownNetwork));
+    assert_eq!(did_syntax::parse(b"did:kosmos:testnet:a:b"), Err(DidSyntaxError::InvalidCharacter));
+    assert_eq!(did_syntax::parse(b"did:kosmos:a%20b"), Err(DidSyntaxError::InvalidCharacter));
+    assert_eq!(did_syntax::parse(&[b'a'; did_syntax::MAX_DID_LEN + 1]), Err(DidSyntaxError::TooLong));
+
+    let mut long_id = [b'a'; 11 + did_syntax::MAX_ID_LEN + 1];
+    long_id[..11].copy_from_slice(b"did:kosmos:");
+    assert_eq!(did_syntax::parse(&long_id), Err(DidSyntaxError::IdTooLong));
+
+    // Valid, but not canonical
+    assert_eq!(did_syntax::parse(b"DID:KOSMOS:abc"), Err(DidSyntaxError::NonCanonical));
+    assert_eq!(did_syntax::parse(b"did:kosmos:mainnet:abc"), Err(DidSyntaxError::NonCanonical));
+    assert_eq!(did_syntax::parse(b"did:kosmos:TestNet:abc"), Err(DidSyntaxError::NonCanonical));
+    assert_eq!(did_syntax::parse(b"did:kosmos:Alice"), Err(DidSyntaxError::NonCanonical));
+}
+
+#[test]
+fn test_register_malformed_did() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:web:foo");
+    assert_eq!(client.try_register_did(&controller, &did_string, &document(&env, "did:web:foo")), Err(Ok(Error::InvalidDid)));
+}
+
+#[test]
+fn test_register_non_canonical_did() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:mainnet:alice");
+    assert_eq!(client.try_register_did(&controller, &did_string, &document(&env, "did:kosmos:mainnet:alice")), Err(Ok(Error::NonCanonicalDid)));
+}
+
+#[test]
+fn test_derived_did_registration() {
+    let env = Env::default();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = client.derive_did(&controller);
+    let document = document(&env, "did:kosmos:derived");
+
+    // Only the controller signs; no admin involvement
+    client
+        .mock_auths(&[MockAuth {
+            address: &controller,
+            invoke: &MockAuthInvoke {
+                contract: &contract_id,
+                fn_name: "register_derived_did",
+                args: (controller.clone(), document.clone()).into_val(&env),
+                sub_invokes: &[],
+            },
+        }])
+        .register_derived_did(&controller, &document);
+
+    // The DID is predictable from the key, and different keys map to different DIDs
+    assert_eq!(did_string.len(), 11 + 40);
+    assert_ne!(client.derive_did(&Address::generate(&env)), did_string);
+    let DidResolution { status, document: resolved_document, .. } = client.get_did(&did_string);
+    assert_eq!(status, DidStatus::Active);
+    assert_eq!(resolved_document, document);
+
+    let mut buf = [0u8; 51];
+    did_string.copy_into_slice(&mut buf);
+    assert!(did_syntax::is_valid(&buf));
+}
+
+#[test]
+fn test_derived_did_depends_on_network() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let public_key = BytesN::from_array(&env, &[7; 32]);
+
+    env.ledger().set_network_id(did_syntax::MAINNET_NETWORK_ID);
+    let mainnet_did = client.derive_did(&controller);
+    let mainnet_key_did = client.derive_key_did(&public_key);
+    env.ledger().set_network_id(did_syntax::TESTNET_NETWORK_ID);
+    let testnet_did = client.derive_did(&controller);
+    let testnet_key_did = client.derive_key_did(&public_key);
+
+    // The same controller or key gets a different DID on each network
+    assert_ne!(mainnet_did, testnet_did);
+    assert_ne!(mainnet_key_did, testnet_key_did);
+
+    // Each DID names its network
+    let mut buf = [0u8; did_syntax::MAX_DID_LEN];
+    let len = mainnet_did.len() as usize;
+    mainnet_did.copy_into_slice(&mut buf[..len]);
+    assert_eq!(did_syntax::parse(&buf[..len]).unwrap().network, Network::Mainnet);
+    let len = testnet_did.len() as usize;
+    testnet_did.copy_into_slice(&mut buf[..len]);
+    assert_eq!(did_syntax::parse(&buf[..len]).unwrap().network, Network::Testnet);
+
+    // Registration assigns the DID of the network the registry runs on
+    assert_eq!(client.register_derived_did(&controller, &document(&env, "did:kosmos:derived")), testnet_did);
+}
+
+#[test]
+fn test_free_form_did_cannot_squat_derived_id() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let victim = Address::generate(&env);
+    let squatter = Address::generate(&env);
+    let did_string = client.derive_did(&victim);
+    assert_eq!(client.try_register_did(&squatter, &did_string, &document(&env, "did:kosmos:squatted")), Err(Ok(Error::ReservedDid)));
+}
+
+#[test]
+fn test_free_form_registration_requires_admin() {
+    let env = Env::default();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:premium-name");
+    let document = document(&env, "did:kosmos:premium-name");
+
+    // The controller alone cannot claim a free-form name
+    let result = client
+        .mock_auths(&[MockAuth {
+            address: &controller,
+            invoke: &MockAuthInvoke {
+                contract: &contract_id,
+                fn_name: "register_did",
+                args: (controller.clone(), did_string.clone(), document.clone()).into_val(&env),
+                sub_invokes: &[],
+            },
+        }])
+        .try_register_did(&controller, &did_string, &document);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_delegates() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let delegate = Address::generate(&env);
+    let sig_auth = Symbol::new(&env, "sigAuth");
+    let veri_key = Symbol::new(&env, "veriKey");
+    let did_string = String::from_str(&env, "did:kosmos:delegating");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:delegating"));
+
+    env.ledger().with_mut(|li| li.timestamp = 1_000);
+    client.add_delegate(&did_string, &signers, &delegate, &sig_auth, &500);
+
+    // Valid only for the delegated purpose, and included in the resolved DID
+    assert!(client.valid_delegate(&did_string, &sig_auth, &delegate));
+    assert!(!client.valid_delegate(&did_string, &veri_key, &delegate));
+    assert_eq!(
+        client.get_did(&did_string).delegates,
+        vec![&env, Delegate { delegate: delegate.clone(), delegate_type: sig_auth.clone(), valid_from: 1_000, valid_to: 1_500 }]
+    );
+
+    // Expires at the end of its validity window
+    env.ledger().with_mut(|li| li.timestamp = 1_500);
+    assert!(!client.valid_delegate(&did_string, &sig_auth, &delegate));
+    assert_eq!(client.get_did(&did_string).delegates.len(), 0);
+
+    // Can be renewed, then revoked early
+    client.add_delegate(&did_string, &signers, &delegate, &sig_auth, &500);
+    assert!(client.valid_delegate(&did_string, &sig_auth, &delegate));
+    client.revoke_delegate(&did_string, &signers, &delegate, &sig_auth);
+    assert!(!client.valid_delegate(&did_string, &sig_auth, &delegate));
+}
+
+#[test]
+fn test_delegates_of_revoked_did_are_invalid() {
     let env = Env::default();
-    let client = DidRegistryContractClient::new(&env, &env.register_contract(None, DidRegistryContract));
-    let did_string = String::from_slice(&env, "did:kosmos:nonexistent");
-    client.get_did(&did_string);
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let delegate = Address::generate(&env);
+    let sig_auth = Symbol::new(&env, "sigAuth");
+    let did_string = String::from_str(&env, "did:kosmos:revoked-delegator");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:revoked-delegator"));
+
+    client.add_delegate(&did_string, &signers, &delegate, &sig_auth, &3_600);
+    client.revoke_did(&did_string, &signers);
+
+    assert!(!client.valid_delegate(&did_string, &sig_auth, &delegate));
+}
+
+#[test]
+fn test_pre_rotation() {
+    let env = Env::default();
+    let (contract_id, client, _) = setup_test(&env);
+    env.mock_all_auths();
+
+    let key_0 = Address::generate(&env);
+    let key_1 = Address::generate(&env);
+    let key_2 = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:pre-rotated");
+    client.register_did(&key_0, &did_string, &document(&env, "did:kosmos:pre-rotated"));
+
+    // Commit to key_1 as the next controller
+    let commitment_1 = client.controller_commitment(&vec![&env, key_1.clone()], &1);
+    client.commit_next_controller(&did_string, &vec![&env, key_0.clone()], &commitment_1);
+    assert_eq!(client.get_next_controller_commitment(&did_string), Some(commitment_1));
+
+    // Rotate to key_1, authorized by key_1 only, committing to key_2
+    let commitment_2 = client.controller_commitment(&vec![&env, key_2.clone()], &1);
+    client
+        .mock_auths(&[MockAuth {
+            address: &key_1,
+            invoke: &MockAuthInvoke {
+                contract: &contract_id,
+                fn_name: "rotate_controller",
+                args: (did_string.clone(), vec![&env, key_1.clone()], 1u32, commitment_2.clone()).into_val(&env),
+                sub_invokes: &[],
+            },
+        }])
+        .rotate_controller(&did_string, &vec![&env, key_1.clone()], &1, &commitment_2);
+
+    assert_eq!(
+        client.get_controller(&did_string),
+        ControllerSet { controllers: vec![&env, key_1.clone()], threshold: 1 }
+    );
+    assert_eq!(client.get_next_controller_commitment(&did_string), Some(commitment_2));
+}
+
+#[test]
+fn test_pre_rotation_rejects_uncommitted_key() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let key_0 = Address::generate(&env);
+    let key_1 = Address::generate(&env);
+    let attacker = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:hijack-target");
+    client.register_did(&key_0, &did_string, &document(&env, "did:kosmos:hijack-target"));
+
+    let commitment_1 = client.controller_commitment(&vec![&env, key_1.clone()], &1);
+    client.commit_next_controller(&did_string, &vec![&env, key_0.clone()], &commitment_1);
+
+    assert_eq!(client.try_rotate_controller(&did_string, &vec![&env, attacker], &1, &BytesN::from_array(&env, &[0; 32])), Err(Ok(Error::CommitmentMismatch)));
+}
+
+#[test]
+fn test_pre_rotation_blocks_change_controller() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let key_0 = Address::generate(&env);
+    let key_1 = Address::generate(&env);
+    let attacker = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:stolen-key");
+    client.register_did(&key_0, &did_string, &document(&env, "did:kosmos:stolen-key"));
+
+    let commitment_1 = client.controller_commitment(&vec![&env, key_1], &1);
+    client.commit_next_controller(&did_string, &vec![&env, key_0.clone()], &commitment_1);
+
+    // A stolen current key cannot transfer the DID
+    assert_eq!(client.try_change_controller(&did_string, &vec![&env, key_0], &vec![&env, attacker], &1), Err(Ok(Error::UsesPreRotation)));
+}
+
+#[test]
+fn test_social_recovery() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let lost_key = Address::generate(&env);
+    let new_key = Address::generate(&env);
+    let guardian_1 = Address::generate(&env);
+    let guardian_2 = Address::generate(&env);
+    let guardian_3 = Address::generate(&env);
+    let guardians = vec![&env, guardian_1.clone(), guardian_2.clone(), guardian_3.clone()];
+    let did_string = String::from_str(&env, "did:kosmos:recoverable");
+    client.register_did(&lost_key, &did_string, &document(&env, "did:kosmos:recoverable"));
+
+    client.set_guardians(&did_string, &vec![&env, lost_key.clone()], &guardians, &2, &86_400);
+    assert_eq!(
+        client.get_guardians(&did_string),
+        Some(GuardianSet { guardians, threshold: 2, delay: 86_400 })
+    );
+
+    // Two of three guardians approve the recovery to the new key
+    env.ledger().with_mut(|li| li.timestamp = 10_000);
+    let new_controllers = vec![&env, new_key.clone()];
+    client.approve_recovery(&did_string, &guardian_1, &new_controllers, &1);
+    assert_eq!(client.get_recovery(&did_string).unwrap().executable_at, 0);
+    client.approve_recovery(&did_string, &guardian_3, &new_controllers, &1);
+    assert_eq!(client.get_recovery(&did_string).unwrap().executable_at, 96_400);
+
+    let mut events = env.events().all();
+    let (_, topics, _) = events.pop_back_unchecked();
+    assert_eq!(
+        topics,
+        (symbol_short!("rec_ready"), did_string.clone()).into_val(&env)
+    );
+
+    // Not executable during the delay window
+    env.ledger().with_mut(|li| li.timestamp = 96_399);
+    assert_eq!(client.try_execute_recovery(&did_string), Err(Ok(Error::RecoveryNotExecutable)));
+
+    env.ledger().with_mut(|li| li.timestamp = 96_400);
+    client.execute_recovery(&did_string);
+    assert_eq!(
+        client.get_controller(&did_string),
+        ControllerSet { controllers: new_controllers, threshold: 1 }
+    );
+    assert_eq!(client.get_recovery(&did_string), None);
+}
+
+#[test]
+fn test_controller_cancels_malicious_recovery() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let attacker = Address::generate(&env);
+    let guardian = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:watched");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:watched"));
+    client.set_guardians(&did_string, &vec![&env, controller.clone()], &vec![&env, guardian.clone()], &1, &86_400);
+
+    // A compromised guardian starts a recovery; the controller cancels it within the delay
+    client.approve_recovery(&did_string, &guardian, &vec![&env, attacker], &1);
+    client.cancel_recovery(&did_string, &vec![&env, controller.clone()]);
+    assert_eq!(client.get_recovery(&did_string), None);
+
+    env.ledger().with_mut(|li| li.timestamp += 86_400);
+    assert_eq!(client.try_execute_recovery(&did_string), Err(Ok(Error::NoRecoveryInProgress)));
+}
+
+#[test]
+fn test_suspension_and_reactivation() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let delegate = Address::generate(&env);
+    let sig_auth = Symbol::new(&env, "sigAuth");
+    let did_string = String::from_str(&env, "did:kosmos:suspicious");
+    env.ledger().with_mut(|li| li.timestamp = 100);
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:suspicious"));
+    client.add_delegate(&did_string, &signers, &delegate, &sig_auth, &10_000);
+
+    // Suspended: delegates stop being valid
+    env.ledger().with_mut(|li| li.timestamp = 200);
+    client.suspend_did(&did_string, &signers, &7);
+    assert_eq!(client.get_did(&did_string).status, DidStatus::Suspended);
+    assert_eq!(client.get_status(&did_string), DidStatus::Suspended);
+    assert!(!client.valid_delegate(&did_string, &sig_auth, &delegate));
+
+    // Reactivated: back to normal
+    env.ledger().with_mut(|li| li.timestamp = 300);
+    client.reactivate_did(&did_string, &signers);
+    assert_eq!(client.get_did(&did_string).status, DidStatus::Active);
+    assert!(client.valid_delegate(&did_string, &sig_auth, &delegate));
+
+    // Deactivated with a reason code
+    env.ledger().with_mut(|li| li.timestamp = 400);
+    client.deactivate_did(&did_string, &signers, &42);
+    assert_eq!(client.get_did(&did_string).status, DidStatus::Deactivated);
+
+    let history = client.get_status_history(&did_string);
+    assert_eq!(history.len(), 4);
+    let statuses = [DidStatus::Active, DidStatus::Suspended, DidStatus::Active, DidStatus::Deactivated];
+    let reasons = [0u32, 7, 0, 42];
+    for (i, change) in history.iter().enumerate() {
+        assert_eq!(
+            change,
+            StatusChange { status: statuses[i], reason: reasons[i], ledger_sequence: 0, timestamp: 100 * (i as u64 + 1) }
+        );
+    }
+}
+
+#[test]
+fn test_guardians_reactivate_suspended_did() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let guardian_1 = Address::generate(&env);
+    let guardian_2 = Address::generate(&env);
+    let guardians = vec![&env, guardian_1.clone(), guardian_2.clone()];
+    let did_string = String::from_str(&env, "did:kosmos:guarded-suspension");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:guarded-suspension"));
+    client.set_guardians(&did_string, &signers, &guardians, &2, &86_400);
+
+    client.suspend_did(&did_string, &signers, &1);
+    client.reactivate_did_by_guardians(&did_string, &guardians);
+    assert_eq!(client.get_did(&did_string).status, DidStatus::Active);
+}
+
+#[test]
+fn test_update_suspended_did() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let did_string = String::from_str(&env, "did:kosmos:frozen");
+    let document = document(&env, "did:kosmos:frozen");
+    client.register_did(&controller, &did_string, &document);
+
+    client.suspend_did(&did_string, &signers, &1);
+    assert_eq!(client.try_update_document(&did_string, &signers, &document), Err(Ok(Error::DidNotActive)));
+}
+
+#[test]
+fn test_granular_document_operations() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let did = "did:kosmos:granular";
+    let did_string = String::from_str(&env, did);
+    client.register_did(&controller, &did_string, &document(&env, did));
+
+    // Verification methods
+    client.add_verification_method(&did_string, &signers, &verification_method(&env, did, "#key-2"));
+    let mut rotated = verification_method(&env, did, "#key-2");
+    rotated.public_key = Bytes::from_array(&env, &[9u8; 32]);
+    client.replace_verification_method(&did_string, &signers, &rotated);
+    client.remove_verification_method(&did_string, &signers, &String::from_str(&env, "#key-1"));
+    assert_eq!(client.get_did(&did_string).document.verification_method, vec![&env, rotated]);
+
+    // Services
+    client.add_service(&did_string, &signers, &service(&env, "#home", "https://old.example.com"));
+    let moved = service(&env, "#home", "https://new.example.com");
+    client.replace_service(&did_string, &signers, &moved);
+    assert_eq!(client.get_did(&did_string).document.service, vec![&env, moved]);
+
+    let mut events = env.events().all();
+    let (_, topics, data) = events.pop_back_unchecked();
+    assert_eq!(
+        topics,
+        (symbol_short!("svc_repl"), did_string.clone()).into_val(&env)
+    );
+    assert_eq!(String::try_from_val(&env, &data), Ok(String::from_str(&env, "#home")));
+
+    client.remove_service(&did_string, &signers, &String::from_str(&env, "#home"));
+    assert_eq!(client.get_did(&did_string).document.service.len(), 0);
+
+    // Every operation is a new document version
+    assert_eq!(client.get_did_at(&did_string, &u64::MAX).version_id, 7);
+}
+
+#[test]
+fn test_remove_last_verification_method() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:keyless");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:keyless"));
+
+    assert_eq!(client.try_remove_verification_method(&did_string, &vec![&env, controller.clone()], &String::from_str(&env, "#key-1")), Err(Ok(Error::InvalidDocument)));
+}
+
+#[test]
+fn test_verification_relationships() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let did = "did:kosmos:university";
+    let did_string = String::from_str(&env, did);
+
+    let mut issuing_key = verification_method(&env, did, "#issuing-key");
+    issuing_key.public_key = Bytes::from_array(&env, &[1u8; 32]);
+    let mut document = document(&env, did);
+    document.verification_method.push_back(issuing_key.clone());
+    document.authentication.push_back(String::from_str(&env, "#key-1"));
+    document.assertion_method.push_back(String::from_str(&env, "#issuing-key"));
+
+    env.ledger().with_mut(|li| li.timestamp = 1_000);
+    client.register_did(&controller, &did_string, &document);
+
+    let auth_key = Bytes::from_array(&env, &[7u8; 32]);
+    let issuing_key_bytes = issuing_key.public_key.clone();
+    assert!(client.is_authorized_key(&did_string, &issuing_key_bytes, &VerificationRelationship::AssertionMethod, &1_000));
+    assert!(!client.is_authorized_key(&did_string, &auth_key, &VerificationRelationship::AssertionMethod, &1_000));
+    assert!(client.is_authorized_key(&did_string, &auth_key, &VerificationRelationship::Authentication, &1_000));
+    // Not before the DID existed
+    assert!(!client.is_authorized_key(&did_string, &issuing_key_bytes, &VerificationRelationship::AssertionMethod, &999));
+
+    // Retiring the issuing key does not invalidate credentials issued before
+    env.ledger().with_mut(|li| li.timestamp = 2_000);
+    client.remove_verification_method(&did_string, &signers, &String::from_str(&env, "#issuing-key"));
+    assert_eq!(client.get_did(&did_string).document.assertion_method.len(), 0);
+    assert!(client.is_authorized_key(&did_string, &issuing_key_bytes, &VerificationRelationship::AssertionMethod, &1_500));
+    assert!(!client.is_authorized_key(&did_string, &issuing_key_bytes, &VerificationRelationship::AssertionMethod, &2_000));
+
+    // Nothing is authorized while the DID is suspended
+    env.ledger().with_mut(|li| li.timestamp = 3_000);
+    client.suspend_did(&did_string, &signers, &1);
+    assert!(!client.is_authorized_key(&did_string, &auth_key, &VerificationRelationship::Authentication, &3_000));
+    assert!(client.is_authorized_key(&did_string, &auth_key, &VerificationRelationship::Authentication, &2_999));
+}
+
+#[test]
+fn test_relationship_with_unknown_method() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:dangling");
+    let mut document = document(&env, "did:kosmos:dangling");
+    document.assertion_method.push_back(String::from_str(&env, "#missing"));
+
+    assert_eq!(client.try_register_did(&controller, &did_string, &document), Err(Ok(Error::VerificationMethodNotFound)));
+}
+
+#[test]
+fn test_signed_operations() {
+    let env = Env::default();
+    let (contract_id, client, _) = setup_test(&env);
+
+    // Nobody authorizes: the key holder's signatures are enough, so the controller may be
+    // an account that does not exist on the ledger yet
+    let signing_key = SigningKey::from_bytes(&[3u8; 32]);
+    let public_key = BytesN::from_array(&env, &signing_key.verifying_key().to_bytes());
+    let did_string = client.derive_key_did(&public_key);
+    let mut did = [0u8; 51];
+    did_string.copy_into_slice(&mut did);
+    let did = core::str::from_utf8(&did).unwrap();
+
+    let controller = Address::generate(&env);
+    let controller_set = ControllerSet { controllers: vec![&env, controller.clone()], threshold: 1 };
+    let document = signer_document(&env, did, &signing_key);
+    let key_id = String::from_str(&env, "#key-1");
+    let expiry = 1_000u64;
+
+    let args_hash = env.crypto().sha256(&(controller_set.clone(), document.clone()).to_xdr(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("register"), 0, expiry, args_hash);
+    let relayer = Address::generate(&env);
+    let registered = client.register_did_signed(&relayer, &controller_set.controllers, &1, &document, &key_id, &expiry, &signature);
+    assert_eq!(registered, did_string);
+    assert!(env.auths().is_empty());
+    assert_eq!(client.get_controller(&did_string), controller_set);
+    assert_eq!(client.get_nonce(&did_string), 1);
+
+    let mut new_document = document.clone();
+    new_document.service.push_back(service(&env, "#hub", "https://hub.example.com"));
+    let args_hash = env.crypto().sha256(&new_document.clone().to_xdr(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("update"), 1, expiry, args_hash);
+    client.update_document_signed(&did_string, &new_document, &key_id, &expiry, &signature);
+    assert_eq!(client.get_did(&did_string).document, new_document);
+    assert_eq!(client.get_nonce(&did_string), 2);
+
+    let args_hash = env.crypto().sha256(&Bytes::new(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("revoke"), 2, expiry, args_hash);
+    client.revoke_did_signed(&did_string, &key_id, &expiry, &signature);
+    assert_eq!(client.get_did(&did_string).status, DidStatus::Revoked);
+
+    let events = env.events().all();
+    let (_, topics, data) = events.last().unwrap();
+    assert_eq!(topics, (symbol_short!("did_rev"), did_string.clone()).into_val(&env));
+    assert_eq!(String::try_from_val(&env, &data).unwrap(), key_id);
+}
+
+#[test]
+fn test_signed_update_replay() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let signing_key = SigningKey::from_bytes(&[4u8; 32]);
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:relayed");
+    let document = signer_document(&env, "did:kosmos:relayed", &signing_key);
+    client.register_did(&controller, &did_string, &document);
+
+    let key_id = String::from_str(&env, "#key-1");
+    let args_hash = env.crypto().sha256(&document.clone().to_xdr(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("update"), 0, 1_000, args_hash);
+    client.update_document_signed(&did_string, &document, &key_id, &1_000, &signature);
+    assert_eq!(client.get_nonce(&did_string), 1);
+
+    // The nonce has moved on, so the same signature no longer verifies: the call traps
+    let result = client.try_update_document_signed(&did_string, &document, &key_id, &1_000, &signature);
+    assert!(matches!(result, Err(Err(_))));
+    assert_eq!(client.get_nonce(&did_string), 1);
+}
+
+#[test]
+fn test_signed_update_with_invalid_signature() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:tampered");
+    let document = signer_document(&env, "did:kosmos:tampered", &signing_key);
+    client.register_did(&controller, &did_string, &document);
+
+    // The relayer swaps in a document the key holder did not sign
+    let key_id = String::from_str(&env, "#key-1");
+    let args_hash = env.crypto().sha256(&document.clone().to_xdr(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("update"), 0, 1_000, args_hash);
+    let mut tampered = document.clone();
+    tampered.service.push_back(service(&env, "#hub", "https://attacker.example.com"));
+
+    // The signature does not verify, so the call traps instead of returning an error
+    let result = client.try_update_document_signed(&did_string, &tampered, &key_id, &1_000, &signature);
+    assert!(matches!(result, Err(Err(_))));
+    assert_eq!(client.get_did(&did_string).document, document);
+    assert_eq!(client.get_nonce(&did_string), 0);
+}
+
+#[test]
+fn test_signed_registration_binds_controller_set() {
+    let env = Env::default();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let signing_key = SigningKey::from_bytes(&[8u8; 32]);
+    let public_key = BytesN::from_array(&env, &signing_key.verifying_key().to_bytes());
+    let did_string = client.derive_key_did(&public_key);
+    let mut did = [0u8; 51];
+    did_string.copy_into_slice(&mut did);
+    let did = core::str::from_utf8(&did).unwrap();
+
+    let controller = Address::generate(&env);
+    let controller_set = ControllerSet { controllers: vec![&env, controller.clone()], threshold: 1 };
+    let document = signer_document(&env, did, &signing_key);
+    let key_id = String::from_str(&env, "#key-1");
+    let args_hash = env.crypto().sha256(&(controller_set.clone(), document.clone()).to_xdr(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("register"), 0, 1_000, args_hash);
+
+    // The relayer swaps in its own controller: the signature does not verify, so the call traps
+    let relayer = Address::generate(&env);
+    let result = client.try_register_did_signed(&relayer, &vec![&env, relayer.clone()], &1, &document, &key_id, &1_000, &signature);
+    assert!(matches!(result, Err(Err(_))));
+    assert_eq!(client.try_get_did(&did_string), Err(Ok(Error::DidNotFound)));
+    assert_eq!(client.get_controlled_dids(&relayer, &0, &MAX_PAGE_SIZE).dids.len(), 0);
+
+    // The key holder's own controller set registers without anyone authorizing
+    client.register_did_signed(&relayer, &controller_set.controllers, &1, &document, &key_id, &1_000, &signature);
+    assert_eq!(client.get_controller(&did_string), controller_set);
+    assert_eq!(client.get_controlled_dids(&controller, &0, &MAX_PAGE_SIZE).dids, vec![&env, did_string]);
+}
+
+#[test]
+fn test_signed_registration_deposit_paid_by_relayer() {
+    let env = Env::default();
+    let (contract_id, client, admin) = setup_test(&env);
+
+    let token_id = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let token_client = token::Client::new(&env, &token_id);
+    let relayer = Address::generate(&env);
+    env.mock_all_auths();
+    token::StellarAssetClient::new(&env, &token_id).mint(&relayer, &100);
+    client.set_deposit(&Some(Deposit { token: token_id.clone(), amount: 30 }));
+
+    let signing_key = SigningKey::from_bytes(&[10u8; 32]);
+    let public_key = BytesN::from_array(&env, &signing_key.verifying_key().to_bytes());
+    let did_string = client.derive_key_did(&public_key);
+    let mut did = [0u8; 51];
+    did_string.copy_into_slice(&mut did);
+    let did = core::str::from_utf8(&did).unwrap();
+
+    // The controller has no funds and never authorizes; only the relayer does, for the deposit
+    let controller = Address::generate(&env);
+    let controller_set = ControllerSet { controllers: vec![&env, controller.clone()], threshold: 1 };
+    let document = signer_document(&env, did, &signing_key);
+    let key_id = String::from_str(&env, "#key-1");
+    let args_hash = env.crypto().sha256(&(controller_set.clone(), document.clone()).to_xdr(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("register"), 0, 1_000, args_hash);
+    client.register_did_signed(&relayer, &controller_set.controllers, &1, &document, &key_id, &1_000, &signature);
+
+    let auths = env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, relayer);
+    assert_ne!(auths[0].0, admin);
+    assert_eq!(token_client.balance(&relayer), 70);
+    assert_eq!(token_client.balance(&contract_id), 30);
+}
+
+#[test]
+fn test_signed_operations_require_single_controller() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    // A 2-of-2 DID whose document holds a capabilityInvocation key
+    let signing_key = SigningKey::from_bytes(&[9u8; 32]);
+    let controllers = vec![&env, Address::generate(&env), Address::generate(&env)];
+    let did_string = String::from_str(&env, "did:kosmos:two-of-two-keyed");
+    let document = signer_document(&env, "did:kosmos:two-of-two-keyed", &signing_key);
+    client.register_multi_controller_did(&controllers, &2, &did_string, &document);
+
+    // The key holder alone cannot stand in for both controllers
+    let key_id = String::from_str(&env, "#key-1");
+    let mut new_document = document.clone();
+    new_document.service.push_back(service(&env, "#hub", "https://hub.example.com"));
+    let args_hash = env.crypto().sha256(&new_document.clone().to_xdr(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("update"), 0, 1_000, args_hash);
+    assert_eq!(client.try_update_document_signed(&did_string, &new_document, &key_id, &1_000, &signature), Err(Ok(Error::NotEnoughSignatures)));
+
+    let args_hash = env.crypto().sha256(&Bytes::new(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("revoke"), 0, 1_000, args_hash);
+    assert_eq!(client.try_revoke_did_signed(&did_string, &key_id, &1_000, &signature), Err(Ok(Error::NotEnoughSignatures)));
+
+    let resolution = client.get_did(&did_string);
+    assert_eq!(resolution.status, DidStatus::Active);
+    assert_eq!(resolution.document, document);
+    assert_eq!(client.get_nonce(&did_string), 0);
+}
+
+#[test]
+fn test_signed_update_expired() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let signing_key = SigningKey::from_bytes(&[5u8; 32]);
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:late");
+    let document = signer_document(&env, "did:kosmos:late", &signing_key);
+    client.register_did(&controller, &did_string, &document);
+
+    let key_id = String::from_str(&env, "#key-1");
+    let args_hash = env.crypto().sha256(&document.clone().to_xdr(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("update"), 0, 1_000, args_hash);
+
+    env.ledger().with_mut(|li| li.timestamp = 1_001);
+    assert_eq!(client.try_update_document_signed(&did_string, &document, &key_id, &1_000, &signature), Err(Ok(Error::SignatureExpired)));
+}
+
+#[test]
+fn test_signed_update_with_authentication_key() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let signing_key = SigningKey::from_bytes(&[6u8; 32]);
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:login-only");
+    let mut document = signer_document(&env, "did:kosmos:login-only", &signing_key);
+    document.authentication = document.capability_invocation.clone();
+    document.capability_invocation = Vec::new(&env);
+    client.register_did(&controller, &did_string, &document);
+
+    let key_id = String::from_str(&env, "#key-1");
+    let args_hash = env.crypto().sha256(&document.clone().to_xdr(&env)).to_bytes();
+    let signature = sign_operation(&env, &contract_id, &signing_key, &did_string, symbol_short!("update"), 0, 1_000, args_hash);
+    assert_eq!(client.try_update_document_signed(&did_string, &document, &key_id, &1_000, &signature), Err(Ok(Error::KeyNotAuthorized)));
+}
+
+#[test]
+fn test_linked_accounts() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let did_string = String::from_str(&env, "did:kosmos:borrower");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:borrower"));
+
+    let savings = Address::generate(&env);
+    let trading = Address::generate(&env);
+    client.link_account(&did_string, &signers, &savings);
+    client.link_account(&did_string, &signers, &trading);
+
+    let events = env.events().all();
+    let (_, topics, data) = events.last().unwrap();
+    assert_eq!(topics, (symbol_short!("acct_link"), did_string.clone()).into_val(&env));
+    assert_eq!(
+        AccountLinkEvent::try_from_val(&env, &data),
+        Ok(AccountLinkEvent { event_version: EVENT_VERSION, account: trading.clone() })
+    );
+
+    assert_eq!(client.get_linked_accounts(&did_string), vec![&env, savings.clone(), trading.clone()]);
+    assert_eq!(client.get_account_did(&savings), Some(did_string.clone()));
+    assert_eq!(client.get_account_did(&controller), None);
+
+    // Unlinked by the controllers
+    client.unlink_account(&did_string, &signers, &savings);
+    assert_eq!(client.get_linked_accounts(&did_string), vec![&env, trading.clone()]);
+    assert_eq!(client.get_account_did(&savings), None);
+
+    // Or by the account itself
+    client.release_account(&trading);
+    assert_eq!(client.get_linked_accounts(&did_string).len(), 0);
+    assert_eq!(client.get_account_did(&trading), None);
+}
+
+#[test]
+#[should_panic]
+fn test_link_account_requires_account_consent() {
+    let env = Env::default();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let did_string = String::from_str(&env, "did:kosmos:claimant");
+    env.mock_all_auths();
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:claimant"));
+
+    // Only the controller authorizes; the account never consents
+    let account = Address::generate(&env);
+    client
+        .mock_auths(&[MockAuth {
+            address: &controller,
+            invoke: &MockAuthInvoke {
+                contract: &contract_id,
+                fn_name: "link_account",
+                args: (did_string.clone(), signers.clone(), account.clone()).into_val(&env),
+                sub_invokes: &[],
+            },
+        }])
+        .link_account(&did_string, &signers, &account);
+}
+
+#[test]
+fn test_link_account_to_two_dids() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let account = Address::generate(&env);
+    let mut results = Vec::new(&env);
+    for did in ["did:kosmos:first", "did:kosmos:second"] {
+        let controller = Address::generate(&env);
+        let did_string = String::from_str(&env, did);
+        client.register_did(&controller, &did_string, &document(&env, did));
+        results.push_back(client.try_link_account(&did_string, &vec![&env, controller], &account).is_ok());
+    }
+    assert_eq!(results, vec![&env, true, false]);
+    assert_eq!(client.get_account_did(&account), Some(String::from_str(&env, "did:kosmos:first")));
+}
+
+#[test]
+fn test_controlled_dids() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let co_controller = Address::generate(&env);
+    let first = String::from_str(&env, "did:kosmos:wallet-1");
+    let second = String::from_str(&env, "did:kosmos:wallet-2");
+    let shared = String::from_str(&env, "did:kosmos:wallet-shared");
+    client.register_did(&controller, &first, &document(&env, "did:kosmos:wallet-1"));
+    client.register_did(&controller, &second, &document(&env, "did:kosmos:wallet-2"));
+    client.register_multi_controller_did(
+        &vec![&env, controller.clone(), co_controller.clone()],
+        &2,
+        &shared,
+        &document(&env, "did:kosmos:wallet-shared"),
+    );
+
+    assert_eq!(client.get_controlled_did_count(&controller), 3);
+    assert_eq!(
+        client.get_controlled_dids(&controller, &0, &2),
+        ControlledDids { dids: vec![&env, first.clone(), second.clone()], next_cursor: Some(2) }
+    );
+    assert_eq!(
+        client.get_controlled_dids(&controller, &2, &2),
+        ControlledDids { dids: vec![&env, shared.clone()], next_cursor: None }
+    );
+    assert_eq!(
+        client.get_controlled_dids(&co_controller, &0, &MAX_PAGE_SIZE),
+        ControlledDids { dids: vec![&env, shared.clone()], next_cursor: None }
+    );
+
+    // Revoking a DID drops it from the index; the last DID takes its place
+    client.revoke_did(&first, &vec![&env, controller.clone()]);
+    assert_eq!(
+        client.get_controlled_dids(&controller, &0, &MAX_PAGE_SIZE),
+        ControlledDids { dids: vec![&env, shared.clone(), second.clone()], next_cursor: None }
+    );
+
+    // A transfer moves the DID to the new controller's index
+    let new_controller = Address::generate(&env);
+    client.change_controller(&second, &vec![&env, controller.clone()], &vec![&env, new_controller.clone()], &1);
+    assert_eq!(client.get_controlled_did_count(&new_controller), 0);
+    client.accept_controller(&second);
+    assert_eq!(
+        client.get_controlled_dids(&controller, &0, &MAX_PAGE_SIZE),
+        ControlledDids { dids: vec![&env, shared.clone()], next_cursor: None }
+    );
+    assert_eq!(
+        client.get_controlled_dids(&new_controller, &0, &MAX_PAGE_SIZE),
+        ControlledDids { dids: vec![&env, second], next_cursor: None }
+    );
+
+    // Deactivation empties the index of every controller
+    client.deactivate_did(&shared, &vec![&env, controller.clone(), co_controller.clone()], &0);
+    assert_eq!(client.get_controlled_did_count(&controller), 0);
+    assert_eq!(
+        client.get_controlled_dids(&co_controller, &0, &1),
+        ControlledDids { dids: Vec::new(&env), next_cursor: None }
+    );
+}
+
+#[test]
+fn test_controlled_dids_page_size() {
+    let env = Env::default();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    assert_eq!(client.try_get_controlled_dids(&controller, &0, &0), Err(Ok(Error::InvalidPageSize)));
+    assert_eq!(
+        client.try_get_controlled_dids(&controller, &0, &(MAX_PAGE_SIZE + 1)),
+        Err(Ok(Error::InvalidPageSize))
+    );
+}
+
+#[test]
+fn test_registration_deposit() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let token_id = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let token_client = token::Client::new(&env, &token_id);
+    let controller = Address::generate(&env);
+    token::StellarAssetClient::new(&env, &token_id).mint(&controller, &100);
+
+    assert_eq!(client.get_deposit(), None);
+    let deposit = Deposit { token: token_id.clone(), amount: 30 };
+    client.set_deposit(&Some(deposit.clone()));
+    assert_eq!(client.get_deposit(), Some(deposit.clone()));
+
+    let did_string = String::from_str(&env, "did:kosmos:depositor");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:depositor"));
+    assert_eq!(token_client.balance(&controller), 70);
+    assert_eq!(token_client.balance(&contract_id), 30);
+    assert_eq!(client.get_did_deposit(&did_string), Some(deposit.clone()));
+
+    // Turning the deposit off does not affect DIDs that already paid one
+    client.set_deposit(&None);
+    let free_did = client.register_derived_did(&controller, &document(&env, "did:kosmos:free"));
+    assert_eq!(client.get_did_deposit(&free_did), None);
+    assert_eq!(token_client.balance(&controller), 70);
+
+    // Revocation refunds the deposit to the controller
+    client.revoke_did(&did_string, &vec![&env, controller.clone()]);
+    assert_eq!(token_client.balance(&controller), 100);
+    assert_eq!(token_client.balance(&contract_id), 0);
+    assert_eq!(client.get_did_deposit(&did_string), None);
+
+    let events = env.events().all();
+    let (_, topics, data) = events.last().unwrap();
+    assert_eq!(topics, (symbol_short!("did_rev"), did_string.clone()).into_val(&env));
+    let (_, topics, data_before) = events.get(events.len() - 2).unwrap();
+    assert_eq!(topics, (symbol_short!("dep_ref"), did_string).into_val(&env));
+    assert_eq!(<(Address, Deposit)>::try_from_val(&env, &data_before), Ok((controller.clone(), deposit)));
+    assert_eq!(Vec::<Address>::try_from_val(&env, &data), Ok(vec![&env, controller]));
+}
+
+#[test]
+fn test_registration_deposit_refunded_to_current_controller() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let token_id = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let token_client = token::Client::new(&env, &token_id);
+    let controller = Address::generate(&env);
+    let new_controller = Address::generate(&env);
+    token::StellarAssetClient::new(&env, &token_id).mint(&controller, &50);
+    client.set_deposit(&Some(Deposit { token: token_id, amount: 50 }));
+
+    let did_string = client.register_derived_did(&controller, &document(&env, "did:kosmos:sold"));
+    client.change_controller(&did_string, &vec![&env, controller.clone()], &vec![&env, new_controller.clone()], &1);
+    client.accept_controller(&did_string);
+
+    client.deactivate_did(&did_string, &vec![&env, new_controller.clone()], &0);
+    assert_eq!(token_client.balance(&controller), 0);
+    assert_eq!(token_client.balance(&new_controller), 50);
+}
+
+#[test]
+fn test_set_invalid_deposit() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let deposit = Deposit { token: Address::generate(&env), amount: 0 };
+    assert_eq!(client.try_set_deposit(&Some(deposit)), Err(Ok(Error::InvalidDeposit)));
+    assert_eq!(client.get_deposit(), None);
+}
+
+#[test]
+fn test_anchored_did() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = vec![&env, controller.clone()];
+    let anchor = DocumentAnchor {
+        hash: BytesN::from_array(&env, &[1u8; 32]),
+        locator: String::from_str(&env, "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"),
+    };
+    env.ledger().with_mut(|li| li.timestamp = 1_000);
+    let did_string = client.register_anchored_did(&controller, &anchor);
+    assert_eq!(did_string, client.derive_did(&controller));
+
+    let events = env.events().all();
+    let (_, topics, data) = events.last().unwrap();
+    assert_eq!(topics, (symbol_short!("anc_set"), did_string.clone()).into_val(&env));
+    assert_eq!(
+        AnchorSetEvent::try_from_val(&env, &data),
+        Ok(AnchorSetEvent { event_version: EVENT_VERSION, anchor: anchor.clone() })
+    );
+
+    let resolution = client.get_did(&did_string);
+    assert_eq!(resolution.status, DidStatus::Active);
+    assert_eq!(resolution.source, DocumentSource::Anchored(anchor.clone()));
+    assert_eq!(resolution.document.verification_method.len(), 0);
+
+    // The document is edited off-chain and re-anchored
+    assert_eq!(
+        client.try_update_document(&did_string, &signers, &document(&env, "did:kosmos:anchored")),
+        Err(Ok(Error::DocumentAnchored))
+    );
+    let new_anchor = DocumentAnchor {
+        hash: BytesN::from_array(&env, &[2u8; 32]),
+        locator: String::from_str(&env, "https://example.com/did.json"),
+    };
+    env.ledger().with_mut(|li| li.timestamp = 2_000);
+    client.update_anchor(&did_string, &signers, &new_anchor);
+    assert_eq!(client.get_did(&did_string).source, DocumentSource::Anchored(new_anchor.clone()));
+
+    // Re-anchoring writes the next document version; each version keeps its anchor
+    let version = client.get_did_version(&did_string, &2);
+    assert_eq!(version.version_id, 2);
+    assert_eq!(version.timestamp, 2_000);
+    assert_eq!(version.document.verification_method.len(), 0);
+    assert_eq!(client.get_did_version(&did_string, &1).timestamp, 1_000);
+    assert_eq!(client.get_anchor_version(&did_string, &1), anchor);
+    assert_eq!(client.get_anchor_version(&did_string, &2), new_anchor);
+    assert_eq!(client.try_get_anchor_version(&did_string, &3), Err(Ok(Error::VersionNotFound)));
+    assert_eq!(client.get_did_at(&did_string, &1_500).version_id, 1);
+}
+
+#[test]
+fn test_invalid_anchor() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let hash = BytesN::from_array(&env, &[1u8; 32]);
+    for locator in ["", "ipfs://", "ftp://example.com/did.json", "HTTPS://example.com/did.json"] {
+        let anchor = DocumentAnchor { hash: hash.clone(), locator: String::from_str(&env, locator) };
+        assert_eq!(client.try_register_anchored_did(&controller, &anchor), Err(Ok(Error::InvalidAnchor)));
+    }
+
+    // Only hash-anchored DIDs can be re-anchored
+    let did_string = String::from_str(&env, "did:kosmos:on-chain");
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:on-chain"));
+    let anchor = DocumentAnchor { hash, locator: String::from_str(&env, "https://example.com/did.json") };
+    assert_eq!(
+        client.try_update_anchor(&did_string, &vec![&env, controller], &anchor),
+        Err(Ok(Error::NotAnchored))
+    );
+    assert_eq!(client.get_did(&did_string).source, DocumentSource::OnChain);
+    assert_eq!(client.try_get_anchor_version(&did_string, &1), Err(Ok(Error::NotAnchored)));
+}
+
+#[test]
+fn test_upgrade_and_migrate_from_instance_storage() {
+    let env = Env::default();
+    env.mock_all_auths();
+    // The WASM is metered as on the network, where uploading it is a transaction of its own
+    env.budget().reset_unlimited();
+
+    // Deploy the registry from its own WASM, so that the upgrade installs real code and
+    // migrate runs in it
+    let wasm = registry_wasm();
+    let contract_id = env.register_contract_wasm(None, wasm.as_slice());
+    let client = DidRegistryContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+
+    // Lay out four DIDs the way schema version 1 did, in instance storage. Version 1
+    // documents were untyped maps: two of them hold `DidDocument` fields, one holds a value
+    // of the wrong type and one has no verification method
+    let controller = Address::generate(&env);
+    let active_did = String::from_str(&env, "did:kosmos:legacy-active");
+    let revoked_did = String::from_str(&env, "did:kosmos:legacy-revoked");
+    let unconvertible_did = String::from_str(&env, "did:kosmos:legacy-unconvertible");
+    let invalid_did = String::from_str(&env, "did:kosmos:legacy-invalid");
+    let mut converted_document = document(&env, "did:kosmos:legacy");
+    converted_document.authentication.push_back(String::from_str(&env, "#key-1"));
+    let mut legacy_document: Map<Symbol, soroban_sdk::Val> = Map::new(&env);
+    legacy_document.set(Symbol::new(&env, "verification_method"), converted_document.verification_method.to_val());
+    legacy_document.set(Symbol::new(&env, "authentication"), converted_document.authentication.to_val());
+    let mut unconvertible_document: Map<Symbol, soroban_sdk::Val> = Map::new(&env);
+    unconvertible_document.set(symbol_short!("service"), String::from_str(&env, "https://example.com/endpoint").to_val());
+    let mut invalid_document: Map<Symbol, soroban_sdk::Val> = Map::new(&env);
+    invalid_document.set(Symbol::new(&env, "authentication"), converted_document.authentication.to_val());
+    env.as_contract(&contract_id, || {
+        let instance = env.storage().instance();
+        for (did, document, status) in [
+            (&active_did, &legacy_document, 1u32),
+            (&revoked_did, &legacy_document, 2u32),
+            (&unconvertible_did, &unconvertible_document, 1u32),
+            (&invalid_did, &invalid_document, 1u32),
+        ] {
+            instance.set(&DataKey::Controller(did.clone()), &controller);
+            instance.set(&(2u32, did.clone()), document);
+            instance.set(&DataKey::Status(did.clone()), &status);
+        }
+        instance.remove(&DataKey::SchemaVersion);
+    });
+    assert_eq!(client.get_schema_version(), 1);
+
+    // Nobody can register them again while they wait to be migrated
+    assert_eq!(
+        client.try_register_did(&Address::generate(&env), &active_did, &document(&env, "did:kosmos:legacy-active")),
+        Err(Ok(Error::DidAlreadyRegistered))
+    );
+
+    // Upgrade to newly uploaded code, then migrate
+    let wasm_hash = env.deployer().upload_contract_wasm(Bytes::from_slice(&env, &wasm));
+    client.upgrade(&wasm_hash);
+    assert_eq!(env.auths()[0].0, admin);
+    let legacy_dids = vec![&env, active_did.clone(), revoked_did.clone(), unconvertible_did.clone(), invalid_did.clone()];
+    assert_eq!(
+        client.migrate(&legacy_dids),
+        vec![
+            &env,
+            MigrationResult::Migrated,
+            MigrationResult::Migrated,
+            MigrationResult::Failed(Error::InvalidDocument as u32),
+            MigrationResult::Failed(Error::InvalidDocument as u32),
+        ]
+    );
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+
+    // The converted DIDs resolve, keeping their controller, status and document
+    for (did, status) in [(&active_did, DidStatus::Active), (&revoked_did, DidStatus::Revoked)] {
+        assert_eq!(client.get_controller(did), ControllerSet { controllers: vec![&env, controller.clone()], threshold: 1 });
+        assert_eq!(client.get_status(did), status);
+        let resolution = client.get_did(did);
+        assert_eq!(resolution.status, status);
+        assert_eq!(resolution.source, DocumentSource::OnChain);
+        assert_eq!(resolution.document, converted_document);
+        assert_eq!(client.get_did_version(did, &1).version_id, 1);
+        assert_eq!(client.get_legacy_document(did), Some(legacy_document.clone()));
+    }
+    assert_eq!(client.get_controlled_did_count(&controller), 1);
+    env.as_contract(&contract_id, || {
+        let instance = env.storage().instance();
+        assert!(!instance.has(&DataKey::Controller(active_did.clone())));
+        assert!(!instance.has(&(2u32, active_did.clone())));
+        assert!(!instance.has(&DataKey::Status(active_did.clone())));
+    });
+
+    // The others are left in the version 1 layout, still reserved
+    for did in [&unconvertible_did, &invalid_did] {
+        assert_eq!(client.try_get_did(did), Err(Ok(Error::DidNotFound)));
+        env.as_contract(&contract_id, || assert!(env.storage().instance().has(&DataKey::Controller(did.clone()))));
+    }
+    assert_eq!(
+        client.try_register_did(&Address::generate(&env), &invalid_did, &document(&env, "did:kosmos:legacy-invalid")),
+        Err(Ok(Error::DidAlreadyRegistered))
+    );
+
+    // Their controller migrates them with a document of their own
+    assert_eq!(
+        client.try_migrate_legacy_did(&unconvertible_did, &DidDocument { verification_method: Vec::new(&env), ..document(&env, "did:kosmos:legacy-unconvertible") }),
+        Err(Ok(Error::InvalidDocument))
+    );
+    let replacement = document(&env, "did:kosmos:legacy-unconvertible");
+    client.migrate_legacy_did(&unconvertible_did, &replacement);
+    assert_eq!(env.auths()[0].0, controller);
+    assert_eq!(client.get_did(&unconvertible_did).document, replacement);
+    assert_eq!(client.get_legacy_document(&unconvertible_did), Some(unconvertible_document));
+    assert_eq!(client.get_controlled_did_count(&controller), 2);
+    assert_eq!(client.try_migrate_legacy_did(&active_did, &replacement), Err(Ok(Error::DidNotFound)));
+
+    // The controller can now publish a current document
+    let new_document = document(&env, "did:kosmos:legacy-active");
+    client.update_document(&active_did, &vec![&env, controller.clone()], &new_document);
+
+    // Migrating the same DIDs again changes nothing
+    assert_eq!(
+        client.migrate(&legacy_dids),
+        vec![
+            &env,
+            MigrationResult::Skipped,
+            MigrationResult::Skipped,
+            MigrationResult::Skipped,
+            MigrationResult::Failed(Error::InvalidDocument as u32),
+        ]
+    );
+    assert_eq!(client.get_did(&active_did).document, new_document);
+    assert_eq!(client.get_controlled_did_count(&controller), 2);
+
+    // Storage written by a newer version is left alone
+    env.as_contract(&contract_id, || {
+        env.storage().instance().set(&DataKey::SchemaVersion, &(SCHEMA_VERSION + 1))
+    });
+    assert_eq!(client.try_migrate(&Vec::new(&env)), Err(Ok(Error::UnsupportedSchemaVersion)));
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION + 1);
+}
+
+#[test]
+fn test_event_stream_reconstructs_state() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let new_controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:indexed");
+    let signers = vec![&env, controller.clone()];
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:indexed"));
+    env.ledger().with_mut(|li| li.timestamp = 100);
+    client.add_service(&did_string, &signers, &service(&env, "#home", "https://example.com"));
+    client.suspend_did(&did_string, &signers, &7);
+    client.reactivate_did(&did_string, &signers);
+    client.change_controller(&did_string, &signers, &vec![&env, new_controller.clone()], &1);
+    client.accept_controller(&did_string);
+    client.deactivate_did(&did_string, &vec![&env, new_controller], &9);
+
+    // Rebuild the DID from the registry's events alone
+    let mut controller_set = None;
+    let mut versions: Vec<DocumentVersion> = Vec::new(&env);
+    let mut history: Vec<StatusChange> = Vec::new(&env);
+    for (contract, topics, data) in env.events().all().iter() {
+        if contract != contract_id {
+            continue;
+        }
+        let topic = Symbol::try_from_val(&env, &topics.get(0).unwrap()).unwrap();
+        if topic == symbol_short!("did_reg") {
+            let event = DidRegisteredEvent::try_from_val(&env, &data).unwrap();
+            assert_eq!(event.event_version, EVENT_VERSION);
+            controller_set = Some(event.controller_set);
+        } else if topic == symbol_short!("did_upd") {
+            let event = DocumentUpdatedEvent::try_from_val(&env, &data).unwrap();
+            assert_eq!(event.document_hash, env.crypto().sha256(&event.document.clone().to_xdr(&env)).to_bytes());
+            versions.push_back(DocumentVersion {
+                version_id: event.version_id,
+                document: event.document,
+                ledger_sequence: event.ledger_sequence,
+                timestamp: event.timestamp,
+            });
+        } else if topic == symbol_short!("did_stat") {
+            let event = StatusChangedEvent::try_from_val(&env, &data).unwrap();
+            history.push_back(StatusChange {
+                status: event.status,
+                reason: event.reason,
+                ledger_sequence: event.ledger_sequence,
+                timestamp: event.timestamp,
+            });
+        } else if topic == symbol_short!("ctl_chg") {
+            let event = ControllerChangedEvent::try_from_val(&env, &data).unwrap();
+            assert_eq!(Some(event.previous_controller_set), controller_set);
+            controller_set = Some(event.controller_set);
+        }
+    }
+
+    assert_eq!(controller_set, Some(client.get_controller(&did_string)));
+    assert_eq!(history, client.get_status_history(&did_string));
+    assert_eq!(versions.len(), 2);
+    for version in versions.iter() {
+        assert_eq!(version, client.get_did_version(&did_string, &version.version_id));
+    }
+}
+
+#[test]
+fn test_event_stream_reconstructs_delegation_and_recovery_state() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (contract_id, client, _) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did_string = String::from_str(&env, "did:kosmos:guarded");
+    let signers = vec![&env, controller.clone()];
+    client.register_did(&controller, &did_string, &document(&env, "did:kosmos:guarded"));
+
+    // Delegates and linked accounts
+    let (issuer, verifier) = (Address::generate(&env), Address::generate(&env));
+    client.add_delegate(&did_string, &signers, &issuer, &symbol_short!("sigAuth"), &86_400);
+    client.add_delegate(&did_string, &signers, &verifier, &symbol_short!("veriKey"), &86_400);
+    client.add_delegate(&did_string, &signers, &issuer, &symbol_short!("veriKey"), &3_600);
+    client.revoke_delegate(&did_string, &signers, &issuer, &symbol_short!("sigAuth"));
+    let (savings, trading) = (Address::generate(&env), Address::generate(&env));
+    client.link_account(&did_string, &signers, &savings);
+    client.link_account(&did_string, &signers, &trading);
+    client.release_account(&savings);
+
+    // A recovery that is cancelled, then one that goes through
+    let guardians = vec![&env, Address::generate(&env), Address::generate(&env), Address::generate(&env)];
+    client.set_guardians(&did_string, &signers, &guardians, &2, &1_000);
+    let recovered = vec![&env, Address::generate(&env)];
+    client.approve_recovery(&did_string, &guardians.get(0).unwrap(), &recovered, &1);
+    client.cancel_recovery(&did_string, &signers);
+    client.change_controller(&did_string, &signers, &vec![&env, Address::generate(&env)], &1);
+    client.approve_recovery(&did_string, &guardians.get(1).unwrap(), &recovered, &1);
+    client.approve_recovery(&did_string, &guardians.get(2).unwrap(), &recovered, &1);
+    env.ledger().with_mut(|li| li.timestamp += 1_000);
+    client.execute_recovery(&did_string);
+
+    // Pre-rotation, with a proposal that the commitment drops
+    let signers = recovered.clone();
+    let next = vec![&env, Address::generate(&env)];
+    let after_next = vec![&env, Address::generate(&env)];
+    client.change_controller(&did_string, &signers, &vec![&env, Address::generate(&env)], &1);
+    client.commit_next_controller(&did_string, &signers, &client.controller_commitment(&next, &1));
+    client.rotate_controller(&did_string, &next, &1, &client.controller_commitment(&after_next, &1));
+
+    // Rebuild everything but the controllers, document and status from the events alone
+    let mut delegates: Vec<Delegate> = Vec::new(&env);
+    let mut accounts: Vec<Address> = Vec::new(&env);
+    let mut guardian_set = None;
+    let mut recovery: Option<RecoveryRequest> = None;
+    let mut pending_controller = None;
+    let mut commitment = None;
+    for (contract, topics, data) in env.events().all().iter() {
+        if contract != contract_id {
+            continue;
+        }
+        let topic = Symbol::try_from_val(&env, &topics.get(0).unwrap()).unwrap();
+        if topic == symbol_short!("dlg_add") {
+            let event = DelegateAddedEvent::try_from_val(&env, &data).unwrap();
+            assert_eq!(event.event_version, EVENT_VERSION);
+            if let Some(i) = delegates.iter().position(|d| d.delegate == event.delegate.delegate && d.delegate_type == event.delegate.delegate_type) {
+                delegates.remove(i as u32);
+            }
+            delegates.push_back(event.delegate);
+        } else if topic == symbol_short!("dlg_rev") {
+            let event = DelegateRevokedEvent::try_from_val(&env, &data).unwrap();
+            let i = delegates.iter().position(|d| d.delegate == event.delegate && d.delegate_type == event.delegate_type).unwrap();
+            delegates.remove(i as u32);
+        } else if topic == symbol_short!("acct_link") {
+            accounts.push_back(AccountLinkEvent::try_from_val(&env, &data).unwrap().account);
+        } else if topic == symbol_short!("acct_unl") {
+            let account = AccountLinkEvent::try_from_val(&env, &data).unwrap().account;
+            accounts.remove(accounts.first_index_of(&account).unwrap());
+        } else if topic == symbol_short!("grd_set") {
+            guardian_set = Some(GuardiansSetEvent::try_from_val(&env, &data).unwrap().guardian_set);
+            recovery = None;
+        } else if topic == symbol_short!("rec_init") {
+            let event = RecoveryInitiatedEvent::try_from_val(&env, &data).unwrap();
+            recovery = Some(RecoveryRequest {
+                new_controller_set: event.new_controller_set,
+                approvals: Vec::new(&env),
+                initiated_at: event.initiated_at,
+                executable_at: 0,
+            });
+        } else if topic == symbol_short!("rec_appr") {
+            let event = RecoveryApprovedEvent::try_from_val(&env, &data).unwrap();
+            recovery.as_mut().unwrap().approvals.push_back(event.guardian);
+        } else if topic == symbol_short!("rec_ready") {
+            recovery.as_mut().unwrap().executable_at = RecoveryReadyEvent::try_from_val(&env, &data).unwrap().executable_at;
+        } else if topic == symbol_short!("rec_cncl") {
+            assert_eq!(RecoveryCancelledEvent::try_from_val(&env, &data).unwrap().signers, vec![&env, controller.clone()]);
+            recovery = None;
+        } else if topic == symbol_short!("rec_done") {
+            let event = RecoveryExecutedEvent::try_from_val(&env, &data).unwrap();
+            assert_eq!(event.controller_set.controllers, recovered);
+            recovery = None;
+            pending_controller = None;
+            commitment = None;
+        } else if topic == symbol_short!("ctl_prop") {
+            pending_controller = Some(ControllerProposedEvent::try_from_val(&env, &data).unwrap().controller_set);
+        } else if topic == symbol_short!("ctl_cmt") {
+            commitment = Some(ControllerCommittedEvent::try_from_val(&env, &data).unwrap().commitment);
+            pending_controller = None;
+        }
+    }
+
+    assert_eq!(delegates, client.get_did(&did_string).delegates);
+    assert_eq!(delegates.len(), 2);
+    assert_eq!(accounts, client.get_linked_accounts(&did_string));
+    assert_eq!(accounts, vec![&env, trading]);
+    assert_eq!(guardian_set, client.get_guardians(&did_string));
+    assert_eq!(recovery, client.get_recovery(&did_string));
+    assert_eq!(pending_controller, client.get_pending_controller(&did_string));
+    assert_eq!(commitment, client.get_next_controller_commitment(&did_string));
+    assert_eq!(commitment, Some(client.controller_commitment(&after_next, &1)));
+}
+
+#[test]
+fn test_batch_registration() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, admin) = setup_test(&env);
+
+    let university = Address::generate(&env);
+    let registrar = Address::generate(&env);
+    let bystander = Address::generate(&env);
+    let taken = String::from_str(&env, "did:kosmos:taken");
+    client.register_did(&Address::generate(&env), &taken, &document(&env, "did:kosmos:taken"));
+
+    let entry = |did: &str, controllers: Vec<Address>, threshold: u32| DidRegistration {
+        did: String::from_str(&env, did),
+        controllers,
+        threshold,
+        document: document(&env, did),
+    };
+    let mut empty_document = entry("did:kosmos:carol", vec![&env, Address::generate(&env)], 1);
+    empty_document.document.verification_method = Vec::new(&env);
+    let registrations = vec![
+        &env,
+        entry("did:kosmos:alice", vec![&env, university.clone()], 1),
+        entry("did:kosmos:bob", vec![&env, university.clone(), registrar.clone()], 2),
+        empty_document,
+    ];
+    let results = client.register_dids(&registrations);
+
+    assert_eq!(
+        results,
+        vec![
+            &env,
+            RegistrationResult::Registered,
+            RegistrationResult::Registered,
+            RegistrationResult::Failed(Error::InvalidDocument as u32),
+        ]
+    );
+
+    // The admin and each controller of a registered entry authorized once; the controller
+    // of the invalid entry was not asked to
+    let auths = env.auths();
+    assert_eq!(auths.len(), 3);
+    assert_eq!((&auths[0].0, &auths[1].0, &auths[2].0), (&admin, &university, &registrar));
+
+    assert_eq!(client.get_did(&String::from_str(&env, "did:kosmos:bob")).status, DidStatus::Active);
+    assert_eq!(client.get_controlled_did_count(&university), 2);
+    assert_eq!(client.try_get_did(&String::from_str(&env, "did:kosmos:carol")), Err(Ok(Error::DidNotFound)));
+
+    // Taken DIDs, including ones taken earlier in the same batch, are skipped
+    let registrations = vec![
+        &env,
+        entry("did:kosmos:taken", vec![&env, bystander.clone()], 1),
+        entry("did:kosmos:dave", vec![&env, registrar.clone()], 1),
+        entry("did:kosmos:dave", vec![&env, bystander.clone()], 1),
+    ];
+    let results = client.register_dids(&registrations);
+
+    assert_eq!(
+        results,
+        vec![
+            &env,
+            RegistrationResult::Failed(Error::DidAlreadyRegistered as u32),
+            RegistrationResult::Registered,
+            RegistrationResult::Failed(Error::DidAlreadyRegistered as u32),
+        ]
+    );
+    let auths = env.auths();
+    assert_eq!(auths.len(), 2);
+    assert_eq!((&auths[0].0, &auths[1].0), (&admin, &registrar));
+    assert_eq!(client.get_controlled_did_count(&registrar), 2);
+    assert_eq!(client.get_controlled_did_count(&bystander), 0);
+}
+
+/// Calls `register_dids` and measures what the network limits per transaction: the ledger
+/// entries the call wrote, authorization nonces included, and the size of the events it
+/// published.
+fn register_batch(env: &Env, client: &DidRegistryContractClient, registrations: &Vec<DidRegistration>) -> (Vec<RegistrationResult>, u32, u32) {
+    let before = env.to_ledger_snapshot().ledger_entries;
+    let published = env.host().get_events().unwrap().0.len();
+    let results = client.register_dids(registrations);
+
+    let writes = env
+        .to_ledger_snapshot()
+        .ledger_entries
+        .iter()
+        .filter(|(key, (entry, _))| !before.iter().any(|(old_key, (old_entry, _))| old_key == key && old_entry.data == entry.data))
+        .count() as u32;
+    let event_bytes = env
+        .host()
+        .get_events()
+        .unwrap()
+        .0
+        .iter()
+        .skip(published)
+        .filter(|event| !event.failed_call && event.event.type_ == ContractEventType::Contract)
+        .map(|event| event.event.to_xdr(Limits::none()).unwrap().len() as u32)
+        .sum();
+
+    (results, writes, event_bytes)
+}
+
+#[test]
+fn test_batch_size() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    assert_eq!(client.try_register_dids(&Vec::new(&env)), Err(Ok(Error::InvalidBatchSize)));
+
+    let controller = Address::generate(&env);
+    let mut registrations = Vec::new(&env);
+    let members = ["did:kosmos:member-1", "did:kosmos:member-2", "did:kosmos:member-3", "did:kosmos:member-4"];
+    for did in members.iter().take(MAX_BATCH_SIZE as usize + 1) {
+        registrations.push_back(DidRegistration {
+            did: String::from_str(&env, did),
+            controllers: vec![&env, controller.clone()],
+            threshold: 1,
+            document: document(&env, did),
+        });
+    }
+    assert_eq!(client.try_register_dids(&registrations), Err(Ok(Error::InvalidBatchSize)));
+
+    // A full batch fits within the default budget and the per-transaction limits
+    registrations.pop_back();
+    env.budget().reset_default();
+    let (results, writes, event_bytes) = register_batch(&env, &client, &registrations);
+    assert!(results.iter().all(|result| result == RegistrationResult::Registered));
+    assert!(writes <= TX_MAX_WRITE_ENTRIES);
+    assert!(event_bytes <= TX_MAX_EVENTS_SIZE_BYTES);
+    assert_eq!(client.get_controlled_did_count(&controller), MAX_BATCH_SIZE);
+}
+
+#[test]
+fn test_batch_within_transaction_limits() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    // Every new controller adds its DID count and authorization nonce to the writes, so the
+    // third entry no longer fits
+    let entry = |did: &str, controller: &Address| DidRegistration {
+        did: String::from_str(&env, did),
+        controllers: vec![&env, controller.clone()],
+        threshold: 1,
+        document: document(&env, did),
+    };
+    let registrations = vec![
+        &env,
+        entry("did:kosmos:one", &Address::generate(&env)),
+        entry("did:kosmos:two", &Address::generate(&env)),
+        entry("did:kosmos:three", &Address::generate(&env)),
+    ];
+    let (results, writes, event_bytes) = register_batch(&env, &client, &registrations);
+    assert_eq!(
+        results,
+        vec![&env, RegistrationResult::Registered, RegistrationResult::Registered, RegistrationResult::Failed(Error::BatchLimitExceeded as u32)]
+    );
+    assert!(writes <= TX_MAX_WRITE_ENTRIES);
+    assert!(event_bytes <= TX_MAX_EVENTS_SIZE_BYTES);
+
+    // The skipped entry goes through in the next batch
+    let (results, _, _) = register_batch(&env, &client, &vec![&env, registrations.get(2).unwrap()]);
+    assert_eq!(results, vec![&env, RegistrationResult::Registered]);
+
+    // Large documents run into the event size limit instead
+    let controller = Address::generate(&env);
+    let mut registrations = Vec::new(&env);
+    for did in ["did:kosmos:large-1", "did:kosmos:large-2", "did:kosmos:large-3"] {
+        let mut registration = entry(did, &controller);
+        for id in ["#s0", "#s1", "#s2", "#s3", "#s4", "#s5", "#s6", "#s7", "#s8", "#s9"] {
+            registration.document.service.push_back(service(&env, id, "https://hub.example.com/a/fairly/long/path/to/the/service/endpoint/of/this/decentralized/identifier"));
+        }
+        registrations.push_back(registration);
+    }
+    let (results, writes, event_bytes) = register_batch(&env, &client, &registrations);
+    assert_eq!(
+        results,
+        vec![&env, RegistrationResult::Registered, RegistrationResult::Registered, RegistrationResult::Failed(Error::BatchLimitExceeded as u32)]
+    );
+    assert!(writes <= TX_MAX_WRITE_ENTRIES);
+    assert!(event_bytes <= TX_MAX_EVENTS_SIZE_BYTES);
+}
+
+#[test]
+fn test_batch_with_deposit_within_transaction_limits() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let token_id = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let controller = Address::generate(&env);
+    token::StellarAssetClient::new(&env, &token_id).mint(&controller, &100);
+    client.set_deposit(&Some(Deposit { token: token_id, amount: 30 }));
+
+    // Each deposit adds its record to the writes, and the balances of the payer and the
+    // registry the first time; the third entry no longer fits
+    let mut registrations = Vec::new(&env);
+    for did in ["did:kosmos:paid-1", "did:kosmos:paid-2", "did:kosmos:paid-3"] {
+        registrations.push_back(DidRegistration {
+            did: String::from_str(&env, did),
+            controllers: vec![&env, controller.clone()],
+            threshold: 1,
+            document: document(&env, did),
+        });
+    }
+    let (results, writes, event_bytes) = register_batch(&env, &client, &registrations);
+    assert_eq!(
+        results,
+        vec![&env, RegistrationResult::Registered, RegistrationResult::Registered, RegistrationResult::Failed(Error::BatchLimitExceeded as u32)]
+    );
+    assert!(writes <= TX_MAX_WRITE_ENTRIES);
+    assert!(event_bytes <= TX_MAX_EVENTS_SIZE_BYTES);
 }
@@ -0,0 +1,226 @@
+//! Just enough HTTP/1.x for the driver: parsing requests and writing responses on the
+//! server side, and a plain-HTTP `POST` for talking to Soroban RPC.
+
+use std::io::{self, BufRead, Read, Write};
+use std::net::{Ipv6Addr, TcpStream};
+use std::time::Duration;
+
+/// Requests whose head (request line and headers) is larger than this are rejected.
+const MAX_HEAD_LEN: usize = 8 * 1024;
+
+/// How long a client call waits on a socket before giving up.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The head of an HTTP request. The body, if any, is left unread.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// The request target as sent, e.g. `/1.0/identifiers/did:kosmos:alice?versionId=1`.
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Reads a request line and its headers.
+    pub fn read(reader: &mut impl BufRead) -> io::Result<Self> {
+        let mut head_len = 0;
+        let request_line = read_head_line(reader, &mut head_len)?;
+        let mut parts = request_line.split(' ');
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+                (method.to_string(), target.to_string())
+            }
+            _ => return Err(invalid_data("malformed request line")),
+        };
+
+        let mut headers = Vec::new();
+        loop {
+            let line = read_head_line(reader, &mut head_len)?;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').ok_or_else(|| invalid_data("malformed header"))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        Ok(Request { method, target, headers })
+    }
+
+    /// Returns the first header called `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// The declared length of the request body.
+    pub fn content_length(&self) -> Option<usize> {
+        self.header("content-length").and_then(|value| value.parse().ok())
+    }
+}
+
+/// Reads one line of a request head, counting it against `MAX_HEAD_LEN`.
+fn read_head_line(reader: &mut impl BufRead, head_len: &mut usize) -> io::Result<String> {
+    let mut line = String::new();
+    *head_len += reader.take((MAX_HEAD_LEN - *head_len) as u64).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(invalid_data("incomplete or oversized request head"));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// An HTTP response. Every response closes the connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Response { status, headers: vec![("Content-Type".into(), content_type.into())], body: body.into() }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason_phrase(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", self.body.len()));
+
+        writer.write_all(head.as_bytes())?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        410 => "Gone",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        _ => "",
+    }
+}
+
+/// A parsed `http://` URL. TLS is not supported: run the driver next to its RPC node, or
+/// behind a TLS-terminating proxy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpUrl {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl HttpUrl {
+    /// Parses `http://host[:port][/path]`. An IPv6 host must be written in brackets, as in
+    /// `http://[::1]:8000/`, and is stored without them.
+    pub fn parse(url: &str) -> Option<Self> {
+        let rest = url.strip_prefix("http://")?;
+        let (authority, path) = match rest.find('/') {
+            Some(at) => (&rest[..at], &rest[at..]),
+            None => (rest, "/"),
+        };
+        if authority.contains('@') {
+            return None;
+        }
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, after) = bracketed.split_once(']')?;
+                host.parse::<Ipv6Addr>().ok()?;
+                match after {
+                    "" => (host, 80),
+                    _ => (host, after.strip_prefix(':')?.parse().ok()?),
+                }
+            }
+            // An unbracketed IPv6 address leaves an empty host or an unparsable port
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, port.parse().ok()?),
+                None => (authority, 80),
+            },
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(HttpUrl { host: host.to_string(), port, path: path.to_string() })
+    }
+
+    /// The host and port as written in a `Host` header, with an IPv6 host in brackets.
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Sends a `POST` and returns the response status and body.
+///
+/// The request is sent as HTTP/1.0, so the server answers with an unchunked body and
+/// closes the connection when it is done.
+pub fn post(url: &HttpUrl, content_type: &str, body: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port))?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let head = format!(
+        "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        url.path,
+        url.authority(),
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let head_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid_data("incomplete response"))?;
+    let status = std::str::from_utf8(&response[..head_end])
+        .ok()
+        .and_then(|head| head.lines().next()?.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid_data("malformed status line"))?;
+
+    Ok((status, response.split_off(head_end + 4)))
+}
+
+/// Decodes `%XX` escapes. Returns `None` for malformed escapes or non-UTF-8 results.
+pub fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3).filter(|hex| hex.bytes().all(|c| c.is_ascii_hexdigit()))?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
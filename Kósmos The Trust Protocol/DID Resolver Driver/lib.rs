@@ -0,0 +1,255 @@
+//! A DIF Universal Resolver driver for `did:kosmos`.
+//!
+//! Serves `GET /1.0/identifiers/{did}` over HTTP, resolving DIDs with a [`Resolver`] that
+//! reads the registry through Soroban RPC ([`rpc::RpcBackend`]). The representation is
+//! negotiated from the `Accept` header:
+//!
+//! - `application/did+ld+json` or `application/did+json`: the DID document alone;
+//! - `application/ld+json;profile="https://w3id.org/did-resolution"`: the whole DID
+//!   Resolution result. This is also the default, and is always used for errors.
+//!
+//! The `versionId` and `versionTime` DID parameters are accepted as query parameters.
+//!
+//! With the `testutils` feature, [`testutils::StandInRpc`] serves the part of Soroban RPC
+//! the driver uses from a soroban test `Env`, so the driver can be tested without a network.
+
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use did_resolver::ledger::LedgerBackend;
+use did_resolver::{parse_timestamp, ErrorCode, ResolutionOptions, ResolutionResult, Resolver};
+
+pub mod http;
+pub mod rpc;
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
+#[cfg(test)]
+mod test;
+
+use http::{percent_decode, Request, Response};
+
+pub const IDENTIFIERS_PATH: &str = "/1.0/identifiers/";
+pub const DID_JSON: &str = "application/did+json";
+pub const DID_LD_JSON: &str = "application/did+ld+json";
+pub const RESOLUTION_RESULT: &str = "application/ld+json;profile=\"https://w3id.org/did-resolution\"";
+const RESOLUTION_PROFILE: &str = "https://w3id.org/did-resolution";
+
+/// How many connections `Driver::serve` handles at once unless set with `with_workers`.
+pub const DEFAULT_WORKERS: usize = 16;
+
+/// How long a connection may take to send its request, and to take in the response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait before accepting again after a failed accept, e.g. when the process
+/// is out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The representations the driver can respond with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Representation {
+    DidLdJson,
+    DidJson,
+    ResolutionResult,
+}
+
+impl Representation {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Representation::DidLdJson => DID_LD_JSON,
+            Representation::DidJson => DID_JSON,
+            Representation::ResolutionResult => RESOLUTION_RESULT,
+        }
+    }
+
+    /// Picks the representation for an `Accept` header: the supported media range with the
+    /// highest quality, the first one on ties. Returns `None` when nothing acceptable is
+    /// supported.
+    pub fn negotiate(accept: Option<&str>) -> Option<Self> {
+        let accept = match accept.map(str::trim) {
+            None | Some("") => return Some(Representation::ResolutionResult),
+            Some(accept) => accept,
+        };
+
+        let mut best: Option<(Representation, f32)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+            let mut quality = 1.0;
+            let mut profile = None;
+            for param in params {
+                match param.split_once('=') {
+                    Some((name, value)) if name.trim().eq_ignore_ascii_case("q") => {
+                        quality = value.trim().parse().unwrap_or(0.0);
+                    }
+                    Some((name, value)) if name.trim().eq_ignore_ascii_case("profile") => {
+                        profile = Some(value.trim().trim_matches('"'));
+                    }
+                    _ => {}
+                }
+            }
+
+            let representation = match media_type.as_str() {
+                DID_LD_JSON => Representation::DidLdJson,
+                DID_JSON => Representation::DidJson,
+                "application/ld+json" if profile == Some(RESOLUTION_PROFILE) => Representation::ResolutionResult,
+                "*/*" | "application/*" => Representation::ResolutionResult,
+                _ => continue,
+            };
+            if quality > 0.0 && !matches!(best, Some((_, q)) if q >= quality) {
+                best = Some((representation, quality));
+            }
+        }
+        best.map(|(representation, _)| representation)
+    }
+}
+
+/// Serves DID resolution requests.
+pub struct Driver<B> {
+    resolver: Resolver<B>,
+    workers: usize,
+}
+
+impl<B: LedgerBackend> Driver<B> {
+    pub fn new(resolver: Resolver<B>) -> Self {
+        Driver { resolver, workers: DEFAULT_WORKERS }
+    }
+
+    /// Sets how many connections `serve` handles at once (at least one).
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Handles one request.
+    pub fn handle(&self, request: &Request) -> Response {
+        if request.method != "GET" {
+            return Response::new(405, "text/plain", "method not allowed").with_header("Allow", "GET");
+        }
+        let (path, query) = request.target.split_once('?').unwrap_or((&request.target, ""));
+        let did = match path.strip_prefix(IDENTIFIERS_PATH) {
+            Some(did) => did,
+            None => return Response::new(404, "text/plain", "not found"),
+        };
+
+        let did = match percent_decode(did) {
+            Some(did) => did,
+            None => return error_response(ErrorCode::InvalidDid, "DID is not correctly percent-encoded"),
+        };
+        let options = match resolution_options(query) {
+            Some(options) => options,
+            None => return error_response(ErrorCode::InvalidOptions, "versionId or versionTime is malformed"),
+        };
+        let representation = match Representation::negotiate(request.header("accept")) {
+            Some(representation) => representation,
+            None => return error_response(ErrorCode::RepresentationNotSupported, "no acceptable representation"),
+        };
+
+        let result = self.resolver.resolve_with(&did, &options);
+        if result.did_resolution_metadata.error.is_some() {
+            return result_response(result);
+        }
+        let status = if result.did_document_metadata.deactivated == Some(true) { 410 } else { 200 };
+        match (representation, &result.did_document) {
+            (Representation::ResolutionResult, _) | (_, None) => Response { status, ..result_response(result) },
+            (_, Some(document)) => Response::new(status, representation.content_type(), document.to_string()),
+        }
+    }
+}
+
+impl<B: LedgerBackend + Send + Sync + 'static> Driver<B> {
+    /// Accepts connections from `listener` forever and handles them on a fixed pool of
+    /// worker threads (see `with_workers`). While every worker is busy, new connections
+    /// wait in the listener's backlog. Failures to accept a connection are logged to
+    /// stderr and do not stop the driver.
+    pub fn serve(self, listener: TcpListener) {
+        let workers = self.workers;
+        let driver = Arc::new(self);
+        // A rendezvous channel: a connection is only accepted once a worker can take it
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(0);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..workers {
+            let driver = driver.clone();
+            let receiver = receiver.clone();
+            thread::spawn(move || loop {
+                let stream = match receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => return,
+                };
+                match stream {
+                    Ok(stream) => driver.handle_connection(stream),
+                    Err(_) => return,
+                }
+            });
+        }
+
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if sender.send(stream).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    eprintln!("did:kosmos driver: failed to accept a connection: {}", err);
+                    thread::sleep(ACCEPT_BACKOFF);
+                }
+            }
+        }
+    }
+
+    fn handle_connection(&self, stream: TcpStream) {
+        if stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err()
+            || stream.set_write_timeout(Some(RESPONSE_TIMEOUT)).is_err()
+        {
+            return;
+        }
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(_) => return,
+        };
+        let response = match Request::read(&mut BufReader::new(stream)) {
+            Ok(request) => self.handle(&request),
+            Err(_) => Response::new(400, "text/plain", "bad request"),
+        };
+        // The client may already be gone; there is nobody left to report a failure to
+        let _ = response.write_to(&mut writer);
+    }
+}
+
+/// Reads the `versionId` and `versionTime` query parameters; others are ignored. Returns
+/// `None` when either is malformed.
+fn resolution_options(query: &str) -> Option<ResolutionOptions> {
+    let mut options = ResolutionOptions::default();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value)?;
+        match name {
+            "versionId" => options.version_id = Some(value.parse().ok()?),
+            "versionTime" => options.version_time = Some(parse_timestamp(&value)?),
+            _ => {}
+        }
+    }
+    Some(options)
+}
+
+fn error_response(code: ErrorCode, message: &str) -> Response {
+    result_response(ResolutionResult::error(code, message))
+}
+
+/// Responds with the whole resolution result, with the HTTP status of its error.
+fn result_response(result: ResolutionResult) -> Response {
+    let status = match result.did_resolution_metadata.error {
+        None => 200,
+        Some(ErrorCode::InvalidDid) | Some(ErrorCode::InvalidOptions) => 400,
+        Some(ErrorCode::NotFound) => 404,
+        Some(ErrorCode::RepresentationNotSupported) => 406,
+        Some(ErrorCode::InternalError) => 500,
+        Some(ErrorCode::MethodNotSupported) => 501,
+    };
+    let body = serde_json::to_vec(&result).expect("resolution results serialize to JSON");
+    Response::new(status, RESOLUTION_RESULT, body)
+}
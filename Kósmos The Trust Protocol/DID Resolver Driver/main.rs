@@ -0,0 +1,38 @@
+//! Runs the `did:kosmos` Universal Resolver driver.
+//!
+//! Configured through the environment:
+//!
+//! - `KOSMOS_RPC_URL`: the Soroban RPC endpoint, over plain HTTP;
+//! - `KOSMOS_REGISTRY_ID`: the contract id of the DID registry;
+//! - `KOSMOS_SOURCE_ACCOUNT` (optional): the account simulated calls are built for;
+//! - `KOSMOS_DOCUMENT_ROOT` (optional): a local store of hash-anchored documents;
+//! - `LISTEN_ADDR` (optional): the address to serve on, `0.0.0.0:8080` by default.
+
+use std::env;
+use std::error::Error;
+use std::net::TcpListener;
+
+use did_anchor::LocalStore;
+use did_resolver::Resolver;
+use did_resolver_driver::rpc::RpcBackend;
+use did_resolver_driver::Driver;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let rpc_url = env::var("KOSMOS_RPC_URL").map_err(|_| "KOSMOS_RPC_URL is not set")?;
+    let registry = env::var("KOSMOS_REGISTRY_ID").map_err(|_| "KOSMOS_REGISTRY_ID is not set")?;
+
+    let mut backend = RpcBackend::new(&rpc_url, &registry)?;
+    if let Ok(account) = env::var("KOSMOS_SOURCE_ACCOUNT") {
+        backend = backend.with_source_account(&account)?;
+    }
+    let mut resolver = Resolver::new(backend);
+    if let Ok(root) = env::var("KOSMOS_DOCUMENT_ROOT") {
+        resolver = resolver.with_document_store(LocalStore::new(root));
+    }
+
+    let listen_addr = env::var("LISTEN_ADDR").unwrap_or_else(|_| "0.0.0.0:8080".into());
+    let listener = TcpListener::bind(&listen_addr)?;
+    eprintln!("did:kosmos driver listening on {}", listen_addr);
+    Driver::new(resolver).serve(listener);
+    Ok(())
+}
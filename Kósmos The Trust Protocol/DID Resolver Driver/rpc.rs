@@ -0,0 +1,311 @@
+//! A [`LedgerBackend`] that reads the DID registry through Soroban RPC.
+//!
+//! Registry state is read by simulating calls to the registry's query entrypoints with
+//! `simulateTransaction`: nothing is signed or submitted, and no fees are paid.
+
+use std::fmt;
+
+use did_registry::Error as RegistryError;
+use did_resolver::ledger::{
+    DidState, Document, DocumentAnchor, DocumentVersion, LedgerBackend, LedgerError, Service, Status, StatusChange,
+    VerificationMethod, VersionSelector,
+};
+use serde_json::{json, Value};
+use stellar_xdr::curr::{
+    Hash, HostFunction, InvokeContractArgs, InvokeHostFunctionOp, Limits, Memo, MuxedAccount, Operation, OperationBody,
+    Preconditions, ReadXdr, ScAddress, ScString, ScSymbol, ScVal, SequenceNumber, Transaction, TransactionEnvelope,
+    TransactionExt, TransactionV1Envelope, Uint256, VecM, WriteXdr,
+};
+
+use crate::http::{self, HttpUrl};
+
+/// Errors in the configuration of an [`RpcBackend`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The RPC endpoint is not a plain `http://` URL.
+    InvalidUrl(String),
+    /// The registry is not a contract strkey (`C...`).
+    InvalidContractId(String),
+    /// The source account is not an account strkey (`G...`).
+    InvalidAccountId(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidUrl(url) => write!(f, "not an http:// URL: {}", url),
+            ConfigError::InvalidContractId(id) => write!(f, "not a contract id: {}", id),
+            ConfigError::InvalidAccountId(id) => write!(f, "not an account id: {}", id),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Reads DID state by simulating calls to the registry's query entrypoints.
+#[derive(Clone, Debug)]
+pub struct RpcBackend {
+    url: HttpUrl,
+    registry: [u8; 32],
+    source_account: [u8; 32],
+}
+
+impl RpcBackend {
+    /// `url` is the RPC endpoint, e.g. `http://localhost:8000/soroban/rpc`, and `registry`
+    /// the id of the DID registry contract.
+    pub fn new(url: &str, registry: &str) -> Result<Self, ConfigError> {
+        let url = HttpUrl::parse(url).ok_or_else(|| ConfigError::InvalidUrl(url.into()))?;
+        let registry = match stellar_strkey::Strkey::from_string(registry) {
+            Ok(stellar_strkey::Strkey::Contract(contract)) => contract.0,
+            _ => return Err(ConfigError::InvalidContractId(registry.into())),
+        };
+        Ok(RpcBackend { url, registry, source_account: [0; 32] })
+    }
+
+    /// Sets the account the simulated transactions are built for. Defaults to the all-zero
+    /// key; RPC nodes that only simulate transactions of existing accounts need a real one.
+    pub fn with_source_account(mut self, account: &str) -> Result<Self, ConfigError> {
+        self.source_account = match stellar_strkey::Strkey::from_string(account) {
+            Ok(stellar_strkey::Strkey::PublicKeyEd25519(key)) => key.0,
+            _ => return Err(ConfigError::InvalidAccountId(account.into())),
+        };
+        Ok(self)
+    }
+
+    /// Simulates a call to a registry entrypoint and returns its result.
+    fn call(&self, function: &str, args: Vec<ScVal>) -> Result<ScVal, LedgerError> {
+        let transaction = self.transaction(function, args).map_err(|_| malformed("could not encode the call"))?;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "simulateTransaction",
+            "params": { "transaction": transaction },
+        });
+
+        let (status, body) = http::post(&self.url, "application/json", request.to_string().as_bytes())
+            .map_err(|err| LedgerError::Unavailable(format!("RPC request failed: {}", err)))?;
+        if status != 200 {
+            return Err(LedgerError::Unavailable(format!("RPC responded with HTTP {}", status)));
+        }
+        let response: Value = serde_json::from_slice(&body).map_err(|_| malformed("RPC response is not JSON"))?;
+        if let Some(error) = response.get("error") {
+            let message = error.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+            return Err(LedgerError::Unavailable(format!("RPC error: {}", message)));
+        }
+
+        let result = &response["result"];
+        if let Some(error) = result.get("error").and_then(Value::as_str) {
+            return Err(simulation_error(error));
+        }
+        let xdr = result["results"][0]["xdr"].as_str().ok_or_else(|| malformed("simulation returned no result"))?;
+        ScVal::from_xdr_base64(xdr, Limits::none()).map_err(|_| malformed("simulation result is not an ScVal"))
+    }
+
+    /// Builds an unsigned transaction invoking `function` on the registry, as base64 XDR.
+    fn transaction(&self, function: &str, args: Vec<ScVal>) -> Result<String, stellar_xdr::curr::Error> {
+        let invoke = InvokeContractArgs {
+            contract_address: ScAddress::Contract(Hash(self.registry)),
+            function_name: ScSymbol(function.try_into()?),
+            args: args.try_into()?,
+        };
+        let operation = Operation {
+            source_account: None,
+            body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+                host_function: HostFunction::InvokeContract(invoke),
+                auth: VecM::default(),
+            }),
+        };
+        let envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx: Transaction {
+                source_account: MuxedAccount::Ed25519(Uint256(self.source_account)),
+                fee: 100,
+                seq_num: SequenceNumber(0),
+                cond: Preconditions::None,
+                memo: Memo::None,
+                operations: vec![operation].try_into()?,
+                ext: TransactionExt::V0,
+            },
+            signatures: VecM::default(),
+        });
+        envelope.to_xdr_base64(Limits::none())
+    }
+}
+
+impl LedgerBackend for RpcBackend {
+    fn read_did(&self, did: &str, version: VersionSelector) -> Result<DidState, LedgerError> {
+        let did = ScVal::String(ScString(did.try_into().map_err(|_| LedgerError::DidNotFound)?));
+        let resolution = self.call("get_did", vec![did.clone()])?;
+        let latest = document_version(&self.call("get_did_at", vec![did.clone(), ScVal::U64(u64::MAX)])?)?;
+        let selected = match version {
+            VersionSelector::Latest => latest.clone(),
+            VersionSelector::Id(version_id) => {
+                document_version(&self.call("get_did_version", vec![did.clone(), ScVal::U32(version_id)])?)?
+            }
+            VersionSelector::AtTime(timestamp) => {
+                document_version(&self.call("get_did_at", vec![did.clone(), ScVal::U64(timestamp)])?)?
+            }
+        };
+        let history = self.call("get_status_history", vec![did.clone()])?;
+        // Past versions of a hash-anchored DID point at the off-chain document of their time
+        let anchor = match anchor(field(&resolution, "source")?)? {
+            Some(_) if version != VersionSelector::Latest => Some(document_anchor(
+                &self.call("get_anchor_version", vec![did, ScVal::U32(selected.version_id)])?,
+            )?),
+            anchor => anchor,
+        };
+
+        Ok(DidState {
+            version: selected,
+            latest_version_id: latest.version_id,
+            status_history: vec_of(&history)?.iter().map(status_change).collect::<Result<_, _>>()?,
+            anchor,
+        })
+    }
+}
+
+/// Maps a failed simulation to a ledger error. Simulation errors read like
+/// `HostError: Error(Contract, #3)`, followed by the diagnostic event log.
+fn simulation_error(error: &str) -> LedgerError {
+    let code = error
+        .split_once("Error(Contract, #")
+        .and_then(|(_, rest)| rest.split(')').next())
+        .and_then(|code| code.parse::<u32>().ok());
+    match code {
+        Some(code) if code == RegistryError::DidNotFound as u32 => LedgerError::DidNotFound,
+        Some(code) if code == RegistryError::VersionNotFound as u32 => LedgerError::VersionNotFound,
+        Some(code) => LedgerError::Unavailable(format!("registry error #{}", code)),
+        None => LedgerError::Unavailable(format!("simulation failed: {}", error.lines().next().unwrap_or_default())),
+    }
+}
+
+fn malformed(message: &str) -> LedgerError {
+    LedgerError::Unavailable(message.into())
+}
+
+// Contract types are encoded as maps keyed by field name, vectors as `ScVal::Vec`, and
+// integer enums such as `DidStatus` as `u32`.
+
+fn field<'a>(value: &'a ScVal, name: &str) -> Result<&'a ScVal, LedgerError> {
+    match value {
+        ScVal::Map(Some(map)) => map
+            .iter()
+            .find(|entry| matches!(&entry.key, ScVal::Symbol(key) if key.as_slice() == name.as_bytes()))
+            .map(|entry| &entry.val)
+            .ok_or_else(|| malformed("unexpected registry response")),
+        _ => Err(malformed("unexpected registry response")),
+    }
+}
+
+fn vec_of(value: &ScVal) -> Result<&[ScVal], LedgerError> {
+    match value {
+        ScVal::Vec(Some(vec)) => Ok(vec.as_slice()),
+        _ => Err(malformed("unexpected registry response")),
+    }
+}
+
+fn u32_of(value: &ScVal) -> Result<u32, LedgerError> {
+    match value {
+        ScVal::U32(value) => Ok(*value),
+        _ => Err(malformed("unexpected registry response")),
+    }
+}
+
+fn u64_of(value: &ScVal) -> Result<u64, LedgerError> {
+    match value {
+        ScVal::U64(value) => Ok(*value),
+        _ => Err(malformed("unexpected registry response")),
+    }
+}
+
+fn string_of(value: &ScVal) -> Result<String, LedgerError> {
+    match value {
+        ScVal::String(value) => Ok(String::from_utf8_lossy(value.as_slice()).into_owned()),
+        _ => Err(malformed("unexpected registry response")),
+    }
+}
+
+fn strings_of(value: &ScVal) -> Result<Vec<String>, LedgerError> {
+    vec_of(value)?.iter().map(string_of).collect()
+}
+
+fn bytes_of(value: &ScVal) -> Result<Vec<u8>, LedgerError> {
+    match value {
+        ScVal::Bytes(value) => Ok(value.to_vec()),
+        _ => Err(malformed("unexpected registry response")),
+    }
+}
+
+fn status(value: &ScVal) -> Result<Status, LedgerError> {
+    match u32_of(value)? {
+        1 => Ok(Status::Active),
+        2 => Ok(Status::Revoked),
+        3 => Ok(Status::Suspended),
+        4 => Ok(Status::Deactivated),
+        _ => Err(malformed("unknown DID status")),
+    }
+}
+
+fn status_change(value: &ScVal) -> Result<StatusChange, LedgerError> {
+    Ok(StatusChange {
+        status: status(field(value, "status")?)?,
+        reason: u32_of(field(value, "reason")?)?,
+        timestamp: u64_of(field(value, "timestamp")?)?,
+    })
+}
+
+fn document_version(value: &ScVal) -> Result<DocumentVersion, LedgerError> {
+    Ok(DocumentVersion {
+        version_id: u32_of(field(value, "version_id")?)?,
+        document: document(field(value, "document")?)?,
+        timestamp: u64_of(field(value, "timestamp")?)?,
+    })
+}
+
+fn document(value: &ScVal) -> Result<Document, LedgerError> {
+    Ok(Document {
+        verification_method: vec_of(field(value, "verification_method")?)?
+            .iter()
+            .map(|method| {
+                Ok(VerificationMethod {
+                    id: string_of(field(method, "id")?)?,
+                    method_type: string_of(field(method, "method_type")?)?,
+                    controller: string_of(field(method, "controller")?)?,
+                    public_key: bytes_of(field(method, "public_key")?)?,
+                })
+            })
+            .collect::<Result<_, LedgerError>>()?,
+        service: vec_of(field(value, "service")?)?
+            .iter()
+            .map(|service| {
+                Ok(Service {
+                    id: string_of(field(service, "id")?)?,
+                    service_type: string_of(field(service, "service_type")?)?,
+                    service_endpoint: string_of(field(service, "service_endpoint")?)?,
+                })
+            })
+            .collect::<Result<_, LedgerError>>()?,
+        also_known_as: strings_of(field(value, "also_known_as")?)?,
+        authentication: strings_of(field(value, "authentication")?)?,
+        assertion_method: strings_of(field(value, "assertion_method")?)?,
+        key_agreement: strings_of(field(value, "key_agreement")?)?,
+        capability_invocation: strings_of(field(value, "capability_invocation")?)?,
+        capability_delegation: strings_of(field(value, "capability_delegation")?)?,
+    })
+}
+
+/// Reads a `DocumentSource`, encoded as `[Symbol]` or `[Symbol, value]`.
+fn anchor(source: &ScVal) -> Result<Option<DocumentAnchor>, LedgerError> {
+    match vec_of(source)? {
+        [ScVal::Symbol(variant)] if variant.as_slice() == b"OnChain" => Ok(None),
+        [ScVal::Symbol(variant), anchor] if variant.as_slice() == b"Anchored" => Ok(Some(document_anchor(anchor)?)),
+        _ => Err(malformed("unknown document source")),
+    }
+}
+
+fn document_anchor(value: &ScVal) -> Result<DocumentAnchor, LedgerError> {
+    let hash = bytes_of(field(value, "hash")?)?;
+    Ok(DocumentAnchor {
+        hash: hash.try_into().map_err(|_| malformed("anchored hash is not 32 bytes"))?,
+        locator: string_of(field(value, "locator")?)?,
+    })
+}
@@ -0,0 +1,252 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+use did_registry::{DidDocument, DidRegistryContract, DidRegistryContractClient, VerificationMethod};
+use did_resolver::testutils::EnvBackend;
+use did_resolver::Resolver;
+use serde_json::Value;
+use soroban_sdk::testutils::{Address as _, Ledger as _};
+use soroban_sdk::xdr::{ScAddress, ScVal};
+use soroban_sdk::{Address, Bytes, Env, String, TryFromVal, Vec};
+
+use crate::http::{percent_decode, HttpUrl, Request};
+use crate::rpc::RpcBackend;
+use crate::testutils::StandInRpc;
+use crate::{Driver, Representation, DID_JSON, DID_LD_JSON, RESOLUTION_RESULT};
+
+/// 2024-05-01T12:00:00Z
+const REGISTERED_AT: u64 = 1_714_564_800;
+
+fn setup_test(env: &Env) -> (Address, DidRegistryContractClient<'_>) {
+    env.mock_all_auths();
+    env.ledger().with_mut(|li| li.timestamp = REGISTERED_AT);
+    let contract_id = env.register_contract(None, DidRegistryContract);
+    let client = DidRegistryContractClient::new(env, &contract_id);
+    client.initialize(&Address::generate(env));
+
+    // alice has two document versions, bob is revoked, carol is suspended
+    let controller = Address::generate(env);
+    let signers = soroban_sdk::vec![env, controller.clone()];
+    let alice = String::from_str(env, "did:kosmos:alice");
+    client.register_did(&controller, &alice, &document(env, "did:kosmos:alice"));
+    env.ledger().with_mut(|li| li.timestamp = REGISTERED_AT + 60);
+    let mut updated = document(env, "did:kosmos:alice");
+    updated.also_known_as.push_back(String::from_str(env, "https://alice.example"));
+    client.update_document(&alice, &signers, &updated);
+
+    let bob = String::from_str(env, "did:kosmos:bob");
+    client.register_did(&controller, &bob, &document(env, "did:kosmos:bob"));
+    client.revoke_did(&bob, &signers);
+
+    let carol = String::from_str(env, "did:kosmos:carol");
+    client.register_did(&controller, &carol, &document(env, "did:kosmos:carol"));
+    client.suspend_did(&carol, &signers, &0);
+
+    (contract_id, client)
+}
+
+fn document(env: &Env, did: &str) -> DidDocument {
+    DidDocument {
+        verification_method: soroban_sdk::vec![
+            env,
+            VerificationMethod {
+                id: String::from_str(env, "#key-1"),
+                method_type: String::from_str(env, "Ed25519VerificationKey2020"),
+                controller: String::from_str(env, did),
+                public_key: Bytes::from_array(env, &[7u8; 32]),
+            },
+        ],
+        service: Vec::new(env),
+        also_known_as: Vec::new(env),
+        authentication: soroban_sdk::vec![env, String::from_str(env, "#key-1")],
+        assertion_method: Vec::new(env),
+        key_agreement: Vec::new(env),
+        capability_invocation: Vec::new(env),
+        capability_delegation: Vec::new(env),
+    }
+}
+
+fn contract_strkey(env: &Env, contract_id: &Address) -> std::string::String {
+    let Ok(ScVal::Address(ScAddress::Contract(hash))) = ScVal::try_from_val(env, &contract_id.to_val()) else {
+        panic!("not a contract address");
+    };
+    stellar_strkey::Contract(hash.0).to_string()
+}
+
+fn request(method: &str, target: &str, accept: Option<&str>) -> Request {
+    Request {
+        method: method.into(),
+        target: target.into(),
+        headers: accept.map(|accept| ("Accept".into(), accept.into())).into_iter().collect(),
+    }
+}
+
+/// Sends a GET to the driver and returns the status, content type and JSON body.
+fn get(addr: SocketAddr, target: &str, accept: Option<&str>) -> (u16, std::string::String, Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let accept = accept.map(|accept| format!("Accept: {}\r\n", accept)).unwrap_or_default();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n{}\r\n", target, accept).unwrap();
+    let mut response = std::string::String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    let content_type = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Type: "))
+        .unwrap_or_default()
+        .to_string();
+    (status, content_type, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn test_resolve_over_http() {
+    let env = Env::default();
+    let (contract_id, _) = setup_test(&env);
+
+    // Resolving through RPC gives the same results as reading the registry directly
+    let direct = Resolver::new(EnvBackend::new(&env, &contract_id));
+    let alice = serde_json::to_value(direct.resolve("did:kosmos:alice")).unwrap();
+
+    let rpc = StandInRpc::bind(&env).unwrap();
+    let backend = RpcBackend::new(&rpc.url(), &contract_strkey(&env, &contract_id)).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || Driver::new(Resolver::new(backend)).serve(listener));
+
+    let client = thread::spawn(move || {
+        vec![
+            get(addr, "/1.0/identifiers/did:kosmos:alice", None),
+            get(addr, "/1.0/identifiers/did:kosmos:alice", Some(DID_LD_JSON)),
+            get(addr, "/1.0/identifiers/did:kosmos:alice?versionId=1", Some(DID_JSON)),
+            get(addr, "/1.0/identifiers/did:kosmos:alice?versionId=3", Some(DID_LD_JSON)),
+            get(addr, "/1.0/identifiers/did:kosmos:bob", Some(DID_LD_JSON)),
+            get(addr, "/1.0/identifiers/did:kosmos:nobody", Some(DID_LD_JSON)),
+            get(addr, "/1.0/identifiers/did:kosmos:alice", Some("text/html")),
+            get(addr, "/1.0/identifiers/did:kosmos:carol", None),
+            get(addr, "/1.0/identifiers/did:kosmos:carol", Some(DID_LD_JSON)),
+        ]
+    });
+    rpc.serve_until(|| client.is_finished()).unwrap();
+    let responses = client.join().unwrap();
+
+    let (status, content_type, body) = &responses[0];
+    assert_eq!((*status, content_type.as_str()), (200, RESOLUTION_RESULT));
+    assert_eq!(body, &alice);
+
+    let (status, content_type, body) = &responses[1];
+    assert_eq!((*status, content_type.as_str()), (200, DID_LD_JSON));
+    assert_eq!(body, &alice["didDocument"]);
+
+    let (status, content_type, body) = &responses[2];
+    assert_eq!((*status, content_type.as_str()), (200, DID_JSON));
+    assert_eq!(body["id"], "did:kosmos:alice");
+    assert_eq!(body.get("alsoKnownAs"), None);
+
+    let (status, _, body) = &responses[3];
+    assert_eq!(*status, 404);
+    assert_eq!(body["didResolutionMetadata"]["error"], "notFound");
+
+    // A revoked DID is gone, but its last document is still served
+    let (status, content_type, body) = &responses[4];
+    assert_eq!((*status, content_type.as_str()), (410, DID_LD_JSON));
+    assert_eq!(body["id"], "did:kosmos:bob");
+
+    let (status, content_type, body) = &responses[5];
+    assert_eq!((*status, content_type.as_str()), (404, RESOLUTION_RESULT));
+    assert_eq!(body["didResolutionMetadata"]["error"], "notFound");
+
+    let (status, _, body) = &responses[6];
+    assert_eq!(*status, 406);
+    assert_eq!(body["didResolutionMetadata"]["error"], "representationNotSupported");
+
+    // A suspended DID is still served, with its status in the metadata
+    let carol = serde_json::to_value(direct.resolve("did:kosmos:carol")).unwrap();
+    let (status, content_type, body) = &responses[7];
+    assert_eq!((*status, content_type.as_str()), (200, RESOLUTION_RESULT));
+    assert_eq!(body, &carol);
+    assert_eq!(body["didDocumentMetadata"]["status"], "suspended");
+    assert_eq!(body["didDocumentMetadata"].get("deactivated"), None);
+
+    let (status, content_type, body) = &responses[8];
+    assert_eq!((*status, content_type.as_str()), (200, DID_LD_JSON));
+    assert_eq!(body["id"], "did:kosmos:carol");
+}
+
+#[test]
+fn test_request_errors() {
+    let env = Env::default();
+    let (contract_id, _) = setup_test(&env);
+    let driver = Driver::new(Resolver::new(EnvBackend::new(&env, &contract_id)));
+
+    let response = driver.handle(&request("POST", "/1.0/identifiers/did:kosmos:alice", None));
+    assert_eq!((response.status, response.header("Allow")), (405, Some("GET")));
+    assert_eq!(driver.handle(&request("GET", "/1.0/resolve/did:kosmos:alice", None)).status, 404);
+
+    let status = |target: &str| driver.handle(&request("GET", target, Some(DID_LD_JSON))).status;
+    assert_eq!(status("/1.0/identifiers/did%3Akosmos%3Aalice"), 200);
+    assert_eq!(status("/1.0/identifiers/did:kosmos:Alice"), 400);
+    assert_eq!(status("/1.0/identifiers/did%ZZkosmos"), 400);
+    assert_eq!(status("/1.0/identifiers/did:web:example.com"), 501);
+    assert_eq!(status("/1.0/identifiers/did:kosmos:alice?versionId=first"), 400);
+    assert_eq!(status("/1.0/identifiers/did:kosmos:alice?versionTime=yesterday"), 400);
+
+    // versionTime selects the version in effect at that time
+    let response = driver.handle(&request(
+        "GET",
+        "/1.0/identifiers/did:kosmos:alice?versionTime=2024-05-01T12%3A00%3A30Z",
+        None,
+    ));
+    let body: Value = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!(body["didDocumentMetadata"]["versionId"], "1");
+    assert_eq!(body["didDocumentMetadata"]["nextVersionId"], "2");
+}
+
+#[test]
+fn test_negotiate_representation() {
+    let negotiate = |accept: Option<&str>| Representation::negotiate(accept);
+    assert_eq!(negotiate(None), Some(Representation::ResolutionResult));
+    assert_eq!(negotiate(Some("*/*")), Some(Representation::ResolutionResult));
+    assert_eq!(negotiate(Some(DID_JSON)), Some(Representation::DidJson));
+    assert_eq!(negotiate(Some(RESOLUTION_RESULT)), Some(Representation::ResolutionResult));
+    assert_eq!(
+        negotiate(Some("application/did+json;q=0.5, application/did+ld+json")),
+        Some(Representation::DidLdJson)
+    );
+    assert_eq!(negotiate(Some("text/html, */*;q=0.1")), Some(Representation::ResolutionResult));
+
+    // ld+json is only the resolution result with its profile
+    assert_eq!(negotiate(Some("application/ld+json")), None);
+    assert_eq!(negotiate(Some("application/did+json;q=0")), None);
+    assert_eq!(negotiate(Some("text/html")), None);
+}
+
+#[test]
+fn test_http_helpers() {
+    let url = HttpUrl::parse("http://localhost:8000/soroban/rpc").unwrap();
+    assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("localhost", 8000, "/soroban/rpc"));
+    assert_eq!(HttpUrl::parse("http://rpc.example").unwrap().path, "/");
+    assert_eq!(HttpUrl::parse("https://rpc.example"), None);
+
+    // IPv6 hosts are bracketed, and only accepted that way
+    let url = HttpUrl::parse("http://[::1]:8000/").unwrap();
+    assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("::1", 8000, "/"));
+    assert_eq!(url.authority(), "[::1]:8000");
+    assert_eq!(HttpUrl::parse("http://[::1]").unwrap().port, 80);
+    assert_eq!(HttpUrl::parse("http://::1:8000/"), None);
+    assert_eq!(HttpUrl::parse("http://[::1:8000/"), None);
+    assert_eq!(HttpUrl::parse("http://[rpc.example]:8000/"), None);
+    assert_eq!(HttpUrl::parse("http://localhost:8000/").unwrap().authority(), "localhost:8000");
+    assert!(RpcBackend::new("http://localhost:8000", "did:kosmos:alice").is_err());
+
+    assert_eq!(percent_decode("did%3Akosmos%3aalice").as_deref(), Some("did:kosmos:alice"));
+    assert_eq!(percent_decode("100%"), None);
+    assert_eq!(percent_decode("%+1"), None);
+
+    let mut raw = "GET /1.0/identifiers/did:kosmos:alice HTTP/1.1\r\nACCEPT: application/did+json\r\n\r\n".as_bytes();
+    let request = Request::read(&mut raw).unwrap();
+    assert_eq!(request.target, "/1.0/identifiers/did:kosmos:alice");
+    assert_eq!(request.header("accept"), Some(DID_JSON));
+    assert!(Request::read(&mut "GET /\r\n\r\n".as_bytes()).is_err());
+}
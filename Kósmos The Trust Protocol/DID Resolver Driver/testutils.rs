@@ -0,0 +1,114 @@
+//! A local stand-in for Soroban RPC, answering `simulateTransaction` by invoking
+//! contracts registered in a soroban test `Env`.
+
+use std::io::{self, BufReader, ErrorKind, Read};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use soroban_sdk::xdr::{
+    HostFunction, Limits, OperationBody, ReadXdr, ScVal, TransactionEnvelope, TransactionV1Envelope, WriteXdr,
+};
+use soroban_sdk::{Address, Env, Symbol, TryFromVal, Val, Vec};
+
+use crate::http::{Request, Response};
+
+/// Serves the `simulateTransaction` JSON-RPC method on a local port.
+///
+/// `Env` cannot leave the thread that created it, so the stand-in is driven from that
+/// thread with [`StandInRpc::serve_until`], while its clients run on other threads.
+pub struct StandInRpc {
+    env: Env,
+    listener: TcpListener,
+}
+
+impl StandInRpc {
+    /// Binds to an ephemeral port on localhost.
+    pub fn bind(env: &Env) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        Ok(StandInRpc { env: env.clone(), listener })
+    }
+
+    /// The RPC endpoint, to pass to `RpcBackend::new`.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.listener.local_addr().expect("listener is bound"))
+    }
+
+    /// Serves requests one at a time until `done` returns true.
+    pub fn serve_until(&self, done: impl Fn() -> bool) -> io::Result<()> {
+        while !done() {
+            match self.listener.accept() {
+                Ok((stream, _)) => self.handle_connection(stream)?,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(5)),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let request = Request::read(&mut reader)?;
+        let mut body = vec![0; request.content_length().unwrap_or(0)];
+        reader.read_exact(&mut body)?;
+
+        let response = match serde_json::from_slice::<Value>(&body) {
+            Ok(call) => {
+                let result = match call["method"].as_str() {
+                    Some("simulateTransaction") => match call["params"]["transaction"].as_str() {
+                        Some(transaction) => self.simulate(transaction),
+                        None => Err(json!({ "code": -32602, "message": "missing transaction" })),
+                    },
+                    _ => Err(json!({ "code": -32601, "message": "method not found" })),
+                };
+                match result {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": call["id"], "result": result }),
+                    Err(error) => json!({ "jsonrpc": "2.0", "id": call["id"], "error": error }),
+                }
+            }
+            Err(_) => json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32700, "message": "parse error" } }),
+        };
+        Response::new(200, "application/json", response.to_string()).write_to(&mut writer)
+    }
+
+    /// Runs the contract call of a transaction. Contract errors are reported the way
+    /// Soroban RPC reports failed simulations, as `HostError: Error(Contract, #<code>)`.
+    fn simulate(&self, transaction: &str) -> Result<Value, Value> {
+        let invalid = || json!({ "code": -32602, "message": "invalid transaction" });
+        let envelope = TransactionEnvelope::from_xdr_base64(transaction, Limits::none()).map_err(|_| invalid())?;
+        let invoke = match envelope {
+            TransactionEnvelope::Tx(TransactionV1Envelope { tx, .. }) => match tx.operations.first().map(|op| &op.body) {
+                Some(OperationBody::InvokeHostFunction(op)) => match &op.host_function {
+                    HostFunction::InvokeContract(invoke) => invoke.clone(),
+                    _ => return Err(invalid()),
+                },
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        };
+
+        let env = &self.env;
+        let contract = Address::try_from_val(env, &ScVal::Address(invoke.contract_address)).map_err(|_| invalid())?;
+        let function = Symbol::try_from_val(env, &ScVal::Symbol(invoke.function_name)).map_err(|_| invalid())?;
+        let mut args = Vec::<Val>::new(env);
+        for arg in invoke.args.iter() {
+            args.push_back(Val::try_from_val(env, arg).map_err(|_| invalid())?);
+        }
+
+        let latest_ledger = env.ledger().sequence();
+        match env.try_invoke_contract::<Val, soroban_sdk::Error>(&contract, &function, args) {
+            Ok(Ok(value)) => {
+                let value = ScVal::try_from_val(env, &value).map_err(|_| invalid())?;
+                let xdr = value.to_xdr_base64(Limits::none()).map_err(|_| invalid())?;
+                Ok(json!({ "results": [{ "auth": [], "xdr": xdr }], "latestLedger": latest_ledger }))
+            }
+            Ok(Err(_)) => Err(invalid()),
+            Err(Ok(error)) => Ok(json!({ "error": format!("HostError: {:?}", error), "latestLedger": latest_ledger })),
+            Err(Err(error)) => Ok(json!({ "error": format!("HostError: {:?}", error), "latestLedger": latest_ledger })),
+        }
+    }
+}
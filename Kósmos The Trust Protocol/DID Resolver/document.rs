@@ -0,0 +1,100 @@
+//! Renders registry documents as W3C DID documents (JSON-LD).
+
+use serde_json::{json, Map, Value};
+
+use crate::ledger::{Document, VerificationMethod};
+
+pub const DID_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+pub const ED25519_2020_CONTEXT: &str = "https://w3id.org/security/suites/ed25519-2020/v1";
+
+/// Multicodec prefix of an ed25519 public key, as used by `publicKeyMultibase`.
+const ED25519_PUB_MULTICODEC: [u8; 2] = [0xed, 0x01];
+
+/// Builds the DID document of `did` from its registry document.
+/// Relative ids such as `#key-1` are resolved against the DID.
+pub fn did_document(did: &str, document: &Document) -> Value {
+    let mut context = vec![Value::from(DID_CONTEXT)];
+    if document.verification_method.iter().any(|m| m.method_type == "Ed25519VerificationKey2020") {
+        context.push(Value::from(ED25519_2020_CONTEXT));
+    }
+
+    let mut out = Map::new();
+    out.insert("@context".into(), Value::Array(context));
+    out.insert("id".into(), Value::from(did));
+    if !document.also_known_as.is_empty() {
+        out.insert("alsoKnownAs".into(), json!(document.also_known_as));
+    }
+
+    let methods: Vec<Value> = document.verification_method.iter().map(|m| verification_method(did, m)).collect();
+    out.insert("verificationMethod".into(), Value::Array(methods));
+
+    for (name, references) in [
+        ("authentication", &document.authentication),
+        ("assertionMethod", &document.assertion_method),
+        ("keyAgreement", &document.key_agreement),
+        ("capabilityInvocation", &document.capability_invocation),
+        ("capabilityDelegation", &document.capability_delegation),
+    ] {
+        if !references.is_empty() {
+            let ids: Vec<Value> = references.iter().map(|id| Value::from(absolute_id(did, id))).collect();
+            out.insert(name.into(), Value::Array(ids));
+        }
+    }
+
+    if !document.service.is_empty() {
+        let services: Vec<Value> = document
+            .service
+            .iter()
+            .map(|s| {
+                json!({
+                    "id": absolute_id(did, &s.id),
+                    "type": s.service_type,
+                    "serviceEndpoint": s.service_endpoint,
+                })
+            })
+            .collect();
+        out.insert("service".into(), Value::Array(services));
+    }
+
+    Value::Object(out)
+}
+
+/// Renders a verification method. Ed25519VerificationKey2018 keys are written as
+/// `publicKeyBase58`; every other key as `publicKeyMultibase` (base58btc), with the
+/// ed25519 multicodec prefix for Ed25519VerificationKey2020 keys.
+fn verification_method(did: &str, method: &VerificationMethod) -> Value {
+    let mut out = Map::new();
+    out.insert("id".into(), Value::from(absolute_id(did, &method.id)));
+    out.insert("type".into(), Value::from(method.method_type.as_str()));
+    out.insert("controller".into(), Value::from(method.controller.as_str()));
+
+    match method.method_type.as_str() {
+        "Ed25519VerificationKey2018" => {
+            out.insert("publicKeyBase58".into(), Value::from(bs58::encode(&method.public_key).into_string()));
+        }
+        "Ed25519VerificationKey2020" => {
+            let mut key = ED25519_PUB_MULTICODEC.to_vec();
+            key.extend_from_slice(&method.public_key);
+            out.insert("publicKeyMultibase".into(), Value::from(multibase(&key)));
+        }
+        _ => {
+            out.insert("publicKeyMultibase".into(), Value::from(multibase(&method.public_key)));
+        }
+    }
+
+    Value::Object(out)
+}
+
+/// Resolves a fragment-only id against the DID; absolute ids are returned unchanged.
+fn absolute_id(did: &str, id: &str) -> String {
+    if id.starts_with('#') {
+        format!("{}{}", did, id)
+    } else {
+        id.to_string()
+    }
+}
+
+/// Encodes bytes as base58btc multibase (`z` prefix).
+fn multibase(bytes: &[u8]) -> String {
+    format!("z{}", bs58::encode(bytes).into_string())
+}
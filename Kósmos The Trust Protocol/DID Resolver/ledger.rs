@@ -0,0 +1,152 @@
+//! The ledger side of the resolver: a read-only view of `DidRegistryContract` state.
+//!
+//! The types here mirror the registry's contract types with plain Rust values, so a backend
+//! can be written against any source of ledger data (an RPC node, an indexer, a test `Env`)
+//! without the resolver depending on how it was read.
+
+use std::fmt;
+
+/// Which version of a DID document to read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionSelector {
+    /// The current version.
+    Latest,
+    /// A version by its id, as with the registry's `get_did_version`.
+    Id(u32),
+    /// The version in effect at a ledger timestamp, as with the registry's `get_did_at`.
+    AtTime(u64),
+}
+
+/// A read-only view of the DID registry.
+pub trait LedgerBackend {
+    /// Reads the state of `did`, with the document version chosen by `version`.
+    fn read_did(&self, did: &str, version: VersionSelector) -> Result<DidState, LedgerError>;
+}
+
+/// The errors a backend can report.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LedgerError {
+    /// The DID is not registered.
+    DidNotFound,
+    /// The DID is registered, but the selected document version does not exist.
+    VersionNotFound,
+    /// The ledger could not be read.
+    Unavailable(String),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::DidNotFound => write!(f, "DID not found"),
+            LedgerError::VersionNotFound => write!(f, "document version not found"),
+            LedgerError::Unavailable(reason) => write!(f, "ledger unavailable: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+/// The registry state of a DID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DidState {
+    /// The selected document version.
+    pub version: DocumentVersion,
+    /// The id of the current document version.
+    pub latest_version_id: u32,
+    /// Every status transition, oldest first, starting with the registration.
+    pub status_history: Vec<StatusChange>,
+    /// The anchor of the selected version. Set for hash-anchored DIDs, whose on-chain
+    /// document is empty.
+    pub anchor: Option<DocumentAnchor>,
+}
+
+impl DidState {
+    /// The current status of the DID.
+    pub fn status(&self) -> Status {
+        self.status_history.last().map_or(Status::Active, |change| change.status)
+    }
+
+    /// When the DID was registered, as a ledger timestamp.
+    pub fn created(&self) -> Option<u64> {
+        self.status_history.first().map(|change| change.timestamp)
+    }
+}
+
+/// Mirrors the registry's `DidStatus`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Active,
+    Revoked,
+    Suspended,
+    Deactivated,
+}
+
+impl Status {
+    /// Revoked and deactivated DIDs can never be used again.
+    pub fn is_terminated(&self) -> bool {
+        matches!(self, Status::Revoked | Status::Deactivated)
+    }
+
+    /// The status as reported in `didDocumentMetadata`, e.g. `suspended`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Active => "active",
+            Status::Revoked => "revoked",
+            Status::Suspended => "suspended",
+            Status::Deactivated => "deactivated",
+        }
+    }
+}
+
+/// Mirrors the registry's `StatusChange`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatusChange {
+    pub status: Status,
+    pub reason: u32,
+    pub timestamp: u64,
+}
+
+/// Mirrors the registry's `DocumentVersion`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocumentVersion {
+    pub version_id: u32,
+    pub document: Document,
+    pub timestamp: u64,
+}
+
+/// Mirrors the registry's `DidDocument`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Document {
+    pub verification_method: Vec<VerificationMethod>,
+    pub service: Vec<Service>,
+    pub also_known_as: Vec<String>,
+    pub authentication: Vec<String>,
+    pub assertion_method: Vec<String>,
+    pub key_agreement: Vec<String>,
+    pub capability_invocation: Vec<String>,
+    pub capability_delegation: Vec<String>,
+}
+
+/// Mirrors the registry's `VerificationMethod`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerificationMethod {
+    pub id: String,
+    pub method_type: String,
+    pub controller: String,
+    pub public_key: Vec<u8>,
+}
+
+/// Mirrors the registry's `Service`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Service {
+    pub id: String,
+    pub service_type: String,
+    pub service_endpoint: String,
+}
+
+/// Mirrors the registry's `DocumentAnchor`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocumentAnchor {
+    pub hash: [u8; 32],
+    pub locator: String,
+}
@@ -0,0 +1,270 @@
+//! Off-chain resolver for `did:kosmos` identifiers.
+//!
+//! Turns a DID into a W3C DID Resolution result (`didDocument`, `didDocumentMetadata` and
+//! `didResolutionMetadata`) by reading `DidRegistryContract` state through a
+//! [`LedgerBackend`]. Hash-anchored DIDs are resolved by fetching their document from a
+//! [`DocumentStore`] and checking it against the anchored hash.
+//!
+//! With the `testutils` feature, [`testutils::EnvBackend`] reads a registry running in a
+//! soroban test `Env`, so the resolver can be exercised without a network.
+
+use did_anchor::{fetch_anchored_document, DocumentStore};
+use did_registry::did_syntax::{self, DidSyntaxError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub mod document;
+pub mod ledger;
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
+
+use ledger::{LedgerBackend, LedgerError, VersionSelector};
+
+pub const RESOLUTION_CONTEXT: &str = "https://w3id.org/did-resolution/v1";
+pub const DID_LD_JSON: &str = "application/did+ld+json";
+
+/// Options accepted by [`Resolver::resolve_with`], as in the DID Resolution `versionId`
+/// and `versionTime` parameters. At most one may be set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResolutionOptions {
+    pub version_id: Option<u32>,
+    /// A ledger timestamp, in seconds since the Unix epoch.
+    pub version_time: Option<u64>,
+}
+
+/// A DID Resolution result.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolutionResult {
+    #[serde(rename = "@context")]
+    pub context: String,
+    pub did_document: Option<Value>,
+    pub did_resolution_metadata: ResolutionMetadata,
+    pub did_document_metadata: DocumentMetadata,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolutionMetadata {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error_message: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetadata {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub updated: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub deactivated: Option<bool>,
+    /// The current registry status of the DID. A suspended DID is not deactivated, but should
+    /// not be relied on until it is reactivated.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub version_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub next_version_id: Option<String>,
+}
+
+/// DID Resolution error codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    InvalidDid,
+    NotFound,
+    MethodNotSupported,
+    InvalidOptions,
+    InternalError,
+    RepresentationNotSupported,
+}
+
+impl ResolutionResult {
+    /// A result carrying only an error.
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        ResolutionResult {
+            context: RESOLUTION_CONTEXT.into(),
+            did_document: None,
+            did_resolution_metadata: ResolutionMetadata {
+                content_type: None,
+                error: Some(code),
+                error_message: Some(message.into()),
+            },
+            did_document_metadata: DocumentMetadata::default(),
+        }
+    }
+}
+
+/// Resolves `did:kosmos` identifiers against a ledger backend.
+pub struct Resolver<B> {
+    backend: B,
+    document_store: Option<Box<dyn DocumentStore + Send + Sync>>,
+}
+
+impl<B: LedgerBackend> Resolver<B> {
+    pub fn new(backend: B) -> Self {
+        Resolver { backend, document_store: None }
+    }
+
+    /// Sets the store hash-anchored documents are fetched from. Without one, resolving a
+    /// hash-anchored DID fails with `internalError`.
+    pub fn with_document_store(mut self, store: impl DocumentStore + Send + Sync + 'static) -> Self {
+        self.document_store = Some(Box::new(store));
+        self
+    }
+
+    /// Resolves the current version of a DID.
+    pub fn resolve(&self, did: &str) -> ResolutionResult {
+        self.resolve_with(did, &ResolutionOptions::default())
+    }
+
+    /// Resolves a DID, optionally at a given version or point in time.
+    pub fn resolve_with(&self, did: &str, options: &ResolutionOptions) -> ResolutionResult {
+        if let Err((code, message)) = check_did(did) {
+            return ResolutionResult::error(code, message);
+        }
+        let selector = match (options.version_id, options.version_time) {
+            (None, None) => VersionSelector::Latest,
+            (Some(version_id), None) => VersionSelector::Id(version_id),
+            (None, Some(version_time)) => VersionSelector::AtTime(version_time),
+            (Some(_), Some(_)) => {
+                return ResolutionResult::error(ErrorCode::InvalidOptions, "versionId and versionTime are mutually exclusive")
+            }
+        };
+
+        let state = match self.backend.read_did(did, selector) {
+            Ok(state) => state,
+            Err(LedgerError::DidNotFound) => return ResolutionResult::error(ErrorCode::NotFound, "DID is not registered"),
+            Err(LedgerError::VersionNotFound) => {
+                return ResolutionResult::error(ErrorCode::NotFound, "no document version matches the options")
+            }
+            Err(err) => return ResolutionResult::error(ErrorCode::InternalError, err.to_string()),
+        };
+
+        let did_document = match &state.anchor {
+            None => document::did_document(did, &state.version.document),
+            Some(anchor) => match self.anchored_document(did, &anchor.locator, &anchor.hash) {
+                Ok(document) => document,
+                Err(message) => return ResolutionResult::error(ErrorCode::InternalError, message),
+            },
+        };
+
+        let version_id = state.version.version_id;
+        ResolutionResult {
+            context: RESOLUTION_CONTEXT.into(),
+            did_document: Some(did_document),
+            did_resolution_metadata: ResolutionMetadata {
+                content_type: Some(DID_LD_JSON.into()),
+                ..Default::default()
+            },
+            did_document_metadata: DocumentMetadata {
+                created: state.created().map(format_timestamp),
+                // Version 1 is the registered document, which has never been updated
+                updated: (version_id > 1).then(|| format_timestamp(state.version.timestamp)),
+                deactivated: state.status().is_terminated().then_some(true),
+                status: Some(state.status().as_str().into()),
+                version_id: Some(version_id.to_string()),
+                next_version_id: (version_id < state.latest_version_id).then(|| (version_id + 1).to_string()),
+            },
+        }
+    }
+
+    /// Fetches and checks the off-chain document of a hash-anchored DID.
+    fn anchored_document(&self, did: &str, locator: &str, hash: &[u8; 32]) -> Result<Value, String> {
+        let store = match &self.document_store {
+            Some(store) => store,
+            None => return Err("no document store is configured for hash-anchored DIDs".into()),
+        };
+        let bytes = fetch_anchored_document(store.as_ref(), locator, hash).map_err(|err| err.to_string())?;
+        let document: Value = serde_json::from_slice(&bytes).map_err(|err| format!("anchored document is not JSON: {}", err))?;
+        if document.get("id").and_then(Value::as_str) != Some(did) {
+            return Err("anchored document does not describe this DID".into());
+        }
+        Ok(document)
+    }
+}
+
+/// Checks that `did` is a canonical `did:kosmos` identifier.
+fn check_did(did: &str) -> Result<(), (ErrorCode, String)> {
+    let mut parts = did.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("did"), Some("kosmos"), Some(_)) => {}
+        (Some("did"), Some(method), Some(_)) if !method.is_empty() => {
+            return Err((ErrorCode::MethodNotSupported, format!("DID method {} is not supported", method)))
+        }
+        _ => return Err((ErrorCode::InvalidDid, "not a DID".into())),
+    }
+
+    match did_syntax::parse(did.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(DidSyntaxError::NonCanonical) => Err((ErrorCode::InvalidDid, "DID is not in canonical form".into())),
+        Err(_) => Err((ErrorCode::InvalidDid, "not a valid did:kosmos identifier".into())),
+    }
+}
+
+/// Parses an XML Schema `dateTime` in UTC, as produced by [`format_timestamp`], into a
+/// ledger timestamp. Other offsets and fractional seconds are not accepted.
+pub fn parse_timestamp(value: &str) -> Option<u64> {
+    let b = value.as_bytes();
+    if b.len() != 20 || b[4] != b'-' || b[7] != b'-' || b[10] != b'T' || b[13] != b':' || b[16] != b':' || b[19] != b'Z' {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = value.get(range)?;
+        digits.bytes().all(|c| c.is_ascii_digit()).then(|| digits.parse().ok()).flatten()
+    };
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if year < 1970 || !(1..=12).contains(&month) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // Days since 1970-01-01 from a civil date, the inverse of `format_timestamp`
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let timestamp = (days * 86_400 + hour * 3_600 + minute * 60 + second) as u64;
+    // Rejects days that do not exist in the month, e.g. 2023-02-29 or 2024-05-00
+    (format_timestamp(timestamp) == value).then_some(timestamp)
+}
+
+/// Formats a ledger timestamp as an XML Schema `dateTime` in UTC, e.g. `2024-05-01T12:00:00Z`.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+
+    // Civil date from days since 1970-01-01 (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,261 @@
+use std::fs;
+
+use did_anchor::{document_hash, LocalStore};
+use did_registry::{DidDocument, DidRegistryContract, DidRegistryContractClient, DocumentAnchor, Service, VerificationMethod};
+use serde_json::json;
+use soroban_sdk::{testutils::{Address as _, Ledger as _}, Address, Bytes, BytesN, Env, String, Vec};
+
+use crate::testutils::EnvBackend;
+use crate::{format_timestamp, parse_timestamp, ErrorCode, ResolutionOptions, Resolver};
+
+/// 2024-05-01T12:00:00Z
+const REGISTERED_AT: u64 = 1_714_564_800;
+
+fn setup_test(env: &Env) -> (Address, DidRegistryContractClient<'_>) {
+    env.mock_all_auths();
+    env.ledger().with_mut(|li| li.timestamp = REGISTERED_AT);
+    let contract_id = env.register_contract(None, DidRegistryContract);
+    let client = DidRegistryContractClient::new(env, &contract_id);
+    client.initialize(&Address::generate(env));
+
+    (contract_id, client)
+}
+
+fn document(env: &Env, did: &str) -> DidDocument {
+    DidDocument {
+        verification_method: soroban_sdk::vec![
+            env,
+            VerificationMethod {
+                id: String::from_str(env, "#key-1"),
+                method_type: String::from_str(env, "Ed25519VerificationKey2020"),
+                controller: String::from_str(env, did),
+                public_key: Bytes::from_array(env, &[0u8; 32]),
+            },
+        ],
+        service: soroban_sdk::vec![
+            env,
+            Service {
+                id: String::from_str(env, "#website"),
+                service_type: String::from_str(env, "LinkedDomains"),
+                service_endpoint: String::from_str(env, "https://alice.example"),
+            },
+        ],
+        also_known_as: Vec::new(env),
+        authentication: soroban_sdk::vec![env, String::from_str(env, "#key-1")],
+        assertion_method: Vec::new(env),
+        key_agreement: Vec::new(env),
+        capability_invocation: Vec::new(env),
+        capability_delegation: Vec::new(env),
+    }
+}
+
+#[test]
+fn test_resolve_did() {
+    let env = Env::default();
+    let (contract_id, client) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did = "did:kosmos:alice";
+    client.register_did(&controller, &String::from_str(&env, did), &document(&env, did));
+
+    let resolver = Resolver::new(EnvBackend::new(&env, &contract_id));
+    let result = resolver.resolve(did);
+    assert_eq!(
+        serde_json::to_value(&result).unwrap(),
+        json!({
+            "@context": "https://w3id.org/did-resolution/v1",
+            "didDocument": {
+                "@context": ["https://www.w3.org/ns/did/v1", "https://w3id.org/security/suites/ed25519-2020/v1"],
+                "id": "did:kosmos:alice",
+                "verificationMethod": [{
+                    "id": "did:kosmos:alice#key-1",
+                    "type": "Ed25519VerificationKey2020",
+                    "controller": "did:kosmos:alice",
+                    "publicKeyMultibase": "z6MkeTG3bFFSLYVU7VqhgZxqr6YzpaGrQtFMh1uvqGy1vDnP",
+                }],
+                "authentication": ["did:kosmos:alice#key-1"],
+                "service": [{
+                    "id": "did:kosmos:alice#website",
+                    "type": "LinkedDomains",
+                    "serviceEndpoint": "https://alice.example",
+                }],
+            },
+            "didResolutionMetadata": { "contentType": "application/did+ld+json" },
+            "didDocumentMetadata": { "created": "2024-05-01T12:00:00Z", "status": "active", "versionId": "1" },
+        })
+    );
+}
+
+#[test]
+fn test_resolve_versions_and_deactivation() {
+    let env = Env::default();
+    let (contract_id, client) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = soroban_sdk::vec![&env, controller.clone()];
+    let did = "did:kosmos:bob";
+    let did_string = String::from_str(&env, did);
+    client.register_did(&controller, &did_string, &document(&env, did));
+
+    env.ledger().with_mut(|li| li.timestamp = REGISTERED_AT + 60);
+    let mut updated = document(&env, did);
+    updated.also_known_as.push_back(String::from_str(&env, "https://bob.example"));
+    client.update_document(&did_string, &signers, &updated);
+
+    let resolver = Resolver::new(EnvBackend::new(&env, &contract_id));
+    let latest = resolver.resolve(did);
+    let metadata = &latest.did_document_metadata;
+    assert_eq!(metadata.version_id.as_deref(), Some("2"));
+    assert_eq!(metadata.created.as_deref(), Some("2024-05-01T12:00:00Z"));
+    assert_eq!(metadata.updated.as_deref(), Some("2024-05-01T12:01:00Z"));
+    assert_eq!(metadata.next_version_id, None);
+    assert_eq!(latest.did_document.unwrap()["alsoKnownAs"], json!(["https://bob.example"]));
+
+    // The registered version, by id and by time
+    let first = resolver.resolve_with(did, &ResolutionOptions { version_id: Some(1), version_time: None });
+    assert_eq!(first.did_document_metadata.version_id.as_deref(), Some("1"));
+    assert_eq!(first.did_document_metadata.next_version_id.as_deref(), Some("2"));
+    assert_eq!(first.did_document.unwrap().get("alsoKnownAs"), None);
+    let at_time = resolver.resolve_with(did, &ResolutionOptions { version_id: None, version_time: Some(REGISTERED_AT + 30) });
+    assert_eq!(at_time.did_document_metadata.version_id.as_deref(), Some("1"));
+
+    let missing = resolver.resolve_with(did, &ResolutionOptions { version_id: Some(3), version_time: None });
+    assert_eq!(missing.did_resolution_metadata.error, Some(ErrorCode::NotFound));
+
+    // A revoked DID still resolves, flagged as deactivated
+    client.revoke_did(&did_string, &signers);
+    let revoked = resolver.resolve(did);
+    assert!(revoked.did_document.is_some());
+    assert_eq!(revoked.did_document_metadata.deactivated, Some(true));
+    assert_eq!(revoked.did_document_metadata.status.as_deref(), Some("revoked"));
+}
+
+#[test]
+fn test_resolve_suspended_did() {
+    let env = Env::default();
+    let (contract_id, client) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let signers = soroban_sdk::vec![&env, controller.clone()];
+    let did = "did:kosmos:carol";
+    let did_string = String::from_str(&env, did);
+    client.register_did(&controller, &did_string, &document(&env, did));
+    client.suspend_did(&did_string, &signers, &7);
+
+    // A suspended DID still resolves, but its metadata says it is suspended
+    let resolver = Resolver::new(EnvBackend::new(&env, &contract_id));
+    let suspended = resolver.resolve(did);
+    assert!(suspended.did_document.is_some());
+    assert_eq!(suspended.did_resolution_metadata.error, None);
+    assert_eq!(suspended.did_document_metadata.status.as_deref(), Some("suspended"));
+    assert_eq!(suspended.did_document_metadata.deactivated, None);
+    assert_eq!(serde_json::to_value(&suspended).unwrap()["didDocumentMetadata"]["status"], "suspended");
+
+    // Past versions report the current status too
+    let first = resolver.resolve_with(did, &ResolutionOptions { version_id: Some(1), version_time: None });
+    assert_eq!(first.did_document_metadata.status.as_deref(), Some("suspended"));
+
+    client.reactivate_did(&did_string, &signers);
+    assert_eq!(resolver.resolve(did).did_document_metadata.status.as_deref(), Some("active"));
+}
+
+#[test]
+fn test_resolution_errors() {
+    let env = Env::default();
+    let (contract_id, _) = setup_test(&env);
+    let resolver = Resolver::new(EnvBackend::new(&env, &contract_id));
+
+    let error = |did: &str| resolver.resolve(did).did_resolution_metadata.error;
+    assert_eq!(error("did:kosmos:nobody"), Some(ErrorCode::NotFound));
+    assert_eq!(error("did:kosmos:Alice"), Some(ErrorCode::InvalidDid));
+    assert_eq!(error("did:kosmos:mainnet:alice"), Some(ErrorCode::InvalidDid));
+    assert_eq!(error("alice.kosmos"), Some(ErrorCode::InvalidDid));
+    assert_eq!(error("did:web:example.com"), Some(ErrorCode::MethodNotSupported));
+
+    let both = ResolutionOptions { version_id: Some(1), version_time: Some(REGISTERED_AT) };
+    assert_eq!(
+        resolver.resolve_with("did:kosmos:alice", &both).did_resolution_metadata.error,
+        Some(ErrorCode::InvalidOptions)
+    );
+
+    let result = resolver.resolve("did:kosmos:nobody");
+    assert_eq!(
+        serde_json::to_value(&result).unwrap(),
+        json!({
+            "@context": "https://w3id.org/did-resolution/v1",
+            "didDocument": null,
+            "didResolutionMetadata": { "error": "notFound", "errorMessage": "DID is not registered" },
+            "didDocumentMetadata": {},
+        })
+    );
+}
+
+#[test]
+fn test_resolve_anchored_did() {
+    let env = Env::default();
+    let (contract_id, client) = setup_test(&env);
+
+    let controller = Address::generate(&env);
+    let did = client.derive_did(&controller);
+    let did_str = did.to_string();
+    let off_chain = format!(r#"{{"@context":"https://www.w3.org/ns/did/v1","id":"{}"}}"#, did_str);
+
+    let root = std::env::temp_dir().join(format!("did-resolver-anchored-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("https/example.com")).unwrap();
+    fs::write(root.join("https/example.com/did.json"), &off_chain).unwrap();
+
+    let anchor = DocumentAnchor {
+        hash: BytesN::from_array(&env, &document_hash(off_chain.as_bytes())),
+        locator: String::from_str(&env, "https://example.com/did.json"),
+    };
+    client.register_anchored_did(&controller, &anchor);
+
+    // Without a document store there is nothing to resolve the DID to
+    let resolver = Resolver::new(EnvBackend::new(&env, &contract_id));
+    assert_eq!(resolver.resolve(&did_str).did_resolution_metadata.error, Some(ErrorCode::InternalError));
+
+    let resolver = Resolver::new(EnvBackend::new(&env, &contract_id)).with_document_store(LocalStore::new(&root));
+    let result = resolver.resolve(&did_str);
+    assert_eq!(result.did_document, Some(serde_json::from_str(&off_chain).unwrap()));
+    assert_eq!(result.did_document_metadata.version_id.as_deref(), Some("1"));
+
+    // Re-anchoring publishes a new version; the previous one still resolves to its own document
+    let updated = off_chain.replace(r#""}"#, r#"","alsoKnownAs":["https://example.com"]}"#);
+    fs::write(root.join("https/example.com/did-2.json"), &updated).unwrap();
+    env.ledger().with_mut(|li| li.timestamp = REGISTERED_AT + 60);
+    let new_anchor = DocumentAnchor {
+        hash: BytesN::from_array(&env, &document_hash(updated.as_bytes())),
+        locator: String::from_str(&env, "https://example.com/did-2.json"),
+    };
+    client.update_anchor(&did, &Vec::from_array(&env, [controller]), &new_anchor);
+
+    let result = resolver.resolve(&did_str);
+    assert_eq!(result.did_document, Some(serde_json::from_str(&updated).unwrap()));
+    assert_eq!(result.did_document_metadata.version_id.as_deref(), Some("2"));
+    assert_eq!(result.did_document_metadata.updated, Some(format_timestamp(REGISTERED_AT + 60)));
+    let result = resolver.resolve_with(&did_str, &ResolutionOptions { version_id: Some(1), ..Default::default() });
+    assert_eq!(result.did_document, Some(serde_json::from_str(&off_chain).unwrap()));
+    assert_eq!(result.did_document_metadata.next_version_id.as_deref(), Some("2"));
+
+    // A document changed without re-anchoring is rejected
+    fs::write(root.join("https/example.com/did-2.json"), updated.replace("did/v1", "did/v2")).unwrap();
+    assert_eq!(resolver.resolve(&did_str).did_resolution_metadata.error, Some(ErrorCode::InternalError));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_format_timestamp() {
+    assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+    assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+    assert_eq!(format_timestamp(REGISTERED_AT + 3_661), "2024-05-01T13:01:01Z");
+    assert_eq!(format_timestamp(4_102_444_799), "2099-12-31T23:59:59Z");
+
+    for timestamp in [0, 951_782_400, REGISTERED_AT + 3_661, 4_102_444_799] {
+        assert_eq!(parse_timestamp(&format_timestamp(timestamp)), Some(timestamp));
+    }
+    assert_eq!(parse_timestamp("2023-02-29T00:00:00Z"), None);
+    assert_eq!(parse_timestamp("2024-05-01T12:00:00+02:00"), None);
+    assert_eq!(parse_timestamp("2024-05-01"), None);
+}
@@ -0,0 +1,129 @@
+//! An in-memory ledger backend for tests, reading a `DidRegistryContract` registered in a
+//! soroban test `Env`.
+
+use did_registry::{DidRegistryContractClient, DidStatus, DocumentSource, Error};
+use soroban_sdk::{Address, Env};
+
+use crate::ledger::{
+    DidState, Document, DocumentAnchor, DocumentVersion, LedgerBackend, LedgerError, Service, Status, StatusChange,
+    VerificationMethod, VersionSelector,
+};
+
+/// Reads DID state by calling the registry's query entrypoints in a test `Env`.
+pub struct EnvBackend<'a> {
+    env: Env,
+    client: DidRegistryContractClient<'a>,
+}
+
+impl<'a> EnvBackend<'a> {
+    pub fn new(env: &Env, registry: &Address) -> Self {
+        EnvBackend { env: env.clone(), client: DidRegistryContractClient::new(env, registry) }
+    }
+}
+
+impl LedgerBackend for EnvBackend<'_> {
+    fn read_did(&self, did: &str, version: VersionSelector) -> Result<DidState, LedgerError> {
+        let did = soroban_sdk::String::from_str(&self.env, did);
+        let resolution = self.client.try_get_did(&did).map_err(ledger_error)?.map_err(conversion_error)?;
+        let latest = self.client.try_get_did_at(&did, &u64::MAX).map_err(ledger_error)?.map_err(conversion_error)?;
+        let selected = match version {
+            VersionSelector::Latest => latest.clone(),
+            VersionSelector::Id(version_id) => {
+                self.client.try_get_did_version(&did, &version_id).map_err(ledger_error)?.map_err(conversion_error)?
+            }
+            VersionSelector::AtTime(timestamp) => {
+                self.client.try_get_did_at(&did, &timestamp).map_err(ledger_error)?.map_err(conversion_error)?
+            }
+        };
+        let history = self.client.try_get_status_history(&did).map_err(ledger_error)?.map_err(conversion_error)?;
+
+        Ok(DidState {
+            version: DocumentVersion {
+                version_id: selected.version_id,
+                document: document(&selected.document),
+                timestamp: selected.timestamp,
+            },
+            latest_version_id: latest.version_id,
+            status_history: history
+                .iter()
+                .map(|change| StatusChange { status: status(change.status), reason: change.reason, timestamp: change.timestamp })
+                .collect(),
+            anchor: match resolution.source {
+                DocumentSource::OnChain => None,
+                DocumentSource::Anchored(anchor) if version == VersionSelector::Latest => {
+                    Some(DocumentAnchor { hash: anchor.hash.to_array(), locator: string(&anchor.locator) })
+                }
+                DocumentSource::Anchored(_) => {
+                    let anchor = self
+                        .client
+                        .try_get_anchor_version(&did, &selected.version_id)
+                        .map_err(ledger_error)?
+                        .map_err(conversion_error)?;
+                    Some(DocumentAnchor { hash: anchor.hash.to_array(), locator: string(&anchor.locator) })
+                }
+            },
+        })
+    }
+}
+
+fn ledger_error<E>(err: Result<Error, E>) -> LedgerError {
+    match err {
+        Ok(Error::DidNotFound) => LedgerError::DidNotFound,
+        Ok(Error::VersionNotFound) => LedgerError::VersionNotFound,
+        Ok(err) => LedgerError::Unavailable(format!("registry error {:?}", err)),
+        Err(_) => LedgerError::Unavailable("registry call failed".into()),
+    }
+}
+
+fn conversion_error<E>(_: E) -> LedgerError {
+    LedgerError::Unavailable("unexpected registry response".into())
+}
+
+fn status(status: DidStatus) -> Status {
+    match status {
+        DidStatus::Active => Status::Active,
+        DidStatus::Revoked => Status::Revoked,
+        DidStatus::Suspended => Status::Suspended,
+        DidStatus::Deactivated => Status::Deactivated,
+    }
+}
+
+fn document(document: &did_registry::DidDocument) -> Document {
+    Document {
+        verification_method: document
+            .verification_method
+            .iter()
+            .map(|method| VerificationMethod {
+                id: string(&method.id),
+                method_type: string(&method.method_type),
+                controller: string(&method.controller),
+                public_key: method.public_key.iter().collect(),
+            })
+            .collect(),
+        service: document
+            .service
+            .iter()
+            .map(|service| Service {
+                id: string(&service.id),
+                service_type: string(&service.service_type),
+                service_endpoint: string(&service.service_endpoint),
+            })
+            .collect(),
+        also_known_as: strings(&document.also_known_as),
+        authentication: strings(&document.authentication),
+        assertion_method: strings(&document.assertion_method),
+        key_agreement: strings(&document.key_agreement),
+        capability_invocation: strings(&document.capability_invocation),
+        capability_delegation: strings(&document.capability_delegation),
+    }
+}
+
+fn string(value: &soroban_sdk::String) -> String {
+    let mut buf = vec![0u8; value.len() as usize];
+    value.copy_into_slice(&mut buf);
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+fn strings(values: &soroban_sdk::Vec<soroban_sdk::String>) -> Vec<String> {
+    values.iter().map(|value| string(&value)).collect()
+}
@@ -1,20 +1,64 @@
 #![no_std]
-use soroban_sdk::{contract, contractimpl, symbol_short, Address, Env, String, Symbol, Vec};
+use soroban_sdk::{contract, contracterror, contractimpl, symbol_short, Address, BytesN, Env, Vec};
+
+// --- Storage TTL ---
+pub const DAY_IN_LEDGERS: u32 = 17280;
+pub const ISSUER_TTL_EXTEND_TO: u32 = 180 * DAY_IN_LEDGERS;
+pub const ISSUER_TTL_THRESHOLD: u32 = ISSUER_TTL_EXTEND_TO - 30 * DAY_IN_LEDGERS;
+pub const INSTANCE_TTL_EXTEND_TO: u32 = 30 * DAY_IN_LEDGERS;
+pub const INSTANCE_TTL_THRESHOLD: u32 = INSTANCE_TTL_EXTEND_TO - DAY_IN_LEDGERS;
+
+/// Version of the storage layout this code reads and writes. It is recorded at
+/// initialization and by `migrate`; deployments initialized before it was recorded are at
+/// version 1.
+///
+/// - 1: the issuer list lives in instance storage.
+/// - 2: the issuer list lives in persistent storage, next to a membership flag per issuer,
+///   so that checking an issuer does not load every other one.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Errors returned by the issuer whitelist. The numeric codes are stable: new errors are
+/// appended and existing codes are never reused.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    IssuerAlreadyWhitelisted = 3,
+    IssuerNotFound = 4,
+    // The stored layout is newer than this code (see `migrate`)
+    UnsupportedSchemaVersion = 5,
+}
 
 #[contract]
 pub struct IssuerWhitelistContract;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 #[repr(u32)]
 pub enum DataKey {
     Admin = 1,
     // Stores the list (Vec<Address>) of whitelisted issuers
     IssuerList = 2,
+    // Stores the storage layout version (u32) of the whitelist
+    SchemaVersion = 3,
+    // Marks an issuer (Address) as whitelisted (bool)
+    Issuer(Address) = 4,
 }
 
 impl soroban_sdk::IntoVal<Env, soroban_sdk::Val> for DataKey {
-    fn into_val(self, env: &Env) -> soroban_sdk::Val {
-        (self as u32).into_val(env)
+    fn into_val(&self, env: &Env) -> soroban_sdk::Val {
+        match self {
+            DataKey::Admin => 1u32.into_val(env),
+            DataKey::IssuerList => 2u32.into_val(env),
+            DataKey::SchemaVersion => 3u32.into_val(env),
+            DataKey::Issuer(issuer) => {
+                let mut vec = Vec::<soroban_sdk::Val>::new(env);
+                vec.push_back(4u32.into_val(env));
+                vec.push_back(issuer.into_val(env));
+                vec.into_val(env)
+            }
+        }
     }
 }
 
@@ -22,77 +66,91 @@ impl soroban_sdk::IntoVal<Env, soroban_sdk::Val> for DataKey {
 impl IssuerWhitelistContract {
     /// Initializes the contract with an administrator.
     /// The admin is the only one who can add or remove issuers.
-    pub fn initialize(env: Env, admin: Address) {
+    pub fn initialize(env: Env, admin: Address) -> Result<(), Error> {
         if env.storage().instance().has(&DataKey::Admin) {
-            panic!("Contract already initialized");
+            return Err(Error::AlreadyInitialized);
         }
         env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
         
         // Initialize with an empty list of issuers
-        env.storage().instance().set(&DataKey::IssuerList, &Vec::<Address>::new(&env));
+        Self::set_issuers(&env, &Vec::new(&env));
+        Ok(())
     }
 
     /// Adds a new trusted issuer to the whitelist.
     /// Requires authorization from the contract admin.
-    pub fn add_issuer(env: Env, issuer_address: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+    pub fn add_issuer(env: Env, issuer_address: Address) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).ok_or(Error::NotInitialized)?;
         admin.require_auth();
 
-        let mut issuers: Vec<Address> = env.storage().instance().get(&DataKey::IssuerList).unwrap();
+        let mut issuers = Self::issuers(&env)?;
         
-        if let Some(_) = issuers.iter().find(|&x| x == issuer_address) {
-             panic!("Issuer already whitelisted");
+        if issuers.contains(&issuer_address) {
+            return Err(Error::IssuerAlreadyWhitelisted);
         }
 
         issuers.push_back(issuer_address.clone());
-        env.storage().instance().set(&DataKey::IssuerList, &issuers);
+        Self::set_issuers(&env, &issuers);
+        let issuer_key = DataKey::Issuer(issuer_address.clone());
+        env.storage().persistent().set(&issuer_key, &true);
+        env.storage().persistent().extend_ttl(&issuer_key, ISSUER_TTL_THRESHOLD, ISSUER_TTL_EXTEND_TO);
 
         // Emit event
         env.events().publish(
             (symbol_short!("iss_add"),),
             issuer_address
         );
+
+        Ok(())
     }
 
     /// Removes an issuer from the whitelist.
     /// Requires authorization from the contract admin.
-    pub fn remove_issuer(env: Env, issuer_address: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+    pub fn remove_issuer(env: Env, issuer_address: Address) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).ok_or(Error::NotInitialized)?;
         admin.require_auth();
 
-        let mut issuers: Vec<Address> = env.storage().instance().get(&DataKey::IssuerList).unwrap();
+        let mut issuers = Self::issuers(&env)?;
 
         let index = issuers.iter().position(|x| x == issuer_address);
         
         if let Some(i) = index {
             issuers.remove(i as u32);
-            env.storage().instance().set(&DataKey::IssuerList, &issuers);
+            Self::set_issuers(&env, &issuers);
+            env.storage().persistent().remove(&DataKey::Issuer(issuer_address.clone()));
 
             // Emit event
             env.events().publish(
                 (symbol_short!("iss_rem"),),
                 issuer_address
             );
+            Ok(())
         } else {
-            panic!("Issuer not found in whitelist");
+            Err(Error::IssuerNotFound)
         }
     }
 
     /// Checks if a given address is a whitelisted issuer.
-    /// This is a read-only function.
+    /// This is a read-only function. Nobody is whitelisted before initialization.
+    /// Checking an issuer keeps its entry alive.
     pub fn is_whitelisted(env: Env, issuer_address: Address) -> bool {
-        let issuers: Vec<Address> = env.storage().instance().get(&DataKey::IssuerList).unwrap();
-        issuers.contains(issuer_address)
+        let issuer_key = DataKey::Issuer(issuer_address);
+        if !env.storage().persistent().has(&issuer_key) {
+            return false;
+        }
+        env.storage().persistent().extend_ttl(&issuer_key, ISSUER_TTL_THRESHOLD, ISSUER_TTL_EXTEND_TO);
+        true
     }
 
     /// Gets the list of all whitelisted issuers.
     pub fn get_issuers(env: Env) -> Vec<Address> {
-         env.storage().instance().get(&DataKey::IssuerList).unwrap_or(Vec::new(&env))
+         Self::issuers(&env).unwrap_or(Vec::new(&env))
     }
 
     /// Transfers admin privileges to a new address.
-    pub fn set_admin(env: Env, new_admin: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+    pub fn set_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).ok_or(Error::NotInitialized)?;
         admin.require_auth();
         env.storage().instance().set(&DataKey::Admin, &new_admin);
 
@@ -101,5 +159,82 @@ impl IssuerWhitelistContract {
             (symbol_short!("new_admin"),),
             new_admin
         );
+
+        Ok(())
+    }
+
+    /// Replaces the contract's code with the uploaded WASM `new_wasm_hash`, keeping its
+    /// storage. The new code runs from the next call on; call `migrate` right after if it
+    /// changes the storage layout.
+    /// Requires authorization from the contract admin.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).ok_or(Error::NotInitialized)?;
+        admin.require_auth();
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("upgraded"),),
+            new_wasm_hash
+        );
+
+        Ok(())
+    }
+
+    /// Brings storage written by an earlier version of the contract up to `SCHEMA_VERSION`,
+    /// one layout change at a time, and records the new version. Storage that is already
+    /// current is left as it is.
+    /// Requires authorization from the contract admin.
+    pub fn migrate(env: Env) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).ok_or(Error::NotInitialized)?;
+        admin.require_auth();
+
+        let from = Self::get_schema_version(env.clone());
+        if from > SCHEMA_VERSION {
+            return Err(Error::UnsupportedSchemaVersion);
+        }
+        if from < 2 {
+            Self::migrate_to_v2(&env);
+        }
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("migrated"),),
+            (from, SCHEMA_VERSION)
+        );
+
+        Ok(())
+    }
+
+    /// Returns the version of the layout the contract's storage is in.
+    pub fn get_schema_version(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::SchemaVersion).unwrap_or(1)
+    }
+
+    fn issuers(env: &Env) -> Result<Vec<Address>, Error> {
+        env.storage().persistent().get(&DataKey::IssuerList).ok_or(Error::NotInitialized)
+    }
+
+    fn set_issuers(env: &Env, issuers: &Vec<Address>) {
+        env.storage().persistent().set(&DataKey::IssuerList, issuers);
+        env.storage().persistent().extend_ttl(&DataKey::IssuerList, ISSUER_TTL_THRESHOLD, ISSUER_TTL_EXTEND_TO);
+    }
+
+    /// Moves the issuer list from instance to persistent storage and flags each issuer.
+    fn migrate_to_v2(env: &Env) {
+        let issuers: Vec<Address> = env.storage().instance().get(&DataKey::IssuerList).unwrap_or(Vec::new(env));
+        for issuer in issuers.iter() {
+            let issuer_key = DataKey::Issuer(issuer);
+            env.storage().persistent().set(&issuer_key, &true);
+            env.storage().persistent().extend_ttl(&issuer_key, ISSUER_TTL_THRESHOLD, ISSUER_TTL_EXTEND_TO);
+        }
+        Self::set_issuers(env, &issuers);
+        env.storage().instance().remove(&DataKey::IssuerList);
     }
 }
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,463 @@
+#![no_std]
+use soroban_sdk::{contract, contractclient, contracterror, contractimpl, contracttype, symbol_short, Address, BytesN, Env, String, Vec};
+
+// --- Handles ---
+// A handle is a lower-case label followed by `.kosmos`, e.g. `alice.kosmos`.
+// It points at a DID registered in the DID registry and is owned by that DID's controllers.
+
+pub const NAME_SUFFIX: &[u8] = b".kosmos";
+pub const MAX_LABEL_LEN: usize = 32;
+/// Labels shorter than this are reserved: only the admin can hand them out.
+pub const MIN_UNRESERVED_LABEL_LEN: usize = 3;
+
+/// How long a registration or renewal lasts, in seconds.
+pub const REGISTRATION_PERIOD: u64 = 365 * 24 * 60 * 60;
+/// How long after expiry the owner can still renew, before anyone can claim the name.
+pub const GRACE_PERIOD: u64 = 30 * 24 * 60 * 60;
+
+// --- Storage TTL ---
+pub const DAY_IN_LEDGERS: u32 = 17280;
+pub const NAME_TTL_EXTEND_TO: u32 = 180 * DAY_IN_LEDGERS;
+pub const NAME_TTL_THRESHOLD: u32 = NAME_TTL_EXTEND_TO - 30 * DAY_IN_LEDGERS;
+pub const INSTANCE_TTL_EXTEND_TO: u32 = 30 * DAY_IN_LEDGERS;
+pub const INSTANCE_TTL_THRESHOLD: u32 = INSTANCE_TTL_EXTEND_TO - DAY_IN_LEDGERS;
+
+/// Version of the storage layout this code reads and writes. It is recorded at
+/// initialization and by `migrate`; deployments initialized before it was recorded are at
+/// version 1.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `DidStatus::Active` as encoded by the DID registry.
+const DID_STATUS_ACTIVE: u32 = 1;
+
+/// Errors returned by the name service. The numeric codes are stable: new errors are
+/// appended and existing codes are never reused.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    InvalidName = 3,
+    NameNotFound = 4,
+    NameExpired = 5,
+    // The name is held by another DID and is not yet past its grace period
+    NameTaken = 6,
+    NameNotReserved = 7,
+    // The name does not resolve to the DID it is being made primary for
+    NameMismatch = 8,
+    // The DID registry has no record of the DID
+    DidNotFound = 9,
+    DidNotActive = 10,
+    NotAController = 11,
+    DuplicateSigner = 12,
+    NotEnoughSignatures = 13,
+    // The stored layout is newer than this code (see `migrate`)
+    UnsupportedSchemaVersion = 14,
+}
+
+/// The controllers of a DID, as returned by the DID registry.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ControllerSet {
+    pub controllers: Vec<Address>,
+    pub threshold: u32,
+}
+
+/// The part of the DID registry interface the name service relies on.
+#[contractclient(name = "DidRegistryClient")]
+pub trait DidRegistry {
+    fn get_controller(env: Env, did: String) -> ControllerSet;
+    fn get_status(env: Env, did: String) -> u32;
+}
+
+/// The DID a handle points at, and until when.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NameRecord {
+    pub did: String,
+    // Ledger timestamp at which the registration lapses
+    pub expires_at: u64,
+}
+
+#[contract]
+pub struct NameServiceContract;
+
+#[derive(Clone)]
+#[repr(u32)]
+pub enum DataKey {
+    Admin = 1,
+    // Stores the Address of the DidRegistryContract
+    DidRegistry = 2,
+    // Stores the registration (NameRecord) of a handle (String)
+    Name(String) = 3,
+    // Stores the primary handle (String) of a DID (String), for reverse resolution
+    Primary(String) = 4,
+    // Marks a handle (String) as reserved (bool) for the admin to assign
+    Reserved(String) = 5,
+    // Stores the storage layout version (u32) of the name service
+    SchemaVersion = 6,
+}
+
+impl soroban_sdk::IntoVal<Env, soroban_sdk::Val> for DataKey {
+    fn into_val(&self, env: &Env) -> soroban_sdk::Val {
+        match self {
+            DataKey::Admin => 1u32.into_val(env),
+            DataKey::DidRegistry => 2u32.into_val(env),
+            DataKey::Name(name) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(3u32.into_val(env));
+                vec.push_back(name.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::Primary(did) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(4u32.into_val(env));
+                vec.push_back(did.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::Reserved(name) => {
+                let mut vec: Vec<soroban_sdk::Val> = Vec::new(env);
+                vec.push_back(5u32.into_val(env));
+                vec.push_back(name.into_val(env));
+                vec.into_val(env)
+            }
+            DataKey::SchemaVersion => 6u32.into_val(env),
+        }
+    }
+}
+
+#[contractimpl]
+impl NameServiceContract {
+    /// Initializes the name service with an administrator and the DID registry it serves.
+    /// The admin manages reserved handles.
+    pub fn initialize(env: Env, admin: Address, did_registry: Address) -> Result<(), Error> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(Error::AlreadyInitialized);
+        }
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::DidRegistry, &did_registry);
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        Ok(())
+    }
+
+    /// Registers a handle for an active DID, for `REGISTRATION_PERIOD` seconds.
+    /// `signers` must contain at least `threshold` of the DID's controllers, each of which
+    /// must authorize the call. Reserved handles also need the admin's authorization.
+    /// A handle can be taken over once its previous registration is past its grace period.
+    /// If the DID has no primary handle yet, this one becomes its primary handle.
+    pub fn register_name(env: Env, name: String, did: String, signers: Vec<Address>) -> Result<(), Error> {
+        Self::validate_name(&name)?;
+        if Self::is_reserved(env.clone(), name.clone()) {
+            Self::admin(&env)?.require_auth();
+        }
+        Self::require_did_controller_auth(&env, &did, &signers)?;
+
+        let name_key = DataKey::Name(name.clone());
+        let now = env.ledger().timestamp();
+        if let Some(record) = env.storage().persistent().get::<_, NameRecord>(&name_key) {
+            if now < record.expires_at.saturating_add(GRACE_PERIOD) {
+                return Err(Error::NameTaken);
+            }
+        }
+
+        let record = NameRecord { did: did.clone(), expires_at: now.saturating_add(REGISTRATION_PERIOD) };
+        env.storage().persistent().set(&name_key, &record);
+        Self::extend_ttl_for(&env, &name);
+
+        let primary_key = DataKey::Primary(did.clone());
+        if Self::reverse_resolve(env.clone(), did.clone()).is_none() {
+            env.storage().persistent().set(&primary_key, &name);
+            env.storage().persistent().extend_ttl(&primary_key, NAME_TTL_THRESHOLD, NAME_TTL_EXTEND_TO);
+        }
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("name_reg"), name),
+            record
+        );
+        Ok(())
+    }
+
+    /// Extends a handle's registration by `REGISTRATION_PERIOD` seconds from its current expiry.
+    /// Possible until the grace period after expiry ends.
+    /// `signers` must contain at least `threshold` of the owning DID's controllers.
+    pub fn renew_name(env: Env, name: String, signers: Vec<Address>) -> Result<(), Error> {
+        let name_key = DataKey::Name(name.clone());
+        let mut record: NameRecord = match env.storage().persistent().get(&name_key) {
+            Some(record) => record,
+            None => return Err(Error::NameNotFound),
+        };
+        if env.ledger().timestamp() >= record.expires_at.saturating_add(GRACE_PERIOD) {
+            return Err(Error::NameExpired);
+        }
+        Self::require_did_controller_auth(&env, &record.did, &signers)?;
+
+        record.expires_at = record.expires_at.saturating_add(REGISTRATION_PERIOD);
+        env.storage().persistent().set(&name_key, &record);
+        Self::extend_ttl_for(&env, &name);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("name_ren"), name),
+            record.expires_at
+        );
+        Ok(())
+    }
+
+    /// Points a handle at another DID, keeping its expiry.
+    /// Both DIDs' controllers must agree: `signers` must meet the current DID's threshold
+    /// and `new_signers` the new DID's.
+    pub fn transfer_name(env: Env, name: String, signers: Vec<Address>, new_did: String, new_signers: Vec<Address>) -> Result<(), Error> {
+        let name_key = DataKey::Name(name.clone());
+        let mut record: NameRecord = match env.storage().persistent().get(&name_key) {
+            Some(record) => record,
+            None => return Err(Error::NameNotFound),
+        };
+        if env.ledger().timestamp() >= record.expires_at {
+            return Err(Error::NameExpired);
+        }
+        Self::require_did_controller_auth(&env, &record.did, &signers)?;
+        Self::require_did_controller_auth(&env, &new_did, &new_signers)?;
+
+        let old_did = record.did;
+        record.did = new_did.clone();
+        env.storage().persistent().set(&name_key, &record);
+        Self::extend_ttl_for(&env, &name);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("name_xfer"), name),
+            (old_did, new_did)
+        );
+        Ok(())
+    }
+
+    /// Chooses which of a DID's handles `reverse_resolve` returns.
+    /// `signers` must contain at least `threshold` of the DID's controllers.
+    pub fn set_primary_name(env: Env, did: String, signers: Vec<Address>, name: String) -> Result<(), Error> {
+        Self::require_did_controller_auth(&env, &did, &signers)?;
+        if Self::resolve_name(env.clone(), name.clone())? != did {
+            return Err(Error::NameMismatch);
+        }
+
+        let primary_key = DataKey::Primary(did.clone());
+        env.storage().persistent().set(&primary_key, &name);
+        env.storage().persistent().extend_ttl(&primary_key, NAME_TTL_THRESHOLD, NAME_TTL_EXTEND_TO);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("name_prim"), did),
+            name
+        );
+        Ok(())
+    }
+
+    /// Resolves a handle to the DID it points at.
+    /// Registrations outlive the maximum storage TTL, so resolving also bumps the record.
+    pub fn resolve_name(env: Env, name: String) -> Result<String, Error> {
+        let record: NameRecord = match env.storage().persistent().get(&DataKey::Name(name.clone())) {
+            Some(record) => record,
+            None => return Err(Error::NameNotFound),
+        };
+        if env.ledger().timestamp() >= record.expires_at {
+            return Err(Error::NameExpired);
+        }
+        Self::extend_ttl_for(&env, &name);
+        Ok(record.did)
+    }
+
+    /// Resolves a DID to its primary handle, if it has one that is unexpired and still
+    /// points back at the DID.
+    pub fn reverse_resolve(env: Env, did: String) -> Option<String> {
+        let name: String = env.storage().persistent().get(&DataKey::Primary(did.clone()))?;
+        let record: NameRecord = env.storage().persistent().get(&DataKey::Name(name.clone()))?;
+        if record.did != did || env.ledger().timestamp() >= record.expires_at {
+            return None;
+        }
+        Some(name)
+    }
+
+    /// Returns the registration of a handle, expired or not, if it was ever registered.
+    pub fn get_name_record(env: Env, name: String) -> Option<NameRecord> {
+        env.storage().persistent().get(&DataKey::Name(name))
+    }
+
+    /// Reserves a handle so that it can only be registered with the admin's authorization,
+    /// e.g. for brands or institutions. Does not affect an existing registration.
+    pub fn reserve_name(env: Env, name: String) -> Result<(), Error> {
+        Self::admin(&env)?.require_auth();
+        Self::validate_name(&name)?;
+
+        let reserved_key = DataKey::Reserved(name.clone());
+        env.storage().persistent().set(&reserved_key, &true);
+        env.storage().persistent().extend_ttl(&reserved_key, NAME_TTL_THRESHOLD, NAME_TTL_EXTEND_TO);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("name_rsv"),),
+            name
+        );
+        Ok(())
+    }
+
+    /// Releases a handle reserved with `reserve_name`.
+    pub fn unreserve_name(env: Env, name: String) -> Result<(), Error> {
+        Self::admin(&env)?.require_auth();
+
+        let reserved_key = DataKey::Reserved(name.clone());
+        if !env.storage().persistent().has(&reserved_key) {
+            return Err(Error::NameNotReserved);
+        }
+        env.storage().persistent().remove(&reserved_key);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("name_ursv"),),
+            name
+        );
+        Ok(())
+    }
+
+    /// Checks whether a handle needs the admin's authorization to be registered,
+    /// either because it was reserved or because its label is shorter than
+    /// `MIN_UNRESERVED_LABEL_LEN`.
+    pub fn is_reserved(env: Env, name: String) -> bool {
+        if (name.len() as usize) < NAME_SUFFIX.len() + MIN_UNRESERVED_LABEL_LEN {
+            return true;
+        }
+        env.storage().persistent().has(&DataKey::Reserved(name))
+    }
+
+    /// Transfers admin privileges to a new address.
+    pub fn set_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        Self::admin(&env)?.require_auth();
+        env.storage().instance().set(&DataKey::Admin, &new_admin);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("new_admin"),),
+            new_admin
+        );
+        Ok(())
+    }
+
+    /// Replaces the contract's code with the uploaded WASM `new_wasm_hash`, keeping its
+    /// storage. The new code runs from the next call on; call `migrate` right after if it
+    /// changes the storage layout.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        Self::admin(&env)?.require_auth();
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("upgraded"),),
+            new_wasm_hash
+        );
+        Ok(())
+    }
+
+    /// Brings storage written by an earlier version of the contract up to `SCHEMA_VERSION`
+    /// and records the new version. Storage that is already current is left as it is.
+    pub fn migrate(env: Env) -> Result<(), Error> {
+        Self::admin(&env)?.require_auth();
+
+        let from = Self::get_schema_version(env.clone());
+        if from > SCHEMA_VERSION {
+            return Err(Error::UnsupportedSchemaVersion);
+        }
+        // Each layout change adds a step here, applied in order:
+        // `if from < 2 { Self::migrate_to_v2(&env); }`
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("migrated"),),
+            (from, SCHEMA_VERSION)
+        );
+        Ok(())
+    }
+
+    /// Returns the version of the layout the contract's storage is in.
+    pub fn get_schema_version(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::SchemaVersion).unwrap_or(1)
+    }
+
+    fn admin(env: &Env) -> Result<Address, Error> {
+        env.storage().instance().get(&DataKey::Admin).ok_or(Error::NotInitialized)
+    }
+
+    /// Checks that the DID is active in the registry and that `signers` are distinct
+    /// controllers of it meeting its threshold, and requires each one's authorization.
+    fn require_did_controller_auth(env: &Env, did: &String, signers: &Vec<Address>) -> Result<(), Error> {
+        let registry_id: Address = env.storage().instance().get(&DataKey::DidRegistry).ok_or(Error::NotInitialized)?;
+        let registry = DidRegistryClient::new(env, &registry_id);
+        let status = match registry.try_get_status(did) {
+            Ok(Ok(status)) => status,
+            _ => return Err(Error::DidNotFound),
+        };
+        if status != DID_STATUS_ACTIVE {
+            return Err(Error::DidNotActive);
+        }
+
+        let controller_set = match registry.try_get_controller(did) {
+            Ok(Ok(controller_set)) => controller_set,
+            _ => return Err(Error::DidNotFound),
+        };
+        for (i, signer) in signers.iter().enumerate() {
+            if !controller_set.controllers.contains(&signer) {
+                return Err(Error::NotAController);
+            }
+            for other in signers.iter().skip(i + 1) {
+                if other == signer {
+                    return Err(Error::DuplicateSigner);
+                }
+            }
+        }
+        if signers.len() < controller_set.threshold {
+            return Err(Error::NotEnoughSignatures);
+        }
+
+        for signer in signers.iter() {
+            signer.require_auth();
+        }
+        Ok(())
+    }
+
+    /// Checks that a handle is a label of lower-case letters, digits and inner hyphens,
+    /// at most `MAX_LABEL_LEN` long, followed by `.kosmos`.
+    fn validate_name(name: &String) -> Result<(), Error> {
+        let len = name.len() as usize;
+        if len <= NAME_SUFFIX.len() || len > MAX_LABEL_LEN + NAME_SUFFIX.len() {
+            return Err(Error::InvalidName);
+        }
+        let mut buf = [0u8; MAX_LABEL_LEN + NAME_SUFFIX.len()];
+        name.copy_into_slice(&mut buf[..len]);
+
+        let (label, suffix) = buf[..len].split_at(len - NAME_SUFFIX.len());
+        if suffix != NAME_SUFFIX {
+            return Err(Error::InvalidName);
+        }
+        if label[0] == b'-' || label[label.len() - 1] == b'-' {
+            return Err(Error::InvalidName);
+        }
+        for &c in label {
+            match c {
+                b'a'..=b'z' | b'0'..=b'9' | b'-' => {}
+                _ => return Err(Error::InvalidName),
+            }
+        }
+        Ok(())
+    }
+
+    /// Bumps a handle's registration, along with the contract instance.
+    fn extend_ttl_for(env: &Env, name: &String) {
+        env.storage().persistent().extend_ttl(&DataKey::Name(name.clone()), NAME_TTL_THRESHOLD, NAME_TTL_EXTEND_TO);
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND_TO);
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,304 @@
+use soroban_sdk::{
+    testutils::{Address as _, Events as _, Ledger as _, MockAuth, MockAuthInvoke},
+    vec, Address, Bytes, Env, IntoVal, String, TryFromVal, Vec, symbol_short,
+};
+
+use crate::{DataKey, Error, NameRecord, NameServiceContract, NameServiceContractClient, GRACE_PERIOD, REGISTRATION_PERIOD, SCHEMA_VERSION};
+
+// The DID registry the name service resolves controllers against
+use did_registry::{DidDocument, DidRegistryContract, DidRegistryContractClient, VerificationMethod};
+
+fn setup_test(env: &Env) -> (Address, NameServiceContractClient<'_>, DidRegistryContractClient<'_>) {
+    let admin = Address::generate(env);
+
+    let registry_id = env.register_contract(None, DidRegistryContract);
+    let registry_client = DidRegistryContractClient::new(env, &registry_id);
+    registry_client.initialize(&admin);
+
+    let contract_id = env.register_contract(None, NameServiceContract);
+    let client = NameServiceContractClient::new(env, &contract_id);
+    client.initialize(&admin, &registry_id);
+
+    (admin, client, registry_client)
+}
+
+/// Registers a free-form DID with a single controller.
+fn register_did(env: &Env, registry_client: &DidRegistryContractClient, did: &str) -> (String, Address) {
+    let controller = Address::generate(env);
+    let document = DidDocument {
+        verification_method: vec![
+            env,
+            VerificationMethod {
+                id: String::from_str(env, "#key-1"),
+                method_type: String::from_str(env, "Ed25519VerificationKey2020"),
+                controller: String::from_str(env, did),
+                public_key: Bytes::from_array(env, &[7u8; 32]),
+            },
+        ],
+        service: Vec::new(env),
+        also_known_as: Vec::new(env),
+        authentication: Vec::new(env),
+        assertion_method: Vec::new(env),
+        key_agreement: Vec::new(env),
+        capability_invocation: Vec::new(env),
+        capability_delegation: Vec::new(env),
+    };
+    let did_string = String::from_str(env, did);
+    registry_client.register_did(&controller, &did_string, &document);
+
+    (did_string, controller)
+}
+
+#[test]
+fn test_register_and_resolve_name() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, registry_client) = setup_test(&env);
+
+    let (did, controller) = register_did(&env, &registry_client, "did:kosmos:alice");
+    let name = String::from_str(&env, "alice.kosmos");
+
+    env.ledger().with_mut(|li| li.timestamp = 1_000);
+    client.register_name(&name, &did, &vec![&env, controller.clone()]);
+
+    let record = NameRecord { did: did.clone(), expires_at: 1_000 + REGISTRATION_PERIOD };
+    let events = env.events().all();
+    let (_, topics, data) = events.last().unwrap();
+    assert_eq!(topics, (symbol_short!("name_reg"), name.clone()).into_val(&env));
+    assert_eq!(NameRecord::try_from_val(&env, &data), Ok(record.clone()));
+
+    assert_eq!(client.resolve_name(&name), did);
+    assert_eq!(client.reverse_resolve(&did), Some(name.clone()));
+    assert_eq!(client.get_name_record(&name), Some(record));
+
+    // A second handle does not replace the primary one until asked to
+    let other = String::from_str(&env, "alice-work.kosmos");
+    client.register_name(&other, &did, &vec![&env, controller.clone()]);
+    assert_eq!(client.reverse_resolve(&did), Some(name.clone()));
+    client.set_primary_name(&did, &vec![&env, controller], &other);
+    assert_eq!(client.reverse_resolve(&did), Some(other));
+}
+
+#[test]
+fn test_name_expiry_and_renewal() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, registry_client) = setup_test(&env);
+
+    let (did, controller) = register_did(&env, &registry_client, "did:kosmos:bob");
+    let signers = vec![&env, controller];
+    let name = String::from_str(&env, "bob.kosmos");
+    client.register_name(&name, &did, &signers);
+
+    // Expired, but still renewable by the owner during the grace period
+    env.ledger().with_mut(|li| li.timestamp = REGISTRATION_PERIOD);
+    assert_eq!(client.reverse_resolve(&did), None);
+    client.renew_name(&name, &signers);
+    assert_eq!(client.get_name_record(&name).unwrap().expires_at, 2 * REGISTRATION_PERIOD);
+    assert_eq!(client.resolve_name(&name), did);
+
+    // Past the grace period, anyone can claim the handle
+    env.ledger().with_mut(|li| li.timestamp = 2 * REGISTRATION_PERIOD + GRACE_PERIOD);
+    let (carol, carol_controller) = register_did(&env, &registry_client, "did:kosmos:carol");
+    client.register_name(&name, &carol, &vec![&env, carol_controller]);
+    assert_eq!(client.resolve_name(&name), carol);
+    assert_eq!(client.reverse_resolve(&carol), Some(name));
+    assert_eq!(client.reverse_resolve(&did), None);
+}
+
+#[test]
+fn test_transfer_name() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, registry_client) = setup_test(&env);
+
+    let (did, controller) = register_did(&env, &registry_client, "did:kosmos:acme-old");
+    let (new_did, new_controller) = register_did(&env, &registry_client, "did:kosmos:acme-new");
+    let name = String::from_str(&env, "acme.kosmos");
+    client.register_name(&name, &did, &vec![&env, controller.clone()]);
+
+    client.transfer_name(&name, &vec![&env, controller], &new_did, &vec![&env, new_controller.clone()]);
+    assert_eq!(client.resolve_name(&name), new_did);
+    // The old DID's reverse record no longer points anywhere
+    assert_eq!(client.reverse_resolve(&did), None);
+
+    client.set_primary_name(&new_did, &vec![&env, new_controller], &name);
+    assert_eq!(client.reverse_resolve(&new_did), Some(name));
+}
+
+#[test]
+fn test_reserved_names() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, client, registry_client) = setup_test(&env);
+
+    let short = String::from_str(&env, "ab.kosmos");
+    let brand = String::from_str(&env, "stellar.kosmos");
+    assert!(client.is_reserved(&short));
+    assert!(!client.is_reserved(&brand));
+
+    client.reserve_name(&brand);
+    assert!(client.is_reserved(&brand));
+
+    // The admin assigns a reserved handle by co-authorizing its registration
+    let (did, controller) = register_did(&env, &registry_client, "did:kosmos:stellar-org");
+    let signers = vec![&env, controller.clone()];
+    client
+        .mock_auths(&[
+            MockAuth {
+                address: &admin,
+                invoke: &MockAuthInvoke {
+                    contract: &client.address,
+                    fn_name: "register_name",
+                    args: (brand.clone(), did.clone(), signers.clone()).into_val(&env),
+                    sub_invokes: &[],
+                },
+            },
+            MockAuth {
+                address: &controller,
+                invoke: &MockAuthInvoke {
+                    contract: &client.address,
+                    fn_name: "register_name",
+                    args: (brand.clone(), did.clone(), signers.clone()).into_val(&env),
+                    sub_invokes: &[],
+                },
+            },
+        ])
+        .register_name(&brand, &did, &signers);
+    assert_eq!(client.resolve_name(&brand), did);
+
+    client.unreserve_name(&brand);
+    assert!(!client.is_reserved(&brand));
+}
+
+#[test]
+#[should_panic]
+fn test_reserved_name_requires_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, registry_client) = setup_test(&env);
+
+    let (did, controller) = register_did(&env, &registry_client, "did:kosmos:squatter");
+    let signers = vec![&env, controller.clone()];
+    let short = String::from_str(&env, "x.kosmos");
+
+    // Only the controller authorizes
+    client
+        .mock_auths(&[MockAuth {
+            address: &controller,
+            invoke: &MockAuthInvoke {
+                contract: &client.address,
+                fn_name: "register_name",
+                args: (short.clone(), did.clone(), signers.clone()).into_val(&env),
+                sub_invokes: &[],
+            },
+        }])
+        .register_name(&short, &did, &signers);
+}
+
+#[test]
+fn test_register_name_by_non_controller() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, registry_client) = setup_test(&env);
+
+    let (did, _) = register_did(&env, &registry_client, "did:kosmos:dave");
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        client.try_register_name(&String::from_str(&env, "dave.kosmos"), &did, &vec![&env, stranger]),
+        Err(Ok(Error::NotAController))
+    );
+}
+
+#[test]
+fn test_register_taken_name() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, registry_client) = setup_test(&env);
+
+    let name = String::from_str(&env, "erin.kosmos");
+    let (did, controller) = register_did(&env, &registry_client, "did:kosmos:erin");
+    client.register_name(&name, &did, &vec![&env, controller]);
+
+    // Still within the grace period
+    env.ledger().with_mut(|li| li.timestamp = REGISTRATION_PERIOD + 1);
+    let (other, other_controller) = register_did(&env, &registry_client, "did:kosmos:erin-2");
+    assert_eq!(
+        client.try_register_name(&name, &other, &vec![&env, other_controller]),
+        Err(Ok(Error::NameTaken))
+    );
+}
+
+#[test]
+fn test_register_name_for_revoked_did() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, registry_client) = setup_test(&env);
+
+    let (did, controller) = register_did(&env, &registry_client, "did:kosmos:frank");
+    registry_client.revoke_did(&did, &vec![&env, controller.clone()]);
+    assert_eq!(
+        client.try_register_name(&String::from_str(&env, "frank.kosmos"), &did, &vec![&env, controller]),
+        Err(Ok(Error::DidNotActive))
+    );
+}
+
+#[test]
+fn test_register_invalid_name() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, registry_client) = setup_test(&env);
+
+    let (did, controller) = register_did(&env, &registry_client, "did:kosmos:grace");
+    assert_eq!(
+        client.try_register_name(&String::from_str(&env, "Grace.kosmos"), &did, &vec![&env, controller]),
+        Err(Ok(Error::InvalidName))
+    );
+}
+
+#[test]
+fn test_register_name_for_unknown_did() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_, client, _) = setup_test(&env);
+
+    let did = String::from_str(&env, "did:kosmos:nobody");
+    let signers = vec![&env, Address::generate(&env)];
+    assert_eq!(
+        client.try_register_name(&String::from_str(&env, "nobody.kosmos"), &did, &signers),
+        Err(Ok(Error::DidNotFound))
+    );
+    assert_eq!(
+        client.try_resolve_name(&String::from_str(&env, "nobody.kosmos")),
+        Err(Ok(Error::NameNotFound))
+    );
+}
+
+#[test]
+fn test_schema_migration() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, client, registry_client) = setup_test(&env);
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+
+    // A deployment initialized before the version was recorded is at version 1
+    let (did, controller) = register_did(&env, &registry_client, "did:kosmos:alice");
+    let name = String::from_str(&env, "alice.kosmos");
+    client.register_name(&name, &did, &vec![&env, controller]);
+    env.as_contract(&client.address, || env.storage().instance().remove(&DataKey::SchemaVersion));
+    assert_eq!(client.get_schema_version(), 1);
+
+    client.migrate();
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(client.resolve_name(&name), did);
+
+    // Nobody else can migrate it
+    assert!(client.mock_auths(&[]).try_migrate().is_err());
+
+    // Storage written by a newer version is left alone
+    env.as_contract(&client.address, || {
+        env.storage().instance().set(&DataKey::SchemaVersion, &(SCHEMA_VERSION + 1))
+    });
+    assert_eq!(client.try_migrate(), Err(Ok(Error::UnsupportedSchemaVersion)));
+}
@@ -1,15 +1,32 @@
 #![no_std]
-use soroban_sdk::{contract, contractimpl, symbol_short, Address, BytesN, Env, Symbol, Vec};
+use soroban_sdk::{contract, contractclient, contracterror, contractimpl, symbol_short, Address, BytesN, Env, Symbol, Vec};
 
-// We need to import the client for the IssuerWhitelistContract
-// This assumes the `issuer-whitelist` crate is available.
+// The part of the IssuerWhitelistContract interface the verifier calls.
 // For cross-contract calls, you'll deploy the whitelist contract and store its ID.
-use soroban_sdk::contractimport;
+#[contractclient(name = "IssuerWhitelistClient")]
+pub trait IssuerWhitelist {
+    fn is_whitelisted(env: Env, issuer_address: Address) -> bool;
+}
 
-#[contractimport(
-    file = "../issuer_whitelist/target/wasm32-unknown-unknown/release/issuer_whitelist.wasm"
-)]
-struct IssuerWhitelistClient;
+/// Version of the storage layout this code reads and writes. It is recorded at
+/// initialization and by `migrate`; deployments initialized before it was recorded are at
+/// version 1.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Errors returned by the ZKP verifier. The numeric codes are stable: new errors are
+/// appended and existing codes are never reused.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    IssuerNotTrusted = 3,
+    // The issuer whitelist contract could not be called
+    WhitelistUnavailable = 4,
+    // The stored layout is newer than this code (see `migrate`)
+    UnsupportedSchemaVersion = 5,
+}
 
 #[contract]
 pub struct ZkpVerifierContract;
@@ -19,11 +36,15 @@ pub struct ZkpVerifierContract;
 pub enum DataKey {
     // Stores the Address of the IssuerWhitelistContract
     IssuerWhitelist = 1,
+    // Stores the Address allowed to change the whitelist and upgrade the verifier
+    Admin = 2,
+    // Stores the storage layout version (u32) of the verifier
+    SchemaVersion = 3,
 }
 
 impl soroban_sdk::IntoVal<Env, soroban_sdk::Val> for DataKey {
-    fn into_val(self, env: &Env) -> soroban_sdk::Val {
-        (self as u32).into_val(env)
+    fn into_val(&self, env: &Env) -> soroban_sdk::Val {
+        (*self as u32).into_val(env)
     }
 }
 
@@ -31,11 +52,15 @@ impl soroban_sdk::IntoVal<Env, soroban_sdk::Val> for DataKey {
 impl ZkpVerifierContract {
     /// Initializes the ZKP Verifier contract.
     /// It needs to know the address of the IssuerWhitelist contract to check issuer trust.
-    pub fn initialize(env: Env, issuer_whitelist_address: Address) {
+    /// The admin can point it at another whitelist and upgrade it.
+    pub fn initialize(env: Env, admin: Address, issuer_whitelist_address: Address) -> Result<(), Error> {
         if env.storage().instance().has(&DataKey::IssuerWhitelist) {
-            panic!("Contract already initialized");
+            return Err(Error::AlreadyInitialized);
         }
+        env.storage().instance().set(&DataKey::Admin, &admin);
         env.storage().instance().set(&DataKey::IssuerWhitelist, &issuer_whitelist_address);
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        Ok(())
     }
 
     /// Verifies a Zero-Knowledge Proof.
@@ -51,18 +76,23 @@ impl ZkpVerifierContract {
     /// 1. Check that the `issuer` is in the `IssuerWhitelistContract`.
     /// 2. Execute the cryptographic verification of the `proof` against the `public_inputs`.
     /// 3. Emit an event if verification is successful.
+    ///
+    /// Fails with `IssuerNotTrusted` if the issuer is not whitelisted, and with
+    /// `WhitelistUnavailable` if the whitelist contract cannot be queried.
     pub fn verify_proof(
         env: Env,
         issuer: Address,
         proof: BytesN<256>, // Placeholder size for a proof. Adjust as needed.
         public_inputs: Vec<soroban_sdk::Val>,
-    ) -> bool {
+    ) -> Result<bool, Error> {
         // --- 1. Check Issuer Trust ---
-        let whitelist_id: Address = env.storage().instance().get(&DataKey::IssuerWhitelist).unwrap();
+        let whitelist_id: Address = env.storage().instance().get(&DataKey::IssuerWhitelist).ok_or(Error::NotInitialized)?;
         let whitelist_client = IssuerWhitelistClient::new(&env, &whitelist_id);
         
-        if !whitelist_client.is_whitelisted(&issuer) {
-            panic!("Issuer is not trusted");
+        match whitelist_client.try_is_whitelisted(&issuer) {
+            Ok(Ok(true)) => {}
+            Ok(Ok(false)) => return Err(Error::IssuerNotTrusted),
+            _ => return Err(Error::WhitelistUnavailable),
         }
 
         // --- 2. Execute Cryptographic Verification ---
@@ -84,12 +114,12 @@ impl ZkpVerifierContract {
         // --- 3. Emit Event ---
         if is_valid {
             env.events().publish(
-                (symbol_short!("zkp_verify"), issuer),
+                (Symbol::new(&env, "zkp_verify"), issuer),
                 public_inputs
             );
         }
 
-        is_valid
+        Ok(is_valid)
     }
 
     /// Placeholder for the actual ZKP verification.
@@ -107,10 +137,61 @@ impl ZkpVerifierContract {
     }
 
     /// Sets a new address for the Issuer Whitelist contract.
-    pub fn set_whitelist_address(env: Env, new_address: Address) {
-         // This function should be admin-controlled.
-         // For simplicity, auth is omitted, but you should add it
-         // similar to the IssuerWhitelistContract.
+    /// Requires authorization from the contract admin.
+    pub fn set_whitelist_address(env: Env, new_address: Address) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).ok_or(Error::NotInitialized)?;
+        admin.require_auth();
         env.storage().instance().set(&DataKey::IssuerWhitelist, &new_address);
+        Ok(())
+    }
+
+    /// Replaces the contract's code with the uploaded WASM `new_wasm_hash`, keeping its
+    /// storage, e.g. to ship a fixed `verify_proof`. The new code runs from the next call
+    /// on; call `migrate` right after if it changes the storage layout.
+    /// Requires authorization from the contract admin.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).ok_or(Error::NotInitialized)?;
+        admin.require_auth();
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("upgraded"),),
+            new_wasm_hash
+        );
+
+        Ok(())
+    }
+
+    /// Brings storage written by an earlier version of the contract up to `SCHEMA_VERSION`
+    /// and records the new version. Storage that is already current is left as it is.
+    /// Requires authorization from the contract admin.
+    pub fn migrate(env: Env) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).ok_or(Error::NotInitialized)?;
+        admin.require_auth();
+
+        let from = Self::get_schema_version(env.clone());
+        if from > SCHEMA_VERSION {
+            return Err(Error::UnsupportedSchemaVersion);
+        }
+        // Each layout change adds a step here, applied in order:
+        // `if from < 2 { Self::migrate_to_v2(&env); }`
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("migrated"),),
+            (from, SCHEMA_VERSION)
+        );
+
+        Ok(())
+    }
+
+    /// Returns the version of the layout the contract's storage is in.
+    pub fn get_schema_version(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::SchemaVersion).unwrap_or(1)
     }
 }
+
+#[cfg(test)]
+mod test;